@@ -5,13 +5,13 @@ import {
 	useQueryClient,
 } from "@tanstack/react-query";
 
-import type { TActivity } from "./types";
+import type { TActivity, TPage } from "./types";
 import { handleFetchResponse } from "./util";
 
 export function useActivity(sourceId?: number, demo?: boolean) {
 	return useQuery({
 		queryKey: ["activity", sourceId, demo ?? false],
-		queryFn: (): Promise<TActivity[]> =>
+		queryFn: (): Promise<TPage<TActivity>> =>
 			fetch(
 				"/api/activity" +
 					(sourceId ? `/${sourceId}` : "") +
@@ -27,24 +27,18 @@ export function useInfiniteActivity(
 ) {
 	return useInfiniteQuery({
 		queryKey: ["activity", sourceId, count, demo ?? false],
-		queryFn: ({ pageParam }): Promise<TActivity[]> => {
+		queryFn: ({ pageParam }): Promise<TPage<TActivity>> => {
 			const searchParams = new URLSearchParams();
 			searchParams.append("count", count.toString());
-			searchParams.append("skip", pageParam.toString());
+			if (pageParam) searchParams.append("cursor", pageParam);
 			if (demo) searchParams.append("demo", "true");
 
 			return fetch(
 				`/api/activity${sourceId ? `/${sourceId}` : ""}?${searchParams.toString()}`,
 			).then(handleFetchResponse("Error fetching activities"));
 		},
-		initialPageParam: 0,
-		getNextPageParam: (lastPage, _allPages, lastPageParam) => {
-			if (lastPage.length < count) {
-				return undefined;
-			} else {
-				return lastPageParam + count;
-			}
-		},
+		initialPageParam: null as string | null,
+		getNextPageParam: (lastPage) => lastPage.next_cursor ?? undefined,
 	});
 }
 
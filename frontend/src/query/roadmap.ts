@@ -6,6 +6,7 @@ import {
 } from "@tanstack/react-query";
 
 import type {
+	TPage,
 	TRoadmapActivity,
 	TRoadmapChange,
 	TRTab,
@@ -16,7 +17,7 @@ import { handleFetchResponse } from "./util";
 export function useRoadmapActivity(demo?: boolean) {
 	return useQuery({
 		queryKey: ["roadmap_activity", demo ?? false],
-		queryFn: (): Promise<TRoadmapActivity[]> =>
+		queryFn: (): Promise<TPage<TRoadmapActivity>> =>
 			fetch(`/api/roadmap_activity${demo ? "?demo=true" : ""}`).then(
 				handleFetchResponse("Error fetching roadmap activity"),
 			),
@@ -26,24 +27,18 @@ export function useRoadmapActivity(demo?: boolean) {
 export function useInfiniteRoadmapActivity(demo?: boolean, count: number = 35) {
 	return useInfiniteQuery({
 		queryKey: ["roadmap_activity", count, demo ?? false],
-		queryFn: ({ pageParam }): Promise<TRoadmapActivity[]> => {
+		queryFn: ({ pageParam }): Promise<TPage<TRoadmapActivity>> => {
 			const searchParams = new URLSearchParams();
 			searchParams.append("count", count.toString());
-			searchParams.append("skip", pageParam.toString());
+			if (pageParam) searchParams.append("cursor", pageParam);
 			if (demo) searchParams.append("demo", "true");
 
 			return fetch(
 				`/api/roadmap_activity?${searchParams.toString()}`,
 			).then(handleFetchResponse("Error fetching roadmap activity"));
 		},
-		initialPageParam: 0,
-		getNextPageParam: (lastPage, _allPages, lastPageParam) => {
-			if (lastPage.length < count) {
-				return undefined;
-			} else {
-				return lastPageParam + count;
-			}
-		},
+		initialPageParam: null as string | null,
+		getNextPageParam: (lastPage) => lastPage.next_cursor ?? undefined,
 	});
 }
 
@@ -12,6 +12,12 @@ export type TActivity = {
 	timestamp: string;
 };
 
+export type TPage<T> = {
+	items: T[];
+	next_cursor: string | null;
+	total: number;
+};
+
 export type TRoadmapActivity = {
 	id: number;
 	timestamp: string;
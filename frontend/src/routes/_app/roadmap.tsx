@@ -220,7 +220,7 @@ function ChangeList() {
 
 	if (
 		roadmapActivity.pages.length === 0 ||
-		roadmapActivity.pages[0].length === 0
+		roadmapActivity.pages[0].items.length === 0
 	) {
 		return (
 			<div className="flex h-full items-center justify-center rounded-lg bg-white/20 p-4">
@@ -244,7 +244,7 @@ function ChangeList() {
 			<div className="flex max-h-full flex-col gap-2 overflow-y-auto">
 				{roadmapActivity.pages.map((page, i) => (
 					<Fragment key={i}>
-						{page
+						{page.items
 							.filter(
 								(activity) =>
 									(activity.change_count &&
@@ -412,7 +412,7 @@ function ActivityList() {
 		);
 	}
 
-	if (activity.pages.length === 0 || activity.pages[0].length === 0) {
+	if (activity.pages.length === 0 || activity.pages[0].items.length === 0) {
 		return (
 			<div className="flex h-full items-center justify-center rounded-lg bg-white/20 p-4">
 				<p>No activity</p>
@@ -425,7 +425,7 @@ function ActivityList() {
 			<div className="flex max-h-full max-w-full flex-col gap-4 overflow-y-auto">
 				{activity.pages.map((page, i) => (
 					<Fragment key={i}>
-						{page.map((post) => (
+						{page.items.map((post) => (
 							<div
 								key={post.id}
 								className="flex max-w-full items-center justify-between gap-4 not-last:border-b-2 not-last:border-white/20 not-last:pb-4"
@@ -27,6 +27,7 @@ function Index() {
 		refetchOnReconnect: false,
 	});
 
+	const [username, setUsername] = useState("");
 	const [password, setPassword] = useState("");
 	const [loading, setLoading] = useState(false);
 	const [error, setError] = useState("");
@@ -40,7 +41,10 @@ function Index() {
 				const res = await fetch("/api/login", {
 					method: "POST",
 					headers: { "Content-Type": "application/json" },
-					body: JSON.stringify({ password }),
+					body: JSON.stringify({
+						username: username || undefined,
+						password,
+					}),
 				});
 				if (res.ok) {
 					queryClient.invalidateQueries({ queryKey: ["loggedin"] });
@@ -56,7 +60,7 @@ function Index() {
 
 			setLoading(false);
 		},
-		[navigate, password, queryClient, redirect],
+		[navigate, password, queryClient, redirect, username],
 	);
 
 	if (loginQuery.isSuccess && loginQuery.data) {
@@ -67,7 +71,20 @@ function Index() {
 		<div className="mx-auto flex h-full min-h-full w-full max-w-80 flex-col justify-center gap-4">
 			<h1 className="mb-6 text-center text-6xl">GREG</h1>
 			<div className="flex flex-col gap-2">
-				<form onSubmit={submit}>
+				<form onSubmit={submit} className="flex flex-col gap-2">
+					<label className="sr-only" htmlFor="username">
+						Username
+					</label>
+					<input
+						value={username}
+						disabled={loading}
+						onChange={(e) => setUsername(e.target.value)}
+						className="block w-full rounded-full bg-white/20 px-4 py-3 text-white outline-none focus-visible:border-2 focus-visible:border-white focus-visible:px-3.5 focus-visible:py-2.5"
+						id="username"
+						type="text"
+						autoComplete="username"
+						placeholder="Username (optional)"
+					/>
 					<label
 						className="sr-only mb-4 block text-2xl"
 						htmlFor="password"
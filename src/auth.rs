@@ -0,0 +1,371 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use actix_web::{
+    FromRequest, HttpRequest,
+    dev::Payload,
+    http::{Method, header::Header},
+    web,
+};
+use actix_web_httpauth::headers::authorization::{Authorization, Bearer};
+use log::{error, info};
+use serde::Deserialize;
+use time::{OffsetDateTime, ext::NumericalDuration};
+use utoipa::IntoParams;
+
+use crate::{
+    config::{AuthConfig, ServerConfig},
+    error::GregError,
+    queries::sessions::is_session_expired,
+    repo::Repo,
+    types::{AppData, DEMO_USER_ID, LOGGED_IN_COOKIE, TokenScope},
+};
+
+/// The user making a request, from either the session cookie or an
+/// `Authorization: Bearer` API token
+pub struct AuthUser {
+    pub id: u32,
+    /// For tokens this also needs the admin scope
+    pub is_admin: bool,
+    /// Set when logged in with the session cookie rather than a token
+    pub session_id: Option<u32>,
+}
+
+/// A logged in admin, or a token with the admin scope
+pub struct AdminUser(pub AuthUser);
+
+/// A user logged in with the session cookie. For things API tokens shouldn't
+/// be able to do, like managing tokens
+pub struct SessionUser(pub AuthUser);
+
+/// Which database read only routes read from and whose data to show. With
+/// `?demo=true` anyone can read the demo database, otherwise this needs a
+/// logged in user.
+pub struct Viewer {
+    pub db: Arc<dyn Repo>,
+    pub user_id: u32,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DemoQuery {
+    /// Read the demo database instead, which needs no login
+    #[serde(default)]
+    pub demo: bool,
+}
+
+type ExtractFuture<T> = Pin<Box<dyn Future<Output = Result<T, GregError>>>>;
+
+impl FromRequest for AuthUser {
+    type Error = GregError;
+    type Future = ExtractFuture<Self>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let req = req.clone();
+
+        Box::pin(async move {
+            let Some(data) = req.app_data::<AppData>() else {
+                error!("[Auth] App data is missing");
+                return Err(GregError::Unauthorized);
+            };
+            let db = data.connect(false).await?;
+
+            if let Ok(auth) = Authorization::<Bearer>::parse(&req) {
+                let (user, scope) = get_token_user(db.as_ref(), auth.as_ref().token())
+                    .await
+                    .ok_or(GregError::Unauthorized)?;
+
+                if scope == TokenScope::Read && !matches!(*req.method(), Method::GET | Method::HEAD)
+                {
+                    info!("[Auth] Read only token used for {}", req.method());
+                    return Err(GregError::ReadOnly);
+                }
+
+                return Ok(user);
+            }
+
+            get_logged_in_user(&req, db.as_ref(), &data.config.auth)
+                .await
+                .ok_or(GregError::Unauthorized)
+        })
+    }
+}
+
+impl FromRequest for AdminUser {
+    type Error = GregError;
+    type Future = ExtractFuture<Self>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let user = AuthUser::from_request(req, payload);
+
+        Box::pin(async move {
+            let user = user.await?;
+            if !user.is_admin {
+                info!("[Auth] User {} is not an admin", user.id);
+                return Err(GregError::Forbidden("Only admins can do this"));
+            }
+
+            Ok(AdminUser(user))
+        })
+    }
+}
+
+impl FromRequest for SessionUser {
+    type Error = GregError;
+    type Future = ExtractFuture<Self>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let user = AuthUser::from_request(req, payload);
+
+        Box::pin(async move {
+            let user = user.await?;
+            if user.session_id.is_none() {
+                info!("[Auth] API token used where a login is needed");
+                return Err(GregError::Forbidden(
+                    "API tokens can't do this, log in instead",
+                ));
+            }
+
+            Ok(SessionUser(user))
+        })
+    }
+}
+
+impl FromRequest for Viewer {
+    type Error = GregError;
+    type Future = ExtractFuture<Self>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let demo =
+            web::Query::<DemoQuery>::from_query(req.query_string()).is_ok_and(|query| query.demo);
+        let data = req.app_data::<AppData>().cloned();
+        let user = (!demo).then(|| AuthUser::from_request(req, payload));
+
+        Box::pin(async move {
+            let Some(data) = data else {
+                error!("[Auth] App data is missing");
+                return Err(GregError::Unauthorized);
+            };
+
+            match user {
+                None => Ok(Viewer {
+                    db: data.connect(true).await?,
+                    user_id: DEMO_USER_ID,
+                }),
+                Some(user) => Ok(Viewer {
+                    user_id: user.await?.id,
+                    db: data.connect(false).await?,
+                }),
+            }
+        })
+    }
+}
+
+async fn get_token_user(db: &dyn Repo, token: &str) -> Option<(AuthUser, TokenScope)> {
+    match db.get_token_owner(token).await {
+        Ok(Some((user_id, user_is_admin, scope))) => Some((
+            AuthUser {
+                id: user_id,
+                is_admin: user_is_admin && scope == TokenScope::Admin,
+                session_id: None,
+            },
+            scope,
+        )),
+        Ok(None) => {
+            info!("[Auth] Token not in db");
+            None
+        }
+        Err(err) => {
+            info!("[Auth] Searching token in db failed with error: {err}");
+            None
+        }
+    }
+}
+
+/// Returns the user the login cookie belongs to, if it's still valid. Using a
+/// session pushes its expiry back.
+async fn get_logged_in_user(
+    req: &HttpRequest,
+    db: &dyn Repo,
+    auth: &AuthConfig,
+) -> Option<AuthUser> {
+    let Some(cookie) = req.cookie(LOGGED_IN_COOKIE) else {
+        info!("[LoggedInCheck] No Loggged in cookie set");
+        return None;
+    };
+
+    let owner = match db.get_session_owner(cookie.value()).await {
+        Ok(Some(owner)) => owner,
+        Ok(None) => {
+            info!("[LoggedInCheck] Key not in db");
+            return None;
+        }
+        Err(err) => {
+            info!("[LoggedInCheck] Searching key in db failed with error: {err}");
+            return None;
+        }
+    };
+
+    if is_session_expired(owner.last_seen, auth.session_lifetime_mins) {
+        info!("[LoggedInCheck] Session expired");
+        return None;
+    }
+
+    // Once a minute is plenty to keep the session alive
+    if OffsetDateTime::now_utc() - owner.last_seen > 1.minutes()
+        && let Err(err) = db.touch_session(owner.session_id).await
+    {
+        error!("[LoggedInCheck] Updating last seen failed with error: {err}");
+    }
+
+    Some(AuthUser {
+        id: owner.user_id,
+        is_admin: owner.is_admin,
+        session_id: Some(owner.session_id),
+    })
+}
+
+/// Slows down and then locks out repeated failed logins, per ip and username
+/// and overall
+pub struct LoginLimiter {
+    state: Mutex<LimiterState>,
+    max_failed: u32,
+    lockout: Duration,
+    base_delay: Duration,
+    max_global_failed: u32,
+}
+
+#[derive(Default)]
+struct LimiterState {
+    /// Keyed by ip and username, so logging in to one account doesn't clear
+    /// the failures against another
+    attempts: HashMap<(String, String), Attempts>,
+    /// Failures from every ip in the last minute, with the id of the attempt
+    recent_failures: VecDeque<(Instant, u64)>,
+    next_id: u64,
+}
+
+struct Attempts {
+    failures: u32,
+    next_allowed: Instant,
+}
+
+/// A login let through by [`LoginLimiter::check`], counted as failed until
+/// it's passed to [`LoginLimiter::record_success`]
+pub struct LoginAttempt {
+    key: (String, String),
+    id: u64,
+}
+
+/// Why a login was refused before the password was checked
+pub struct LoginBlocked {
+    pub reason: &'static str,
+    pub retry_after: Duration,
+}
+
+impl LoginLimiter {
+    pub fn new(config: &AuthConfig) -> Self {
+        LoginLimiter {
+            state: Mutex::default(),
+            max_failed: config.max_failed_logins.max(1),
+            lockout: Duration::from_secs(config.lockout_mins * 60),
+            base_delay: Duration::from_millis(config.login_delay_ms),
+            max_global_failed: config.max_global_failed_logins.max(1),
+        }
+    }
+
+    /// Refuses the login if the ip and username or everyone is over the
+    /// limit. Otherwise the attempt is counted as a failure straight away, so
+    /// logins sent at the same time can't all get past, and
+    /// [`Self::record_success`] takes it back.
+    pub fn check(&self, ip: &str, username: &str) -> Result<LoginAttempt, LoginBlocked> {
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap();
+
+        while state
+            .recent_failures
+            .front()
+            .is_some_and(|(failed, _)| now - *failed > Duration::from_secs(60))
+        {
+            state.recent_failures.pop_front();
+        }
+        if state.recent_failures.len() >= self.max_global_failed as usize {
+            let (oldest, _) = *state.recent_failures.front().unwrap();
+            return Err(LoginBlocked {
+                reason: "global_limit",
+                retry_after: Duration::from_secs(60).saturating_sub(now - oldest),
+            });
+        }
+
+        let key = (ip.to_owned(), username.to_owned());
+        if let Some(attempts) = state.attempts.get(&key)
+            && attempts.next_allowed > now
+        {
+            return Err(LoginBlocked {
+                reason: if attempts.failures >= self.max_failed {
+                    "locked_out"
+                } else {
+                    "too_soon"
+                },
+                retry_after: attempts.next_allowed - now,
+            });
+        }
+
+        Ok(self.record_failure(&mut state, key, now))
+    }
+
+    fn record_failure(
+        &self,
+        state: &mut LimiterState,
+        key: (String, String),
+        now: Instant,
+    ) -> LoginAttempt {
+        let id = state.next_id;
+        state.next_id += 1;
+        state.recent_failures.push_back((now, id));
+        // Forget logins that are free to try again and have been for a while
+        state.attempts.retain(|_, attempts| {
+            now.saturating_duration_since(attempts.next_allowed) < self.lockout
+        });
+
+        let attempts = state.attempts.entry(key.clone()).or_insert(Attempts {
+            failures: 0,
+            next_allowed: now,
+        });
+        attempts.failures += 1;
+
+        let wait = if attempts.failures >= self.max_failed {
+            self.lockout
+        } else {
+            self.base_delay * 2u32.saturating_pow(attempts.failures - 1)
+        };
+        attempts.next_allowed = now + wait.min(self.lockout);
+
+        LoginAttempt { key, id }
+    }
+
+    /// Takes back the failure [`Self::check`] counted for the attempt and
+    /// clears the earlier ones for its ip and username
+    pub fn record_success(&self, attempt: LoginAttempt) {
+        let mut state = self.state.lock().unwrap();
+        state.attempts.remove(&attempt.key);
+        state.recent_failures.retain(|(_, id)| *id != attempt.id);
+    }
+}
+
+/// The ip to rate limit logins by
+pub fn client_ip(req: &HttpRequest, server: &ServerConfig) -> String {
+    let info = req.connection_info();
+    let ip = if server.behind_proxy {
+        info.realip_remote_addr()
+    } else {
+        info.peer_addr()
+    };
+
+    ip.unwrap_or("unknown").to_owned()
+}
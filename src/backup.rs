@@ -0,0 +1,208 @@
+use std::collections::{BTreeMap, HashSet};
+
+use libsql::{Connection, Value, params};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Number};
+use time::OffsetDateTime;
+use utoipa::ToSchema;
+
+use crate::{
+    db::{
+        ACTIVITIES_T, API_TOKENS_T, LOGINS_T, R_ACTIVITIES_T, R_CARD_ASSIGNS_T, R_CARDS_T,
+        R_CHANGES_T, R_TAB_ASSIGNS_T, R_TABS_T, R_WATCHED_TABS_T, SOURCE_EVENTS_T, SOURCES_T,
+        USERS_T, migrations,
+    },
+    types::StringError,
+};
+
+/// Bumped when the archive layout changes, not when the schema does
+pub const BACKUP_FORMAT: u32 = 1;
+
+/// Every table a backup holds, parents before the tables pointing at them.
+/// Sessions, login attempts and the search indexes are left out, the indexes
+/// are rebuilt by their triggers on restore.
+pub const BACKUP_TABLES: &[&str] = &[
+    USERS_T,
+    API_TOKENS_T,
+    SOURCES_T,
+    SOURCE_EVENTS_T,
+    ACTIVITIES_T,
+    R_ACTIVITIES_T,
+    R_TABS_T,
+    R_CARDS_T,
+    R_TAB_ASSIGNS_T,
+    R_CARD_ASSIGNS_T,
+    R_CHANGES_T,
+    R_WATCHED_TABS_T,
+];
+
+pub type Row = Map<String, serde_json::Value>;
+
+/// A copy of every row in [`BACKUP_TABLES`]. Rows keep their ids so
+/// references between tables survive a restore.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct Backup {
+    pub format: u32,
+    /// Latest migration applied to the database the backup was taken from
+    pub schema_version: u32,
+    #[serde(with = "time::serde::rfc3339")]
+    #[schema(value_type = String)]
+    pub created: OffsetDateTime,
+    /// Rows of every table by table name
+    #[schema(value_type = Object)]
+    pub tables: BTreeMap<String, Vec<Row>>,
+}
+
+impl Backup {
+    pub fn row_count(&self) -> usize {
+        self.tables.values().map(Vec::len).sum()
+    }
+}
+
+fn to_json(value: Value) -> anyhow::Result<serde_json::Value> {
+    Ok(match value {
+        Value::Null => serde_json::Value::Null,
+        Value::Integer(n) => n.into(),
+        Value::Real(n) => Number::from_f64(n)
+            .map(serde_json::Value::Number)
+            .ok_or_else(|| StringError(format!("Can't back up the number {n}")))?,
+        Value::Text(s) => s.into(),
+        Value::Blob(_) => return Err(StringError("Can't back up blobs".into()).into()),
+    })
+}
+
+fn from_json(value: &serde_json::Value) -> anyhow::Result<Value> {
+    Ok(match value {
+        serde_json::Value::Null => Value::Null,
+        serde_json::Value::Bool(b) => Value::Integer((*b).into()),
+        serde_json::Value::Number(n) => match n.as_i64() {
+            Some(n) => Value::Integer(n),
+            None => Value::Real(n.as_f64().unwrap_or_default()),
+        },
+        serde_json::Value::String(s) => Value::Text(s.clone()),
+        _ => return Err(StringError(format!("Can't restore the value {value}")).into()),
+    })
+}
+
+async fn schema_version(db: &Connection) -> anyhow::Result<u32> {
+    Ok(migrations::applied_migrations(db.clone())
+        .await?
+        .last()
+        .map_or(0, |migration| migration.version))
+}
+
+async fn table_columns(db: &Connection, table: &str) -> anyhow::Result<HashSet<String>> {
+    let mut rows = db
+        .query(&format!("PRAGMA table_info(`{table}`)"), params!())
+        .await?;
+
+    let mut columns = HashSet::new();
+    while let Some(row) = rows.next().await? {
+        columns.insert(row.get::<String>(1)?);
+    }
+    Ok(columns)
+}
+
+/// Reads every backed up table. Runs in a transaction so the copy is
+/// consistent even while checks are writing.
+pub async fn export(db: Connection) -> anyhow::Result<Backup> {
+    let schema_version = schema_version(&db).await?;
+    let tx = db.transaction().await?;
+
+    let mut tables = BTreeMap::new();
+    for table in BACKUP_TABLES {
+        let mut result = tx
+            .query(&format!("SELECT * FROM {table} ORDER BY rowid"), params!())
+            .await?;
+        let names = (0..result.column_count())
+            .map(|i| result.column_name(i).unwrap_or_default().to_owned())
+            .collect::<Vec<_>>();
+
+        let mut rows = Vec::new();
+        while let Some(row) = result.next().await? {
+            let mut values = Row::new();
+            for (i, name) in names.iter().enumerate() {
+                values.insert(name.clone(), to_json(row.get_value(i as i32)?)?);
+            }
+            rows.push(values);
+        }
+        tables.insert((*table).to_owned(), rows);
+    }
+
+    tx.commit().await?;
+    Ok(Backup {
+        format: BACKUP_FORMAT,
+        schema_version,
+        created: OffsetDateTime::now_utc(),
+        tables,
+    })
+}
+
+/// Replaces the backed up tables with the backup's rows, all or nothing.
+/// The database has to be migrated to the same version the backup was taken
+/// at. Every session is ended since the users they belong to may be gone.
+pub async fn restore(db: Connection, backup: &Backup) -> anyhow::Result<()> {
+    if backup.format != BACKUP_FORMAT {
+        return Err(StringError(format!(
+            "Backup is format {}, this build reads format {BACKUP_FORMAT}",
+            backup.format
+        ))
+        .into());
+    }
+    let version = schema_version(&db).await?;
+    if backup.schema_version != version {
+        return Err(StringError(format!(
+            "Backup is from schema version {} but the database is at {version}. Restore it with a build at the same version",
+            backup.schema_version
+        ))
+        .into());
+    }
+    if let Some(table) = backup
+        .tables
+        .keys()
+        .find(|table| !BACKUP_TABLES.contains(&table.as_str()))
+    {
+        return Err(StringError(format!("Backup has unknown table {table}")).into());
+    }
+
+    let tx = db.transaction().await?;
+    tx.execute(&format!("DELETE FROM {LOGINS_T}"), params!())
+        .await?;
+    for table in BACKUP_TABLES.iter().rev() {
+        tx.execute(&format!("DELETE FROM {table}"), params!())
+            .await?;
+    }
+
+    for table in BACKUP_TABLES {
+        let Some(rows) = backup.tables.get(*table) else {
+            continue;
+        };
+        let columns = table_columns(&tx, table).await?;
+
+        for row in rows {
+            if let Some(column) = row.keys().find(|column| !columns.contains(*column)) {
+                return Err(StringError(format!("{table} has no column {column}")).into());
+            }
+
+            let names = row
+                .keys()
+                .map(|column| format!("`{column}`"))
+                .collect::<Vec<_>>()
+                .join(", ");
+            let placeholders = (1..=row.len())
+                .map(|i| format!("?{i}"))
+                .collect::<Vec<_>>()
+                .join(", ");
+            let values = row.values().map(from_json).collect::<Result<Vec<_>, _>>()?;
+
+            tx.execute(
+                &format!("INSERT INTO {table} ({names}) VALUES ({placeholders})"),
+                values,
+            )
+            .await?;
+        }
+    }
+
+    tx.commit().await?;
+    Ok(())
+}
@@ -0,0 +1,440 @@
+use std::{collections::HashSet, fs, io, path::PathBuf};
+
+use clap::{Parser, Subcommand};
+use log::{error, info};
+use url::Url;
+
+use crate::{
+    backup::{self, Backup},
+    db::{self, VERSION_T, migrations},
+    opml,
+    repo::Backend,
+    tasks::{
+        check_roadmap::check_roadmap,
+        check_sources::{check_sources, preview_source},
+        prune_data,
+    },
+    types::{AppData, StringError},
+};
+
+#[derive(Parser)]
+#[command(version, about = "Self hosted email notifier for RSS and Atom feeds")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Run the web server and scheduler (the default)
+    Serve {
+        /// Address to bind to, overrides server.address
+        #[arg(long)]
+        address: Option<String>,
+        /// Port to bind to, overrides server.port
+        #[arg(long)]
+        port: Option<u16>,
+    },
+    /// Migrate the app and demo databases then exit
+    Migrate {
+        #[command(subcommand)]
+        command: Option<MigrateCommand>,
+    },
+    /// Check every source once
+    CheckSources,
+    /// Check the roadmap once
+    CheckRoadmap,
+    /// Delete data past the retention settings once
+    Prune,
+    /// Manage a user's sources
+    Source {
+        /// Username the sources belong to, defaults to auth.admin_username
+        #[arg(long, global = true)]
+        user: Option<String>,
+        #[command(subcommand)]
+        command: SourceCommand,
+    },
+    /// Add every feed in an OPML file as a source
+    ImportOpml {
+        file: PathBuf,
+        /// Username to add the sources for, defaults to auth.admin_username
+        #[arg(long)]
+        user: Option<String>,
+    },
+    /// Write a user's sources as OPML to a file or stdout
+    ExportOpml {
+        file: Option<PathBuf>,
+        /// Username to export the sources of, defaults to auth.admin_username
+        #[arg(long)]
+        user: Option<String>,
+    },
+    /// Manage users
+    #[command(subcommand)]
+    User(UserCommand),
+    /// Write every source, activity, roadmap check and user to a JSON
+    /// archive file or stdout. Only works on libsql
+    Backup { file: Option<PathBuf> },
+    /// Replace the app database's data with a backup's. Only works on libsql
+    ///
+    /// Restoring is only possible from the CLI, the API can only download
+    /// backups.
+    Restore { file: PathBuf },
+}
+
+#[derive(Subcommand)]
+pub enum MigrateCommand {
+    /// Apply every pending migration (the default)
+    Up,
+    /// Roll back migrations, only the latest unless `--to` is given
+    Down {
+        /// Version to roll back to, 0 rolls back everything
+        #[arg(long)]
+        to: Option<u32>,
+        /// Roll back the demo database instead of the app one
+        #[arg(long)]
+        demo: bool,
+    },
+    /// List the migrations and whether they've been applied
+    Status {
+        /// Show the demo database instead of the app one
+        #[arg(long)]
+        demo: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum SourceCommand {
+    /// Add a source after making sure it can be fetched and parsed
+    Add {
+        url: String,
+        /// Add without fetching the feed first
+        #[arg(long)]
+        no_check: bool,
+    },
+    /// List all sources
+    List,
+    /// Remove a source
+    Remove { id: u32 },
+    /// Enable a source, resetting its failure count
+    Enable {
+        id: u32,
+        /// Disable the source instead
+        #[arg(long)]
+        disable: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum UserCommand {
+    /// Add a user. The password is read from stdin unless given
+    Add {
+        username: String,
+        #[arg(long)]
+        password: Option<String>,
+        #[arg(long)]
+        admin: bool,
+    },
+    /// List all users
+    List,
+    /// Change a user's password. The password is read from stdin unless given
+    Passwd {
+        username: String,
+        #[arg(long)]
+        password: Option<String>,
+    },
+}
+
+/// Runs every command other than `serve`. The databases are expected to be
+/// migrated already.
+pub async fn run(command: Command, data: AppData) -> anyhow::Result<()> {
+    match command {
+        Command::Serve { .. } | Command::Migrate { .. } => {}
+        Command::CheckSources => check_sources(&data).await,
+        Command::CheckRoadmap => check_roadmap(&data).await,
+        Command::Prune => {
+            let db = data.app.connect().await?;
+            let report = prune_data::prune(db.as_ref(), &data.config.retention).await?;
+            println!("Deleted {report}");
+        }
+        Command::Source { user, command } => {
+            let user_id = resolve_user(user, &data).await?;
+            run_source(command, user_id, &data).await?
+        }
+        Command::ImportOpml { file, user } => {
+            let user_id = resolve_user(user, &data).await?;
+            import_opml(&file, user_id, &data).await?
+        }
+        Command::ExportOpml { file, user } => {
+            let user_id = resolve_user(user, &data).await?;
+            let all_sources = data.app.connect().await?.get_sources(user_id).await?;
+            let content = opml::export_sources(&all_sources);
+
+            match file {
+                Some(file) => {
+                    fs::write(&file, content)?;
+                    info!(
+                        "[Export OPML] Wrote {} sources to {}",
+                        all_sources.len(),
+                        file.display()
+                    );
+                }
+                None => print!("{content}"),
+            }
+        }
+        Command::User(command) => run_user(command, &data).await?,
+        Command::Backup { file } => {
+            let backup = backup::export(db::connect(libsql_database(&data)?).await?).await?;
+            let content = serde_json::to_string(&backup)?;
+
+            match file {
+                Some(file) => {
+                    fs::write(&file, content)?;
+                    info!(
+                        "[Backup] Wrote {} rows to {}",
+                        backup.row_count(),
+                        file.display()
+                    );
+                }
+                None => println!("{content}"),
+            }
+        }
+        Command::Restore { file } => {
+            let backup: Backup = serde_json::from_str(&fs::read_to_string(&file)?)?;
+            backup::restore(db::connect(libsql_database(&data)?).await?, &backup).await?;
+            println!(
+                "Restored {} rows from {}",
+                backup.row_count(),
+                file.display()
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Backups copy libsql tables as they are, so they don't work on PostgreSQL
+fn libsql_database(data: &AppData) -> anyhow::Result<&libsql::Database> {
+    data.app
+        .libsql()
+        .ok_or_else(|| StringError("Backups only work on libsql databases".into()).into())
+}
+
+async fn resolve_user(username: Option<String>, data: &AppData) -> anyhow::Result<u32> {
+    let username = username.unwrap_or_else(|| data.config.auth.admin_username.clone());
+    match data
+        .app
+        .connect()
+        .await?
+        .get_user_by_username(&username)
+        .await?
+    {
+        Some(user) => Ok(user.id),
+        None => anyhow::bail!("User {username} does not exist"),
+    }
+}
+
+fn read_password(password: Option<String>) -> anyhow::Result<String> {
+    let password = match password {
+        Some(password) => password,
+        None => {
+            let mut line = String::new();
+            io::stdin().read_line(&mut line)?;
+            line.trim_end_matches(['\r', '\n']).to_owned()
+        }
+    };
+
+    if password.is_empty() {
+        anyhow::bail!("Password must not be empty");
+    }
+    Ok(password)
+}
+
+async fn run_user(command: UserCommand, data: &AppData) -> anyhow::Result<()> {
+    let db = data.app.connect().await?;
+
+    match command {
+        UserCommand::Add {
+            username,
+            password,
+            admin,
+        } => {
+            let password = read_password(password)?;
+            db.add_user(&username, &password, admin).await?;
+            println!("Added {username}");
+        }
+        UserCommand::List => {
+            for user in db.get_users().await? {
+                println!(
+                    "{}\t{}\t{}",
+                    user.id,
+                    if user.is_admin { "admin" } else { "user" },
+                    user.username
+                );
+            }
+        }
+        UserCommand::Passwd { username, password } => {
+            let password = read_password(password)?;
+            if db.set_password(&username, &password).await? == 0 {
+                anyhow::bail!("User {username} does not exist");
+            }
+            println!("Changed password for {username}");
+        }
+    }
+
+    Ok(())
+}
+
+async fn run_source(command: SourceCommand, user_id: u32, data: &AppData) -> anyhow::Result<()> {
+    let db = data.app.connect().await?;
+
+    match command {
+        SourceCommand::Add { url, no_check } => {
+            Url::parse(&url)?;
+            if !no_check {
+                let preview = preview_source(data, &url).await.map_err(StringError)?;
+                info!(
+                    "[Add Source] {} has {} entries",
+                    preview.channel_title.as_deref().unwrap_or("Untitled feed"),
+                    preview.entries.len()
+                );
+            }
+
+            db.add_source(user_id, &url).await?;
+            println!("Added {url}");
+        }
+        SourceCommand::List => {
+            for source in db.get_sources(user_id).await? {
+                println!(
+                    "{}\t{}\tfailed {}\t{}",
+                    source.id,
+                    if source.enabled {
+                        "enabled"
+                    } else {
+                        "disabled"
+                    },
+                    source.failed_count,
+                    source.url
+                );
+            }
+        }
+        SourceCommand::Remove { id } => {
+            if db.delete_source(user_id, id).await? == 0 {
+                anyhow::bail!("Source {id} does not exist");
+            }
+            println!("Removed source {id}");
+        }
+        SourceCommand::Enable { id, disable } => {
+            let enabled = !disable;
+            if db.set_source_enabled(user_id, id, enabled).await? == 0 {
+                anyhow::bail!("Source {id} does not exist");
+            }
+            println!(
+                "{} source {id}",
+                if enabled { "Enabled" } else { "Disabled" }
+            );
+        }
+    }
+
+    Ok(())
+}
+
+async fn import_opml(file: &PathBuf, user_id: u32, data: &AppData) -> anyhow::Result<()> {
+    let content = fs::read_to_string(file)?;
+    let urls = opml::parse_feed_urls(&content)?;
+
+    let db = data.app.connect().await?;
+    let existing = db
+        .get_sources(user_id)
+        .await?
+        .into_iter()
+        .map(|s| s.url)
+        .collect::<HashSet<_>>();
+
+    let mut added = 0;
+    let mut skipped = 0;
+    for url in urls {
+        if existing.contains(&url) {
+            info!("[Import OPML] Skipping existing source {url}");
+            skipped += 1;
+            continue;
+        }
+        if let Err(err) = Url::parse(&url) {
+            error!("[Import OPML] Skipping invalid url {url}. Err: {err}");
+            skipped += 1;
+            continue;
+        }
+
+        match db.add_source(user_id, &url).await {
+            Ok(_) => added += 1,
+            Err(err) => {
+                error!("[Import OPML] Failed to add {url}. Err: {err}");
+                skipped += 1;
+            }
+        }
+    }
+
+    println!("Imported {added} sources, skipped {skipped}");
+    Ok(())
+}
+
+/// Migrates a database, logging which one
+pub async fn migrate(backend: &dyn Backend, name: &str) -> anyhow::Result<()> {
+    info!("Migrating {name} Database");
+    backend.migrate().await?;
+
+    info!("Migrated Database");
+    Ok(())
+}
+
+/// Runs `migrate down` and `migrate status`. These run before the databases
+/// are migrated up, so they work on databases this build would refuse.
+pub async fn run_migrate(
+    command: MigrateCommand,
+    app: &dyn Backend,
+    demo: &dyn Backend,
+) -> anyhow::Result<()> {
+    // Rolling back and listing migrations only knows the libsql registry
+    let database = |demo_db: bool| {
+        let backend = if demo_db { demo } else { app };
+        backend
+            .libsql()
+            .ok_or_else(|| StringError("Only migrate up works on PostgreSQL".into()))
+    };
+
+    match command {
+        MigrateCommand::Up => {
+            migrate(app, "app").await?;
+            migrate(demo, "demo").await?;
+        }
+        MigrateCommand::Down { to, demo } => {
+            let conn = db::connect(database(demo)?).await?;
+            let to = migrations::migrate_down(conn, to).await?;
+            println!("Rolled back to version {to}");
+        }
+        MigrateCommand::Status { demo } => {
+            let conn = db::connect(database(demo)?).await?;
+            if let Some(version) = migrations::legacy_version(&conn).await? {
+                println!("Database is at version {version} in {VERSION_T}, migrate up records it");
+            }
+            let applied = migrations::applied_migrations(conn).await?;
+
+            for migration in migrations::MIGRATIONS {
+                match applied.iter().find(|a| a.version == migration.version) {
+                    Some(applied) => println!(
+                        "{}\t{}\tapplied {}",
+                        migration.version, migration.name, applied.applied_at
+                    ),
+                    None => println!("{}\t{}\tpending", migration.version, migration.name),
+                }
+            }
+            for unknown in applied
+                .iter()
+                .filter(|a| a.version > migrations::latest_version())
+            {
+                println!("{}\t{}\tunknown", unknown.version, unknown.name);
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,444 @@
+use std::{env, fs, path::PathBuf, str::FromStr};
+
+use serde::Deserialize;
+
+const DEFAULT_CONFIG_PATH: &str = "greg.toml";
+
+#[derive(thiserror::Error, Debug)]
+pub enum ConfigError {
+    #[error("Couldn't read config file {path}: {source}")]
+    Read {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Couldn't parse config file {path}: {source}")]
+    Parse {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+    #[error("Env var {key} has invalid value {value:?}")]
+    Env { key: String, value: String },
+    #[error("Invalid config:\n\t{}", .0.join("\n\t"))]
+    Invalid(Vec<String>),
+}
+
+/// Greg's configuration. Loaded from a TOML file (`GREG_CONFIG` or `greg.toml`)
+/// with env vars layered on top, see [`Config::load`].
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub database: DatabaseConfig,
+    pub auth: AuthConfig,
+    pub mail: Option<MailConfig>,
+    pub roadmap: RoadmapConfig,
+    pub sources: SourcesConfig,
+    pub http: HttpConfig,
+    pub retention: RetentionConfig,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub address: String,
+    pub port: u16,
+    /// Take the client ip from `X-Forwarded-For`. Only enable this behind a
+    /// proxy that sets it, otherwise clients can pick their own ip
+    pub behind_proxy: bool,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            address: "0.0.0.0".into(),
+            port: 10000,
+            behind_proxy: false,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseConfig {
+    /// Use the local file at `local_path` instead of the remote database
+    pub use_local: bool,
+    pub url: Option<String>,
+    pub auth_key: Option<String>,
+    pub local_path: Option<String>,
+    /// Keep a copy of the remote database at this path, synced every
+    /// `sync_interval_secs` and after writes. Ignored with `use_local`
+    pub replica_path: Option<String>,
+    pub sync_interval_secs: u64,
+    /// Store the app data in PostgreSQL instead, needs the postgres feature.
+    /// The demo database stays at `demo_path`
+    pub postgres_url: Option<String>,
+    pub demo_path: String,
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        DatabaseConfig {
+            use_local: false,
+            url: None,
+            auth_key: None,
+            local_path: None,
+            replica_path: None,
+            sync_interval_secs: 60,
+            postgres_url: None,
+            demo_path: "db/demo.db".into(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    /// Admin created on first run when there are no users yet
+    pub admin_username: String,
+    /// Password for that admin. Only read while the users table is empty
+    pub password: Option<String>,
+    /// Sessions expire after this long without a request
+    pub session_lifetime_mins: i64,
+    /// Failed logins in a row to one username from one ip before they're
+    /// locked out
+    pub max_failed_logins: u32,
+    pub lockout_mins: u64,
+    /// Wait after the first failed login, doubled for each one after
+    pub login_delay_ms: u64,
+    /// Failed logins per minute from all ips before every login is refused
+    pub max_global_failed_logins: u32,
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        AuthConfig {
+            admin_username: "admin".into(),
+            password: None,
+            session_lifetime_mins: 60,
+            max_failed_logins: 5,
+            lockout_mins: 15,
+            login_delay_ms: 500,
+            max_global_failed_logins: 30,
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MailConfig {
+    pub url: String,
+    pub token: String,
+    pub from_email: String,
+    pub from_name: String,
+    pub to_email: String,
+    pub to_name: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RoadmapConfig {
+    /// Page the roadmap json is scraped from
+    pub url: Option<String>,
+    /// Base url of the frontend, used for links in emails
+    pub base_url: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SourcesConfig {
+    /// Failures in a row before a source is disabled
+    pub disable_after: u32,
+    pub max_concurrent_checks: usize,
+    pub max_concurrent_per_host: usize,
+    /// Minimum time between requests to the same host
+    pub host_delay_ms: u64,
+}
+
+impl Default for SourcesConfig {
+    fn default() -> Self {
+        SourcesConfig {
+            disable_after: 10,
+            max_concurrent_checks: 8,
+            max_concurrent_per_host: 2,
+            host_delay_ms: 1000,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HttpConfig {
+    pub connect_timeout_secs: u64,
+    pub read_timeout_secs: u64,
+    pub request_timeout_secs: u64,
+    pub max_redirects: usize,
+    /// Contact url included in the User-Agent
+    pub contact_url: String,
+    /// `http://`, `https://` or `socks5://` proxy for all requests
+    pub proxy_url: Option<String>,
+    /// Paths to extra PEM root certificates
+    pub ca_bundles: Vec<String>,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        HttpConfig {
+            connect_timeout_secs: 10,
+            read_timeout_secs: 30,
+            request_timeout_secs: 60,
+            max_redirects: 5,
+            contact_url: "https://github.com/cirqlar/greg".into(),
+            proxy_url: None,
+            ca_bundles: Vec::new(),
+        }
+    }
+}
+
+/// How long data is kept before the daily prune deletes it. 0 keeps it forever
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetentionConfig {
+    /// Delete activities older than this many days
+    pub activity_days: u32,
+    /// Only keep this many of the newest activities for each source
+    pub activities_per_source: u32,
+    /// Delete roadmap checks that found no changes after this many days
+    pub unchanged_roadmap_days: u32,
+    /// Delete every roadmap check after this many days
+    pub roadmap_days: u32,
+    /// Delete records of failed logins after this many days
+    pub login_attempt_days: u32,
+}
+
+fn env_string(key: &str, field: &mut String) {
+    if let Ok(value) = env::var(key) {
+        *field = value;
+    }
+}
+
+fn env_opt_string(key: &str, field: &mut Option<String>) {
+    if let Ok(value) = env::var(key) {
+        *field = (!value.is_empty()).then_some(value);
+    }
+}
+
+fn env_parse<T: FromStr>(key: &str, field: &mut T) -> Result<(), ConfigError> {
+    if let Ok(value) = env::var(key) {
+        *field = value.parse().map_err(|_| ConfigError::Env {
+            key: key.into(),
+            value,
+        })?;
+    }
+    Ok(())
+}
+
+impl Config {
+    /// Reads the config file if there is one, applies env overrides and validates the result
+    pub fn load() -> Result<Config, ConfigError> {
+        let (path, required) = match env::var("GREG_CONFIG") {
+            Ok(path) => (PathBuf::from(path), true),
+            Err(_) => (PathBuf::from(DEFAULT_CONFIG_PATH), false),
+        };
+
+        let mut config = match fs::read_to_string(&path) {
+            Ok(content) => {
+                toml::from_str(&content).map_err(|source| ConfigError::Parse { path, source })?
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound && !required => {
+                Config::default()
+            }
+            Err(source) => return Err(ConfigError::Read { path, source }),
+        };
+
+        config.apply_env()?;
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    /// Env vars take precedence over the file. These are the names Greg has
+    /// always used so existing `.env` files keep working.
+    fn apply_env(&mut self) -> Result<(), ConfigError> {
+        env_string("HOST", &mut self.server.address);
+        env_parse("PORT", &mut self.server.port)?;
+        env_parse("BEHIND_PROXY", &mut self.server.behind_proxy)?;
+
+        if let Ok(use_local) = env::var("USE_LOCAL") {
+            self.database.use_local = use_local != "false";
+        }
+        env_opt_string("DATABASE_URL", &mut self.database.url);
+        env_opt_string("DATABASE_AUTH_KEY", &mut self.database.auth_key);
+        env_opt_string("LOCAL_DB_URL", &mut self.database.local_path);
+        env_opt_string("REPLICA_DB_URL", &mut self.database.replica_path);
+        env_parse(
+            "DATABASE_SYNC_INTERVAL_SECS",
+            &mut self.database.sync_interval_secs,
+        )?;
+        env_opt_string("POSTGRES_URL", &mut self.database.postgres_url);
+        env_string("DEMO_DB_URL", &mut self.database.demo_path);
+
+        env_string("ADMIN_USERNAME", &mut self.auth.admin_username);
+        env_opt_string("PASSWORD", &mut self.auth.password);
+        env_parse(
+            "SESSION_LIFETIME_MINS",
+            &mut self.auth.session_lifetime_mins,
+        )?;
+        env_parse("MAX_FAILED_LOGINS", &mut self.auth.max_failed_logins)?;
+        env_parse("LOCKOUT_MINS", &mut self.auth.lockout_mins)?;
+        env_parse("LOGIN_DELAY_MS", &mut self.auth.login_delay_ms)?;
+        env_parse(
+            "MAX_GLOBAL_FAILED_LOGINS",
+            &mut self.auth.max_global_failed_logins,
+        )?;
+
+        let mail_keys = [
+            "MAIL_URL",
+            "MAIL_TOKEN",
+            "FROM_EMAIL",
+            "FROM_NAME",
+            "TO_EMAIL",
+            "TO_NAME",
+        ];
+        if mail_keys.iter().any(|k| env::var(k).is_ok()) {
+            let mail = self.mail.get_or_insert_with(MailConfig::default);
+            env_string("MAIL_URL", &mut mail.url);
+            env_string("MAIL_TOKEN", &mut mail.token);
+            env_string("FROM_EMAIL", &mut mail.from_email);
+            env_string("FROM_NAME", &mut mail.from_name);
+            env_string("TO_EMAIL", &mut mail.to_email);
+            env_string("TO_NAME", &mut mail.to_name);
+        }
+
+        env_opt_string("VITE_ROADMAP_URL", &mut self.roadmap.url);
+        env_opt_string("VITE_BASE_URL", &mut self.roadmap.base_url);
+
+        env_parse("SOURCE_DISABLE_AFTER", &mut self.sources.disable_after)?;
+        env_parse(
+            "SOURCE_MAX_CONCURRENT_CHECKS",
+            &mut self.sources.max_concurrent_checks,
+        )?;
+        env_parse(
+            "SOURCE_MAX_CONCURRENT_PER_HOST",
+            &mut self.sources.max_concurrent_per_host,
+        )?;
+        env_parse("SOURCE_HOST_DELAY_MS", &mut self.sources.host_delay_ms)?;
+
+        env_parse("HTTP_CONNECT_TIMEOUT", &mut self.http.connect_timeout_secs)?;
+        env_parse("HTTP_READ_TIMEOUT", &mut self.http.read_timeout_secs)?;
+        env_parse("HTTP_REQUEST_TIMEOUT", &mut self.http.request_timeout_secs)?;
+        env_parse("HTTP_MAX_REDIRECTS", &mut self.http.max_redirects)?;
+        env_string("HTTP_CONTACT_URL", &mut self.http.contact_url);
+        env_opt_string("HTTP_PROXY_URL", &mut self.http.proxy_url);
+        if let Ok(bundles) = env::var("HTTP_CA_BUNDLES") {
+            self.http.ca_bundles = bundles
+                .split(',')
+                .map(str::trim)
+                .filter(|p| !p.is_empty())
+                .map(str::to_owned)
+                .collect();
+        }
+
+        env_parse("RETENTION_ACTIVITY_DAYS", &mut self.retention.activity_days)?;
+        env_parse(
+            "RETENTION_ACTIVITIES_PER_SOURCE",
+            &mut self.retention.activities_per_source,
+        )?;
+        env_parse(
+            "RETENTION_UNCHANGED_ROADMAP_DAYS",
+            &mut self.retention.unchanged_roadmap_days,
+        )?;
+        env_parse("RETENTION_ROADMAP_DAYS", &mut self.retention.roadmap_days)?;
+        env_parse(
+            "RETENTION_LOGIN_ATTEMPT_DAYS",
+            &mut self.retention.login_attempt_days,
+        )?;
+
+        Ok(())
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        let mut problems = Vec::new();
+
+        if self.database.postgres_url.is_some() {
+            if !cfg!(feature = "postgres") {
+                problems.push(
+                    "database.postgres_url (POSTGRES_URL) needs a build with the postgres feature"
+                        .into(),
+                );
+            }
+        } else if self.database.use_local {
+            if self.database.local_path.is_none() {
+                problems.push(
+                    "database.local_path (LOCAL_DB_URL) must be set when using a local database"
+                        .into(),
+                );
+            }
+        } else {
+            if self.database.url.is_none() {
+                problems.push("database.url (DATABASE_URL) must be set".into());
+            }
+            if self.database.auth_key.is_none() {
+                problems.push("database.auth_key (DATABASE_AUTH_KEY) must be set".into());
+            }
+            if self.database.replica_path.is_some() && self.database.sync_interval_secs == 0 {
+                problems.push("database.sync_interval_secs must be at least 1".into());
+            }
+        }
+
+        if self.auth.admin_username.is_empty() {
+            problems.push("auth.admin_username (ADMIN_USERNAME) must not be empty".into());
+        }
+        if self.auth.session_lifetime_mins < 1 {
+            problems.push("auth.session_lifetime_mins must be at least 1".into());
+        }
+        if self.auth.max_failed_logins == 0 {
+            problems.push("auth.max_failed_logins must be at least 1".into());
+        }
+        if self.auth.max_global_failed_logins == 0 {
+            problems.push("auth.max_global_failed_logins must be at least 1".into());
+        }
+
+        if cfg!(feature = "mail") {
+            match &self.mail {
+                None => {
+                    problems.push("mail must be configured when the mail feature is enabled".into())
+                }
+                Some(mail) => {
+                    let fields = [
+                        ("mail.url (MAIL_URL)", &mail.url),
+                        ("mail.token (MAIL_TOKEN)", &mail.token),
+                        ("mail.from_email (FROM_EMAIL)", &mail.from_email),
+                        ("mail.from_name (FROM_NAME)", &mail.from_name),
+                        ("mail.to_email (TO_EMAIL)", &mail.to_email),
+                        ("mail.to_name (TO_NAME)", &mail.to_name),
+                    ];
+                    for (name, value) in fields {
+                        if value.is_empty() {
+                            problems.push(format!("{name} must be set"));
+                        }
+                    }
+                }
+            }
+        }
+
+        if cfg!(feature = "scheduler") && self.roadmap.url.is_none() {
+            problems.push(
+                "roadmap.url (VITE_ROADMAP_URL) must be set when the scheduler feature is enabled"
+                    .into(),
+            );
+        }
+
+        if self.sources.max_concurrent_checks == 0 {
+            problems.push("sources.max_concurrent_checks must be at least 1".into());
+        }
+        if self.sources.max_concurrent_per_host == 0 {
+            problems.push("sources.max_concurrent_per_host must be at least 1".into());
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigError::Invalid(problems))
+        }
+    }
+}
@@ -0,0 +1,1084 @@
+//! Schema migrations. Each one runs in its own transaction and is recorded in
+//! `schema_migrations` with a checksum of its sql, so edits to a migration
+//! that has already run are caught instead of silently skipped. New schema
+//! changes go at the end of [`MIGRATIONS`] with a `down` that undoes them.
+
+use std::pin::Pin;
+
+use libsql::{Connection, params};
+use log::info;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use time::OffsetDateTime;
+
+use crate::{
+    db::{
+        ACTIVITIES_FTS_T, ACTIVITIES_T, API_TOKENS_T, LOGIN_ATTEMPTS_T, LOGINS_T, MIGRATIONS_T,
+        R_ACTIVITIES_T, R_CARD_ASSIGNS_T, R_CARDS_FTS_T, R_CARDS_T, R_CHANGES_T, R_TAB_ASSIGNS_T,
+        R_TABS_T, R_WATCHED_TABS_T, SOURCE_EVENTS_T, SOURCES_T, USERS_T, VERSION_T, sort_key,
+    },
+    types::StringError,
+    utils::hash_card,
+};
+
+/// Rust run after a migration's sql in the same transaction, for changes sql
+/// can't make on its own. It isn't part of the checksum.
+type DataStep =
+    for<'a> fn(&'a Connection) -> Pin<Box<dyn Future<Output = anyhow::Result<()>> + Send + 'a>>;
+
+pub struct Migration {
+    pub version: u32,
+    pub name: &'static str,
+    up: fn() -> Vec<String>,
+    down: fn() -> Vec<String>,
+    data: Option<DataStep>,
+}
+
+impl Migration {
+    fn up_sql(&self) -> String {
+        (self.up)().join(";\n")
+    }
+
+    fn down_sql(&self) -> String {
+        (self.down)().join(";\n")
+    }
+
+    pub fn checksum(&self) -> String {
+        Sha256::digest(self.up_sql().as_bytes())
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect()
+    }
+}
+
+#[derive(Deserialize)]
+pub struct AppliedMigration {
+    pub version: u32,
+    pub name: String,
+    pub checksum: String,
+    #[serde(deserialize_with = "crate::types::deserialize_timestamp")]
+    pub applied_at: OffsetDateTime,
+}
+
+/// Every migration, oldest first. Versions have to keep counting up by one.
+pub static MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "initial",
+        up: v1_up,
+        down: v1_down,
+        data: None,
+    },
+    Migration {
+        version: 2,
+        name: "source_failures",
+        up: v2_up,
+        down: v2_down,
+        data: None,
+    },
+    Migration {
+        version: 3,
+        name: "source_events",
+        up: v3_up,
+        down: v3_down,
+        data: None,
+    },
+    Migration {
+        version: 4,
+        name: "users",
+        up: v4_up,
+        down: v4_down,
+        data: None,
+    },
+    Migration {
+        version: 5,
+        name: "session_activity",
+        up: v5_up,
+        down: v5_down,
+        data: None,
+    },
+    Migration {
+        version: 6,
+        name: "api_tokens",
+        up: v6_up,
+        down: v6_down,
+        data: None,
+    },
+    Migration {
+        version: 7,
+        name: "login_attempts",
+        up: v7_up,
+        down: v7_down,
+        data: None,
+    },
+    Migration {
+        version: 8,
+        name: "search",
+        up: v8_up,
+        down: v8_down,
+        data: None,
+    },
+    Migration {
+        version: 9,
+        name: "foreign_keys",
+        up: v9_up,
+        down: v9_down,
+        data: None,
+    },
+    Migration {
+        version: 10,
+        name: "card_hashes",
+        up: v10_up,
+        down: v10_down,
+        data: Some(v10_data),
+    },
+    Migration {
+        version: 11,
+        name: "card_dedup",
+        up: v11_up,
+        down: v11_down,
+        data: None,
+    },
+];
+
+pub fn latest_version() -> u32 {
+    MIGRATIONS.last().map_or(0, |migration| migration.version)
+}
+
+fn v1_up() -> Vec<String> {
+    #[rustfmt::skip]
+    let stmnts = vec![
+        format!(
+            "CREATE TABLE IF NOT EXISTS `{SOURCES_T}`(
+                `id` INTEGER NOT NULL PRIMARY KEY,
+                `url` TEXT NOT NULL UNIQUE,
+                `last_checked` TEXT NOT NULL
+            )"
+        ),
+        format!(
+            "CREATE TABLE IF NOT EXISTS `{ACTIVITIES_T}`(
+                `id` INTEGER NOT NULL PRIMARY KEY,
+                `source_id` INTEGER NOT NULL,
+                `post_url` TEXT NOT NULL,
+                `timestamp` TEXT NOT NULL
+            )"
+        ),
+        format!(
+            "CREATE TABLE IF NOT EXISTS `{LOGINS_T}`(
+                `id` INTEGER NOT NULL PRIMARY KEY,
+                `timestamp` TEXT NOT NULL,
+                `key` TEXT NOT NULL UNIQUE
+            )"
+        ),
+        format!("CREATE INDEX IF NOT EXISTS idx_key ON {LOGINS_T} (key)"),
+
+        // Added 26/05/025
+        format!(
+            "CREATE TABLE IF NOT EXISTS `{R_ACTIVITIES_T}`(
+                `id` INTEGER NOT NULL PRIMARY KEY,
+                `timestamp` TEXT NOT NULL
+            )"
+        ),
+        format!(
+            "CREATE TABLE IF NOT EXISTS `{R_WATCHED_TABS_T}`(
+                `id` INTEGER NOT NULL PRIMARY KEY,
+                `tab_roadmap_id` TEXT NOT NULL UNIQUE,
+                `timestamp` TEXT NOT NULL
+            )"
+        ),
+        format!(
+            "CREATE TABLE IF NOT EXISTS `{R_CARDS_T}`(
+                `id` INTEGER NOT NULL PRIMARY KEY,
+                `roadmap_id` TEXT NOT NULL,
+                `name` TEXT NOT NULL,
+                `description` TEXT NOT NULL,
+                `image_url` TEXT,
+                `slug` TEXT NOT NULL,
+                `timestamp` TEXT NOT NULL
+            )"
+        ),
+        format!(
+            "CREATE TABLE IF NOT EXISTS `{R_TABS_T}`(
+                `id` INTEGER NOT NULL PRIMARY KEY,
+                `roadmap_id` TEXT NOT NULL UNIQUE,
+                `name` TEXT NOT NULL,
+                `slug` TEXT NOT NULL,
+                `timestamp` TEXT NOT NULL
+            )"
+        ),
+        format!(
+            "CREATE TABLE IF NOT EXISTS `{R_CARD_ASSIGNS_T}`(
+                `id` INTEGER NOT NULL PRIMARY KEY,
+                `activity_id` INTEGER NOT NULL,
+                `tab_id` INTEGER NOT NULL,
+                `card_id` INTEGER NOT NULL,
+                `section_position` INTEGER NOT NULL,
+                `card_position` INTEGER NOT NULL,
+                `timestamp` TEXT NOT NULL
+            )"
+        ),
+        format!(
+            "CREATE TABLE IF NOT EXISTS `{R_TAB_ASSIGNS_T}`(
+                `id` INTEGER NOT NULL PRIMARY KEY,
+                `activity_id` INTEGER NOT NULL,
+                `tab_id` INTEGER NOT NULL,
+                `timestamp` TEXT NOT NULL
+            )"
+        ),
+        format!(
+            "CREATE TABLE IF NOT EXISTS `{R_CHANGES_T}`(
+                `id` INTEGER NOT NULL PRIMARY KEY,
+                `type` TEXT NOT NULL,
+                `activity_id` INTEGER NOT NULL,
+                `previous_card_id` INTEGER,
+                `current_card_id` INTEGER,
+                `tab_id` INTEGER,
+                `timestamp` TEXT NOT NULL
+            )"
+        ),
+    ];
+
+    stmnts
+}
+
+fn v1_down() -> Vec<String> {
+    [
+        SOURCES_T,
+        ACTIVITIES_T,
+        LOGINS_T,
+        R_ACTIVITIES_T,
+        R_WATCHED_TABS_T,
+        R_CARDS_T,
+        R_TABS_T,
+        R_CARD_ASSIGNS_T,
+        R_TAB_ASSIGNS_T,
+        R_CHANGES_T,
+    ]
+    .iter()
+    .map(|table| format!("DROP TABLE IF EXISTS {table}"))
+    .collect()
+}
+
+fn v2_up() -> Vec<String> {
+    #[rustfmt::skip]
+    let stmnts = vec![
+        format!("
+            ALTER TABLE {SOURCES_T}
+            ADD enabled INTEGER NOT NULL DEFAULT 1
+        "),
+        format!("
+            ALTER TABLE {SOURCES_T}
+            ADD failed_count INTEGER NOT NULL DEFAULT 0
+        "),
+    ];
+
+    stmnts
+}
+
+fn v2_down() -> Vec<String> {
+    vec![
+        format!("ALTER TABLE {SOURCES_T} DROP COLUMN enabled"),
+        format!("ALTER TABLE {SOURCES_T} DROP COLUMN failed_count"),
+    ]
+}
+
+fn v3_up() -> Vec<String> {
+    #[rustfmt::skip]
+    let stmnts = vec![
+        format!("
+            ALTER TABLE {SOURCES_T}
+            ADD redirect_url TEXT
+        "),
+        format!(
+            "CREATE TABLE IF NOT EXISTS `{SOURCE_EVENTS_T}`(
+                `id` INTEGER NOT NULL PRIMARY KEY,
+                `source_id` INTEGER NOT NULL,
+                `type` TEXT NOT NULL,
+                `message` TEXT NOT NULL,
+                `timestamp` TEXT NOT NULL
+            )"
+        ),
+    ];
+
+    stmnts
+}
+
+fn v3_down() -> Vec<String> {
+    vec![
+        format!("DROP TABLE IF EXISTS {SOURCE_EVENTS_T}"),
+        format!("ALTER TABLE {SOURCES_T} DROP COLUMN redirect_url"),
+    ]
+}
+
+/// Adds users and scopes sources and watched tabs to them. Rows that already
+/// exist are given to user 1, which is whoever is created first.
+fn v4_up() -> Vec<String> {
+    #[rustfmt::skip]
+    let stmnts = vec![
+        format!(
+            "CREATE TABLE IF NOT EXISTS `{USERS_T}`(
+                `id` INTEGER NOT NULL PRIMARY KEY,
+                `username` TEXT NOT NULL UNIQUE,
+                `password_hash` TEXT NOT NULL,
+                `is_admin` INTEGER NOT NULL DEFAULT 0,
+                `timestamp` TEXT NOT NULL
+            )"
+        ),
+
+        // Logins from before users existed can't be tied to anyone
+        format!("DELETE FROM {LOGINS_T}"),
+        format!("
+            ALTER TABLE {LOGINS_T}
+            ADD user_id INTEGER
+        "),
+
+        // Urls and tabs are only unique per user now, which needs a rebuild
+        format!(
+            "CREATE TABLE `{SOURCES_T}_v4`(
+                `id` INTEGER NOT NULL PRIMARY KEY,
+                `user_id` INTEGER NOT NULL,
+                `url` TEXT NOT NULL,
+                `last_checked` TEXT NOT NULL,
+                `enabled` INTEGER NOT NULL DEFAULT 1,
+                `failed_count` INTEGER NOT NULL DEFAULT 0,
+                `redirect_url` TEXT,
+                UNIQUE(`user_id`, `url`)
+            )"
+        ),
+        format!("
+            INSERT INTO {SOURCES_T}_v4 (id, user_id, url, last_checked, enabled, failed_count, redirect_url)
+            SELECT id, 1, url, last_checked, enabled, failed_count, redirect_url FROM {SOURCES_T}
+        "),
+        format!("DROP TABLE {SOURCES_T}"),
+        format!("ALTER TABLE {SOURCES_T}_v4 RENAME TO {SOURCES_T}"),
+
+        format!(
+            "CREATE TABLE `{R_WATCHED_TABS_T}_v4`(
+                `id` INTEGER NOT NULL PRIMARY KEY,
+                `user_id` INTEGER NOT NULL,
+                `tab_roadmap_id` TEXT NOT NULL,
+                `timestamp` TEXT NOT NULL,
+                UNIQUE(`user_id`, `tab_roadmap_id`)
+            )"
+        ),
+        format!("
+            INSERT INTO {R_WATCHED_TABS_T}_v4 (id, user_id, tab_roadmap_id, timestamp)
+            SELECT id, 1, tab_roadmap_id, timestamp FROM {R_WATCHED_TABS_T}
+        "),
+        format!("DROP TABLE {R_WATCHED_TABS_T}"),
+        format!("ALTER TABLE {R_WATCHED_TABS_T}_v4 RENAME TO {R_WATCHED_TABS_T}"),
+    ];
+
+    stmnts
+}
+
+/// Urls and tabs become unique again, so when users share one only the
+/// oldest row is kept
+fn v4_down() -> Vec<String> {
+    #[rustfmt::skip]
+    let stmnts = vec![
+        format!(
+            "CREATE TABLE `{SOURCES_T}_v3`(
+                `id` INTEGER NOT NULL PRIMARY KEY,
+                `url` TEXT NOT NULL UNIQUE,
+                `last_checked` TEXT NOT NULL,
+                `enabled` INTEGER NOT NULL DEFAULT 1,
+                `failed_count` INTEGER NOT NULL DEFAULT 0,
+                `redirect_url` TEXT
+            )"
+        ),
+        format!("
+            INSERT OR IGNORE INTO {SOURCES_T}_v3 (id, url, last_checked, enabled, failed_count, redirect_url)
+            SELECT id, url, last_checked, enabled, failed_count, redirect_url FROM {SOURCES_T} ORDER BY id
+        "),
+        format!("DROP TABLE {SOURCES_T}"),
+        format!("ALTER TABLE {SOURCES_T}_v3 RENAME TO {SOURCES_T}"),
+
+        format!(
+            "CREATE TABLE `{R_WATCHED_TABS_T}_v3`(
+                `id` INTEGER NOT NULL PRIMARY KEY,
+                `tab_roadmap_id` TEXT NOT NULL UNIQUE,
+                `timestamp` TEXT NOT NULL
+            )"
+        ),
+        format!("
+            INSERT OR IGNORE INTO {R_WATCHED_TABS_T}_v3 (id, tab_roadmap_id, timestamp)
+            SELECT id, tab_roadmap_id, timestamp FROM {R_WATCHED_TABS_T} ORDER BY id
+        "),
+        format!("DROP TABLE {R_WATCHED_TABS_T}"),
+        format!("ALTER TABLE {R_WATCHED_TABS_T}_v3 RENAME TO {R_WATCHED_TABS_T}"),
+
+        format!("ALTER TABLE {LOGINS_T} DROP COLUMN user_id"),
+        format!("DROP TABLE IF EXISTS {USERS_T}"),
+    ];
+
+    stmnts
+}
+
+fn v5_up() -> Vec<String> {
+    #[rustfmt::skip]
+    let stmnts = vec![
+        format!("
+            ALTER TABLE {LOGINS_T}
+            ADD last_seen TEXT
+        "),
+        format!("
+            ALTER TABLE {LOGINS_T}
+            ADD user_agent TEXT
+        "),
+        format!("UPDATE {LOGINS_T} SET last_seen = timestamp"),
+    ];
+
+    stmnts
+}
+
+fn v5_down() -> Vec<String> {
+    vec![
+        format!("ALTER TABLE {LOGINS_T} DROP COLUMN last_seen"),
+        format!("ALTER TABLE {LOGINS_T} DROP COLUMN user_agent"),
+    ]
+}
+
+fn v6_up() -> Vec<String> {
+    #[rustfmt::skip]
+    let stmnts = vec![
+        format!(
+            "CREATE TABLE IF NOT EXISTS `{API_TOKENS_T}`(
+                `id` INTEGER NOT NULL PRIMARY KEY,
+                `user_id` INTEGER NOT NULL,
+                `name` TEXT NOT NULL,
+                `token_hash` TEXT NOT NULL UNIQUE,
+                `scope` TEXT NOT NULL,
+                `timestamp` TEXT NOT NULL,
+                `last_used` TEXT
+            )"
+        ),
+    ];
+
+    stmnts
+}
+
+fn v6_down() -> Vec<String> {
+    vec![format!("DROP TABLE IF EXISTS {API_TOKENS_T}")]
+}
+
+fn v7_up() -> Vec<String> {
+    #[rustfmt::skip]
+    let stmnts = vec![
+        format!(
+            "CREATE TABLE IF NOT EXISTS `{LOGIN_ATTEMPTS_T}`(
+                `id` INTEGER NOT NULL PRIMARY KEY,
+                `ip` TEXT NOT NULL,
+                `username` TEXT NOT NULL,
+                `reason` TEXT NOT NULL,
+                `timestamp` TEXT NOT NULL
+            )"
+        ),
+
+        // Session keys are stored hashed now, existing ones can't be matched
+        format!("DELETE FROM {LOGINS_T}"),
+    ];
+
+    stmnts
+}
+
+/// Sessions are hashed either way, so there's nothing to undo for them
+fn v7_down() -> Vec<String> {
+    vec![format!("DROP TABLE IF EXISTS {LOGIN_ATTEMPTS_T}")]
+}
+
+/// Adds full text search over activities and roadmap cards. The indexes only
+/// point at their tables and are kept in sync by triggers. Activities from
+/// before this have no title or body, so only their url is searchable.
+fn v8_up() -> Vec<String> {
+    #[rustfmt::skip]
+    let stmnts = vec![
+        format!("
+            ALTER TABLE {ACTIVITIES_T}
+            ADD title TEXT
+        "),
+        format!("
+            ALTER TABLE {ACTIVITIES_T}
+            ADD body TEXT
+        "),
+
+        format!("
+            CREATE VIRTUAL TABLE IF NOT EXISTS {ACTIVITIES_FTS_T}
+            USING fts5(title, body, post_url, content='{ACTIVITIES_T}', content_rowid='id')
+        "),
+        format!("
+            CREATE TRIGGER IF NOT EXISTS {ACTIVITIES_FTS_T}_insert AFTER INSERT ON {ACTIVITIES_T} BEGIN
+                INSERT INTO {ACTIVITIES_FTS_T} (rowid, title, body, post_url)
+                VALUES (new.id, new.title, new.body, new.post_url);
+            END
+        "),
+        format!("
+            CREATE TRIGGER IF NOT EXISTS {ACTIVITIES_FTS_T}_delete AFTER DELETE ON {ACTIVITIES_T} BEGIN
+                INSERT INTO {ACTIVITIES_FTS_T} ({ACTIVITIES_FTS_T}, rowid, title, body, post_url)
+                VALUES ('delete', old.id, old.title, old.body, old.post_url);
+            END
+        "),
+        format!("
+            CREATE TRIGGER IF NOT EXISTS {ACTIVITIES_FTS_T}_update AFTER UPDATE ON {ACTIVITIES_T} BEGIN
+                INSERT INTO {ACTIVITIES_FTS_T} ({ACTIVITIES_FTS_T}, rowid, title, body, post_url)
+                VALUES ('delete', old.id, old.title, old.body, old.post_url);
+                INSERT INTO {ACTIVITIES_FTS_T} (rowid, title, body, post_url)
+                VALUES (new.id, new.title, new.body, new.post_url);
+            END
+        "),
+        format!("INSERT INTO {ACTIVITIES_FTS_T} ({ACTIVITIES_FTS_T}) VALUES ('rebuild')"),
+
+        format!("
+            CREATE VIRTUAL TABLE IF NOT EXISTS {R_CARDS_FTS_T}
+            USING fts5(name, description, content='{R_CARDS_T}', content_rowid='id')
+        "),
+        format!("
+            CREATE TRIGGER IF NOT EXISTS {R_CARDS_FTS_T}_insert AFTER INSERT ON {R_CARDS_T} BEGIN
+                INSERT INTO {R_CARDS_FTS_T} (rowid, name, description)
+                VALUES (new.id, new.name, new.description);
+            END
+        "),
+        format!("
+            CREATE TRIGGER IF NOT EXISTS {R_CARDS_FTS_T}_delete AFTER DELETE ON {R_CARDS_T} BEGIN
+                INSERT INTO {R_CARDS_FTS_T} ({R_CARDS_FTS_T}, rowid, name, description)
+                VALUES ('delete', old.id, old.name, old.description);
+            END
+        "),
+        format!("
+            CREATE TRIGGER IF NOT EXISTS {R_CARDS_FTS_T}_update AFTER UPDATE ON {R_CARDS_T} BEGIN
+                INSERT INTO {R_CARDS_FTS_T} ({R_CARDS_FTS_T}, rowid, name, description)
+                VALUES ('delete', old.id, old.name, old.description);
+                INSERT INTO {R_CARDS_FTS_T} (rowid, name, description)
+                VALUES (new.id, new.name, new.description);
+            END
+        "),
+        format!("INSERT INTO {R_CARDS_FTS_T} ({R_CARDS_FTS_T}) VALUES ('rebuild')"),
+    ];
+
+    stmnts
+}
+
+fn v8_down() -> Vec<String> {
+    let mut stmnts = Vec::new();
+    for table in [ACTIVITIES_FTS_T, R_CARDS_FTS_T] {
+        for trigger in ["insert", "delete", "update"] {
+            stmnts.push(format!("DROP TRIGGER IF EXISTS {table}_{trigger}"));
+        }
+        stmnts.push(format!("DROP TABLE IF EXISTS {table}"));
+    }
+    stmnts.push(format!("ALTER TABLE {ACTIVITIES_T} DROP COLUMN title"));
+    stmnts.push(format!("ALTER TABLE {ACTIVITIES_T} DROP COLUMN body"));
+
+    stmnts
+}
+
+/// Recreates a table with a new definition, keeping its rows. Indexes and
+/// triggers on the old table are dropped with it.
+fn rebuild_table(table: &str, definition: &str, columns: &str) -> Vec<String> {
+    vec![
+        format!("CREATE TABLE `{table}_new`({definition})"),
+        format!("INSERT INTO {table}_new ({columns}) SELECT {columns} FROM {table}"),
+        format!("DROP TABLE {table}"),
+        format!("ALTER TABLE {table}_new RENAME TO {table}"),
+    ]
+}
+
+/// The search index triggers from v8, which go when activities is rebuilt
+fn activities_fts_triggers() -> Vec<String> {
+    #[rustfmt::skip]
+    let stmnts = vec![
+        format!("
+            CREATE TRIGGER {ACTIVITIES_FTS_T}_insert AFTER INSERT ON {ACTIVITIES_T} BEGIN
+                INSERT INTO {ACTIVITIES_FTS_T} (rowid, title, body, post_url)
+                VALUES (new.id, new.title, new.body, new.post_url);
+            END
+        "),
+        format!("
+            CREATE TRIGGER {ACTIVITIES_FTS_T}_delete AFTER DELETE ON {ACTIVITIES_T} BEGIN
+                INSERT INTO {ACTIVITIES_FTS_T} ({ACTIVITIES_FTS_T}, rowid, title, body, post_url)
+                VALUES ('delete', old.id, old.title, old.body, old.post_url);
+            END
+        "),
+        format!("
+            CREATE TRIGGER {ACTIVITIES_FTS_T}_update AFTER UPDATE ON {ACTIVITIES_T} BEGIN
+                INSERT INTO {ACTIVITIES_FTS_T} ({ACTIVITIES_FTS_T}, rowid, title, body, post_url)
+                VALUES ('delete', old.id, old.title, old.body, old.post_url);
+                INSERT INTO {ACTIVITIES_FTS_T} (rowid, title, body, post_url)
+                VALUES (new.id, new.title, new.body, new.post_url);
+            END
+        "),
+    ];
+
+    stmnts
+}
+
+const ACTIVITIES_COLUMNS: &str = "id, source_id, post_url, timestamp, title, body";
+const SOURCE_EVENTS_COLUMNS: &str = "id, source_id, type, message, timestamp";
+const R_CARD_ASSIGNS_COLUMNS: &str =
+    "id, activity_id, tab_id, card_id, section_position, card_position, timestamp";
+const R_TAB_ASSIGNS_COLUMNS: &str = "id, activity_id, tab_id, timestamp";
+const R_CHANGES_COLUMNS: &str =
+    "id, type, activity_id, previous_card_id, current_card_id, tab_id, timestamp";
+
+/// Indexes added by v9 on tables it doesn't rebuild
+const V9_INDEXES: &[&str] = &[
+    "idx_roadmap_activities_sort",
+    "idx_logins_user_id",
+    "idx_api_tokens_user_id",
+];
+
+/// Adds foreign keys so deleting a source or roadmap row takes everything
+/// pointing at it along, and indexes every column that's joined or sorted
+/// on. Sqlite can't add foreign keys to a table, so the tables are rebuilt
+/// after deleting rows that already point at nothing. Rows from before users
+/// are owned by user 1, who might not exist yet, so user ids are left alone.
+fn v9_up() -> Vec<String> {
+    let activities_sort = sort_key("timestamp");
+
+    #[rustfmt::skip]
+    let mut stmnts = vec![
+        format!("DELETE FROM {ACTIVITIES_T} WHERE source_id NOT IN (SELECT id FROM {SOURCES_T})"),
+        format!("DELETE FROM {SOURCE_EVENTS_T} WHERE source_id NOT IN (SELECT id FROM {SOURCES_T})"),
+        format!("
+            DELETE FROM {R_CARD_ASSIGNS_T}
+            WHERE activity_id NOT IN (SELECT id FROM {R_ACTIVITIES_T})
+                OR tab_id NOT IN (SELECT id FROM {R_TABS_T})
+                OR card_id NOT IN (SELECT id FROM {R_CARDS_T})
+        "),
+        format!("
+            DELETE FROM {R_TAB_ASSIGNS_T}
+            WHERE activity_id NOT IN (SELECT id FROM {R_ACTIVITIES_T})
+                OR tab_id NOT IN (SELECT id FROM {R_TABS_T})
+        "),
+        format!("
+            DELETE FROM {R_CHANGES_T}
+            WHERE activity_id NOT IN (SELECT id FROM {R_ACTIVITIES_T})
+                OR previous_card_id NOT IN (SELECT id FROM {R_CARDS_T})
+                OR current_card_id NOT IN (SELECT id FROM {R_CARDS_T})
+                OR tab_id NOT IN (SELECT id FROM {R_TABS_T})
+        "),
+    ];
+
+    stmnts.extend(rebuild_table(
+        ACTIVITIES_T,
+        &format!(
+            "`id` INTEGER NOT NULL PRIMARY KEY,
+            `source_id` INTEGER NOT NULL REFERENCES {SOURCES_T}(id) ON DELETE CASCADE,
+            `post_url` TEXT NOT NULL,
+            `timestamp` TEXT NOT NULL,
+            `title` TEXT,
+            `body` TEXT"
+        ),
+        ACTIVITIES_COLUMNS,
+    ));
+    stmnts.extend(activities_fts_triggers());
+    stmnts.extend(rebuild_table(
+        SOURCE_EVENTS_T,
+        &format!(
+            "`id` INTEGER NOT NULL PRIMARY KEY,
+            `source_id` INTEGER NOT NULL REFERENCES {SOURCES_T}(id) ON DELETE CASCADE,
+            `type` TEXT NOT NULL,
+            `message` TEXT NOT NULL,
+            `timestamp` TEXT NOT NULL"
+        ),
+        SOURCE_EVENTS_COLUMNS,
+    ));
+    stmnts.extend(rebuild_table(
+        R_CARD_ASSIGNS_T,
+        &format!(
+            "`id` INTEGER NOT NULL PRIMARY KEY,
+            `activity_id` INTEGER NOT NULL REFERENCES {R_ACTIVITIES_T}(id) ON DELETE CASCADE,
+            `tab_id` INTEGER NOT NULL REFERENCES {R_TABS_T}(id) ON DELETE CASCADE,
+            `card_id` INTEGER NOT NULL REFERENCES {R_CARDS_T}(id) ON DELETE CASCADE,
+            `section_position` INTEGER NOT NULL,
+            `card_position` INTEGER NOT NULL,
+            `timestamp` TEXT NOT NULL"
+        ),
+        R_CARD_ASSIGNS_COLUMNS,
+    ));
+    stmnts.extend(rebuild_table(
+        R_TAB_ASSIGNS_T,
+        &format!(
+            "`id` INTEGER NOT NULL PRIMARY KEY,
+            `activity_id` INTEGER NOT NULL REFERENCES {R_ACTIVITIES_T}(id) ON DELETE CASCADE,
+            `tab_id` INTEGER NOT NULL REFERENCES {R_TABS_T}(id) ON DELETE CASCADE,
+            `timestamp` TEXT NOT NULL"
+        ),
+        R_TAB_ASSIGNS_COLUMNS,
+    ));
+    stmnts.extend(rebuild_table(
+        R_CHANGES_T,
+        &format!(
+            "`id` INTEGER NOT NULL PRIMARY KEY,
+            `type` TEXT NOT NULL,
+            `activity_id` INTEGER NOT NULL REFERENCES {R_ACTIVITIES_T}(id) ON DELETE CASCADE,
+            `previous_card_id` INTEGER REFERENCES {R_CARDS_T}(id) ON DELETE CASCADE,
+            `current_card_id` INTEGER REFERENCES {R_CARDS_T}(id) ON DELETE CASCADE,
+            `tab_id` INTEGER REFERENCES {R_TABS_T}(id) ON DELETE CASCADE,
+            `timestamp` TEXT NOT NULL"
+        ),
+        R_CHANGES_COLUMNS,
+    ));
+
+    #[rustfmt::skip]
+    stmnts.extend([
+        format!("CREATE INDEX idx_activities_source_id ON {ACTIVITIES_T} (source_id)"),
+        format!("CREATE INDEX idx_activities_sort ON {ACTIVITIES_T} ({activities_sort}, id)"),
+        format!("CREATE INDEX idx_source_events_source_id ON {SOURCE_EVENTS_T} (source_id)"),
+        format!("CREATE INDEX idx_card_assignments_activity_id ON {R_CARD_ASSIGNS_T} (activity_id)"),
+        format!("CREATE INDEX idx_card_assignments_tab_id ON {R_CARD_ASSIGNS_T} (tab_id)"),
+        format!("CREATE INDEX idx_card_assignments_card_id ON {R_CARD_ASSIGNS_T} (card_id)"),
+        format!("CREATE INDEX idx_tab_assignments_activity_id ON {R_TAB_ASSIGNS_T} (activity_id)"),
+        format!("CREATE INDEX idx_tab_assignments_tab_id ON {R_TAB_ASSIGNS_T} (tab_id)"),
+        format!("CREATE INDEX idx_changes_activity_id ON {R_CHANGES_T} (activity_id)"),
+        format!("CREATE INDEX idx_changes_previous_card_id ON {R_CHANGES_T} (previous_card_id)"),
+        format!("CREATE INDEX idx_changes_current_card_id ON {R_CHANGES_T} (current_card_id)"),
+        format!("CREATE INDEX idx_changes_tab_id ON {R_CHANGES_T} (tab_id)"),
+        format!("CREATE INDEX {} ON {R_ACTIVITIES_T} ({activities_sort}, id)", V9_INDEXES[0]),
+        format!("CREATE INDEX {} ON {LOGINS_T} (user_id)", V9_INDEXES[1]),
+        format!("CREATE INDEX {} ON {API_TOKENS_T} (user_id)", V9_INDEXES[2]),
+    ]);
+
+    stmnts
+}
+
+/// Rebuilding drops the foreign keys and the indexes on those tables, the
+/// deleted orphans stay deleted
+fn v9_down() -> Vec<String> {
+    let mut stmnts = V9_INDEXES
+        .iter()
+        .map(|index| format!("DROP INDEX IF EXISTS {index}"))
+        .collect::<Vec<_>>();
+
+    stmnts.extend(rebuild_table(
+        ACTIVITIES_T,
+        "`id` INTEGER NOT NULL PRIMARY KEY,
+        `source_id` INTEGER NOT NULL,
+        `post_url` TEXT NOT NULL,
+        `timestamp` TEXT NOT NULL,
+        `title` TEXT,
+        `body` TEXT",
+        ACTIVITIES_COLUMNS,
+    ));
+    stmnts.extend(activities_fts_triggers());
+    stmnts.extend(rebuild_table(
+        SOURCE_EVENTS_T,
+        "`id` INTEGER NOT NULL PRIMARY KEY,
+        `source_id` INTEGER NOT NULL,
+        `type` TEXT NOT NULL,
+        `message` TEXT NOT NULL,
+        `timestamp` TEXT NOT NULL",
+        SOURCE_EVENTS_COLUMNS,
+    ));
+    stmnts.extend(rebuild_table(
+        R_CARD_ASSIGNS_T,
+        "`id` INTEGER NOT NULL PRIMARY KEY,
+        `activity_id` INTEGER NOT NULL,
+        `tab_id` INTEGER NOT NULL,
+        `card_id` INTEGER NOT NULL,
+        `section_position` INTEGER NOT NULL,
+        `card_position` INTEGER NOT NULL,
+        `timestamp` TEXT NOT NULL",
+        R_CARD_ASSIGNS_COLUMNS,
+    ));
+    stmnts.extend(rebuild_table(
+        R_TAB_ASSIGNS_T,
+        "`id` INTEGER NOT NULL PRIMARY KEY,
+        `activity_id` INTEGER NOT NULL,
+        `tab_id` INTEGER NOT NULL,
+        `timestamp` TEXT NOT NULL",
+        R_TAB_ASSIGNS_COLUMNS,
+    ));
+    stmnts.extend(rebuild_table(
+        R_CHANGES_T,
+        "`id` INTEGER NOT NULL PRIMARY KEY,
+        `type` TEXT NOT NULL,
+        `activity_id` INTEGER NOT NULL,
+        `previous_card_id` INTEGER,
+        `current_card_id` INTEGER,
+        `tab_id` INTEGER,
+        `timestamp` TEXT NOT NULL",
+        R_CHANGES_COLUMNS,
+    ));
+
+    stmnts
+}
+
+/// Cards are stored by a hash of their content so unchanged cards can be
+/// reused instead of saved again on every check
+fn v10_up() -> Vec<String> {
+    vec![format!("ALTER TABLE {R_CARDS_T} ADD content_hash TEXT")]
+}
+
+fn v10_data(conn: &Connection) -> Pin<Box<dyn Future<Output = anyhow::Result<()>> + Send + '_>> {
+    Box::pin(async move {
+        let mut rows = conn
+            .query(
+                &format!(
+                    "SELECT id, roadmap_id, name, description, image_url, slug FROM {R_CARDS_T}"
+                ),
+                params!(),
+            )
+            .await?;
+
+        while let Some(row) = rows.next().await? {
+            let hash = hash_card(
+                &row.get::<String>(1)?,
+                &row.get::<String>(2)?,
+                &row.get::<String>(3)?,
+                row.get::<Option<String>>(4)?.as_deref(),
+                &row.get::<String>(5)?,
+            );
+            conn.execute(
+                &format!("UPDATE {R_CARDS_T} SET content_hash = ?1 WHERE id = ?2"),
+                (hash, row.get::<u32>(0)?),
+            )
+            .await?;
+        }
+
+        Ok(())
+    })
+}
+
+fn v10_down() -> Vec<String> {
+    vec![format!("ALTER TABLE {R_CARDS_T} DROP COLUMN content_hash")]
+}
+
+/// Points everything at the oldest copy of each card and deletes the rest
+fn v11_up() -> Vec<String> {
+    let oldest_copy = |column: &str| {
+        format!(
+            "(SELECT MIN(c2.id) FROM {R_CARDS_T} AS c1
+            INNER JOIN {R_CARDS_T} AS c2
+                ON c1.content_hash = c2.content_hash
+            WHERE c1.id = {column})"
+        )
+    };
+
+    #[rustfmt::skip]
+    let stmnts = vec![
+        format!("UPDATE {R_CARD_ASSIGNS_T} SET card_id = {}", oldest_copy("card_id")),
+        format!("
+            UPDATE {R_CHANGES_T}
+            SET previous_card_id = {}
+            WHERE previous_card_id IS NOT NULL
+        ", oldest_copy("previous_card_id")),
+        format!("
+            UPDATE {R_CHANGES_T}
+            SET current_card_id = {}
+            WHERE current_card_id IS NOT NULL
+        ", oldest_copy("current_card_id")),
+        format!("
+            DELETE FROM {R_CARDS_T}
+            WHERE id NOT IN (SELECT MIN(id) FROM {R_CARDS_T} GROUP BY content_hash)
+        "),
+        format!("CREATE UNIQUE INDEX idx_roadmap_cards_content_hash ON {R_CARDS_T} (content_hash)"),
+    ];
+
+    stmnts
+}
+
+/// The duplicates aren't coming back, only the index goes
+fn v11_down() -> Vec<String> {
+    vec!["DROP INDEX IF EXISTS idx_roadmap_cards_content_hash".into()]
+}
+
+async fn create_migrations_table(conn: &Connection) -> anyhow::Result<()> {
+    conn.execute(
+        &format!(
+            "CREATE TABLE IF NOT EXISTS `{MIGRATIONS_T}`(
+                `version` INTEGER NOT NULL PRIMARY KEY,
+                `name` TEXT NOT NULL,
+                `checksum` TEXT NOT NULL,
+                `applied_at` TEXT NOT NULL
+            )"
+        ),
+        params!(),
+    )
+    .await?;
+
+    Ok(())
+}
+
+async fn table_exists(conn: &Connection, name: &str) -> anyhow::Result<bool> {
+    Ok(conn
+        .query(
+            "SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?1",
+            [name],
+        )
+        .await?
+        .next()
+        .await?
+        .is_some())
+}
+
+/// The version a database from before `schema_migrations` is at, if it still
+/// has its `db_version` table
+pub async fn legacy_version(conn: &Connection) -> anyhow::Result<Option<u32>> {
+    if !table_exists(conn, VERSION_T).await? {
+        return Ok(None);
+    }
+
+    // The row was only added by v2, so without it only v1 has run
+    let row = conn
+        .query(
+            &format!("SELECT version_number FROM {VERSION_T} WHERE id = 1"),
+            params!(),
+        )
+        .await?
+        .next()
+        .await?;
+
+    Ok(Some(match row {
+        Some(row) => row.get(0)?,
+        None => 1,
+    }))
+}
+
+/// Databases from before this tracked a single number in `db_version`. The
+/// migrations up to it are recorded as applied and the old table dropped.
+async fn adopt_version_table(conn: &Connection) -> anyhow::Result<()> {
+    let Some(version) = legacy_version(conn).await? else {
+        return Ok(());
+    };
+
+    info!("[Migrate] Adopting database at version {version} from {VERSION_T}");
+    let tx = conn.transaction().await?;
+    for migration in MIGRATIONS.iter().take_while(|m| m.version <= version) {
+        record_migration(&tx, migration).await?;
+    }
+    tx.execute(&format!("DROP TABLE {VERSION_T}"), params!())
+        .await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+async fn record_migration(conn: &Connection, migration: &Migration) -> anyhow::Result<()> {
+    conn.execute(
+        &format!(
+            "INSERT INTO {MIGRATIONS_T} (version, name, checksum, applied_at) VALUES (?1, ?2, ?3, ?4)"
+        ),
+        (
+            migration.version,
+            migration.name,
+            migration.checksum(),
+            serde_json::to_string(&OffsetDateTime::now_utc()).unwrap(),
+        ),
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Creates `schema_migrations` and adopts an old `db_version` table, so
+/// [`applied_migrations`] sees every migration that has run
+async fn prepare(conn: &Connection) -> anyhow::Result<()> {
+    create_migrations_table(conn).await?;
+    adopt_version_table(conn).await
+}
+
+/// Migrations recorded in `schema_migrations`, oldest first. Doesn't write,
+/// so a database that hasn't been migrated yet has none.
+pub async fn applied_migrations(conn: Connection) -> anyhow::Result<Vec<AppliedMigration>> {
+    if !table_exists(&conn, MIGRATIONS_T).await? {
+        return Ok(Vec::new());
+    }
+
+    let mut result = conn
+        .query(
+            &format!("SELECT * FROM {MIGRATIONS_T} ORDER BY version"),
+            params!(),
+        )
+        .await?;
+
+    let mut applied = Vec::new();
+    while let Some(row) = result.next().await? {
+        applied.push(libsql::de::from_row(&row)?);
+    }
+
+    Ok(applied)
+}
+
+/// Runs every migration that hasn't been yet. Refuses to if the database
+/// has migrations this build doesn't know about or ones that have changed.
+pub async fn migrate_up(conn: Connection) -> anyhow::Result<()> {
+    prepare(&conn).await?;
+    let applied = applied_migrations(conn.clone()).await?;
+
+    for applied in &applied {
+        let Some(migration) = MIGRATIONS.iter().find(|m| m.version == applied.version) else {
+            return Err(StringError(format!(
+                "Database has migration {} ({}) which this build doesn't know about, the newest it knows is {}. Run a newer build or roll back with it",
+                applied.version,
+                applied.name,
+                latest_version()
+            ))
+            .into());
+        };
+
+        if migration.checksum() != applied.checksum {
+            return Err(StringError(format!(
+                "Migration {} ({}) has changed since it was applied",
+                migration.version, migration.name
+            ))
+            .into());
+        }
+    }
+
+    for migration in MIGRATIONS
+        .iter()
+        .filter(|m| !applied.iter().any(|a| a.version == m.version))
+    {
+        info!(
+            "[Migrate] Applying {} ({})",
+            migration.version, migration.name
+        );
+        let tx = conn.transaction().await?;
+        tx.execute_batch(&migration.up_sql()).await?;
+        if let Some(data) = migration.data {
+            data(&tx).await?;
+        }
+        record_migration(&tx, migration).await?;
+        tx.commit().await?;
+    }
+
+    Ok(())
+}
+
+/// Rolls back every migration after `to`, newest first. Without `to` only the
+/// latest is. Returns the version the database is left at.
+pub async fn migrate_down(conn: Connection, to: Option<u32>) -> anyhow::Result<u32> {
+    prepare(&conn).await?;
+    let applied = applied_migrations(conn.clone()).await?;
+    let to = to.unwrap_or_else(|| {
+        applied
+            .last()
+            .map_or(0, |latest| latest.version.saturating_sub(1))
+    });
+
+    for applied in applied.iter().rev().filter(|a| a.version > to) {
+        let Some(migration) = MIGRATIONS.iter().find(|m| m.version == applied.version) else {
+            return Err(StringError(format!(
+                "Can't roll back migration {} ({}), this build doesn't know about it",
+                applied.version, applied.name
+            ))
+            .into());
+        };
+
+        info!(
+            "[Migrate] Rolling back {} ({})",
+            migration.version, migration.name
+        );
+        let tx = conn.transaction().await?;
+        tx.execute_batch(&migration.down_sql()).await?;
+        tx.execute(
+            &format!("DELETE FROM {MIGRATIONS_T} WHERE version = ?1"),
+            [migration.version],
+        )
+        .await?;
+        tx.commit().await?;
+    }
+
+    Ok(to)
+}
@@ -0,0 +1,130 @@
+use libsql::{Builder, Connection, Database, OpenFlags, params};
+use log::{error, info};
+
+use crate::{
+    config::DatabaseConfig,
+    types::{DatabaseMode, StringError},
+};
+
+pub mod migrations;
+
+pub fn database_mode(config: &DatabaseConfig) -> DatabaseMode {
+    if config.postgres_url.is_some() {
+        DatabaseMode::Postgres
+    } else if config.use_local {
+        DatabaseMode::Local
+    } else if config.replica_path.is_some() {
+        DatabaseMode::Replica
+    } else {
+        DatabaseMode::Remote
+    }
+}
+
+pub async fn get_database(config: &DatabaseConfig) -> anyhow::Result<Database> {
+    let remote = || match (&config.url, &config.auth_key) {
+        (Some(url), Some(auth_key)) => Ok((url.clone(), auth_key.clone())),
+        _ => Err(StringError(
+            "database.url and database.auth_key must be set".into(),
+        )),
+    };
+
+    match database_mode(config) {
+        DatabaseMode::Local => {
+            let path = config
+                .local_path
+                .as_deref()
+                .ok_or_else(|| StringError("database.local_path must be set".into()))?;
+            Ok(Builder::new_local(path)
+                .flags(OpenFlags::default())
+                .build()
+                .await?)
+        }
+        DatabaseMode::Remote => {
+            let (url, auth_key) = remote()?;
+            Ok(Builder::new_remote(url, auth_key).build().await?)
+        }
+        DatabaseMode::Replica => {
+            let (url, auth_key) = remote()?;
+            let path = config.replica_path.as_deref().unwrap_or_default();
+            let database = Builder::new_remote_replica(path, url, auth_key)
+                .read_your_writes(true)
+                .build()
+                .await?;
+
+            // Migrations read the replica, so it has to be caught up first.
+            // Building already needs the remote, this only fails when it
+            // drops out in between.
+            match database.sync().await {
+                Ok(synced) => info!(
+                    "[Sync Database] Synced {} frames into {path}",
+                    synced.frames_synced()
+                ),
+                Err(err) => error!("[Sync Database] Initial sync failed with err: {err}"),
+            }
+            Ok(database)
+        }
+        DatabaseMode::Postgres => Err(StringError(
+            "database.postgres_url is set, the app database isn't libsql".into(),
+        )
+        .into()),
+    }
+}
+
+pub async fn get_demo_database(config: &DatabaseConfig) -> anyhow::Result<Database> {
+    Ok(Builder::new_local(&config.demo_path)
+        .flags(OpenFlags::default())
+        .build()
+        .await?)
+}
+
+/// Connects to a database with foreign keys enforced. Sqlite has them off by
+/// default and the setting only lasts for the connection.
+pub async fn connect(database: &Database) -> anyhow::Result<Connection> {
+    let conn = database.connect()?;
+    conn.execute("PRAGMA foreign_keys = ON", params!()).await?;
+
+    Ok(conn)
+}
+
+/// SQL for nanoseconds since the epoch of a timestamp column, so rows can be
+/// ordered and paged by time. Timestamps are stored as serialized
+/// `OffsetDateTime`s: `[year, ordinal, hour, minute, second, nanosecond,
+/// offset hours, offset minutes, offset seconds]`.
+pub fn sort_key(column: &str) -> String {
+    let part = |i: u8| format!("json_extract({column}, '$[{i}]')");
+    format!(
+        "(((CAST(julianday(printf('%04d-01-01', {})) - 2440587.5 AS INTEGER) + {} - 1) * 86400
+            + {} * 3600 + {} * 60 + {} - {} * 3600 - {} * 60 - {}) * 1000000000 + {})",
+        part(0),
+        part(1),
+        part(2),
+        part(3),
+        part(4),
+        part(6),
+        part(7),
+        part(8),
+        part(5),
+    )
+}
+
+pub const SOURCES_T: &str = "sources";
+pub const SOURCE_EVENTS_T: &str = "source_events";
+pub const ACTIVITIES_T: &str = "activities";
+pub const LOGINS_T: &str = "logins";
+pub const USERS_T: &str = "users";
+pub const API_TOKENS_T: &str = "api_tokens";
+pub const LOGIN_ATTEMPTS_T: &str = "login_attempts";
+pub const R_ACTIVITIES_T: &str = "roadmap_activities";
+pub const R_WATCHED_TABS_T: &str = "roadmap_watched_tabs";
+pub const R_CARDS_T: &str = "roadmap_cards";
+pub const R_TABS_T: &str = "roadmap_tabs";
+pub const R_CARD_ASSIGNS_T: &str = "roadmap_card_assignments";
+pub const R_TAB_ASSIGNS_T: &str = "roadmap_tab_assignments";
+pub const R_CHANGES_T: &str = "roadmap_changes";
+pub const ACTIVITIES_FTS_T: &str = "activities_fts";
+pub const R_CARDS_FTS_T: &str = "roadmap_cards_fts";
+
+/// Where versions were tracked before [`migrations`], only read to adopt
+/// databases from then
+pub const VERSION_T: &str = "db_version";
+pub const MIGRATIONS_T: &str = "schema_migrations";
@@ -0,0 +1,142 @@
+use std::{error::Error, fmt::Display};
+
+use actix_web::{
+    HttpRequest, HttpResponse, ResponseError,
+    http::{StatusCode, header::RETRY_AFTER},
+};
+use log::error;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::utils::removal_cookie;
+
+pub type RouteResult = Result<HttpResponse, GregError>;
+
+/// Errors returned from routes. Every error is sent as `{ code, message }`,
+/// where `code` is stable for clients to match on and `message` is for people.
+/// Internal errors are logged but never sent to the client.
+#[derive(thiserror::Error, Debug)]
+pub enum GregError {
+    #[error("Not logged in")]
+    Unauthorized,
+    #[error("Wrong password")]
+    WrongPassword,
+    #[error("{0}")]
+    Forbidden(&'static str),
+    #[error("Read only tokens can't make changes")]
+    ReadOnly,
+    #[error("{0}")]
+    NotFound(String),
+    #[error("{0}")]
+    Conflict(String),
+    #[error("{0}")]
+    BadRequest(String),
+    #[error("{0}")]
+    FeedUnavailable(String),
+    #[error("Too many failed logins. Try again in {retry_after} seconds")]
+    RateLimited { retry_after: u64 },
+    #[error("Something went wrong")]
+    Internal(#[from] anyhow::Error),
+}
+
+/// The body of every error response
+#[derive(Serialize, ToSchema)]
+pub struct ErrorBody {
+    /// Stable identifier like `not_found` or `rate_limited`
+    #[schema(example = "not_found")]
+    code: &'static str,
+    message: String,
+}
+
+impl GregError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            GregError::Unauthorized => "unauthorized",
+            GregError::WrongPassword => "wrong_password",
+            GregError::Forbidden(_) => "forbidden",
+            GregError::ReadOnly => "read_only_token",
+            GregError::NotFound(_) => "not_found",
+            GregError::Conflict(_) => "conflict",
+            GregError::BadRequest(_) => "bad_request",
+            GregError::FeedUnavailable(_) => "feed_unavailable",
+            GregError::RateLimited { .. } => "rate_limited",
+            GregError::Internal(_) => "internal",
+        }
+    }
+}
+
+impl From<libsql::Error> for GregError {
+    fn from(err: libsql::Error) -> Self {
+        GregError::Internal(err.into())
+    }
+}
+
+impl ResponseError for GregError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            GregError::Unauthorized | GregError::WrongPassword => StatusCode::UNAUTHORIZED,
+            GregError::Forbidden(_) | GregError::ReadOnly => StatusCode::FORBIDDEN,
+            GregError::NotFound(_) => StatusCode::NOT_FOUND,
+            GregError::Conflict(_) => StatusCode::CONFLICT,
+            GregError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            GregError::FeedUnavailable(_) => StatusCode::BAD_GATEWAY,
+            GregError::RateLimited { .. } => StatusCode::TOO_MANY_REQUESTS,
+            GregError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut res = HttpResponse::build(self.status_code());
+        match self {
+            GregError::Unauthorized | GregError::WrongPassword => {
+                res.cookie(removal_cookie());
+            }
+            GregError::RateLimited { retry_after } => {
+                res.insert_header((RETRY_AFTER, retry_after.to_string()));
+            }
+            _ => {}
+        }
+
+        res.json(ErrorBody {
+            code: self.code(),
+            message: self.to_string(),
+        })
+    }
+}
+
+/// Error handler for the json, path and query extractors, so malformed
+/// requests get the same error body as everything else
+pub fn bad_request<E: Display>(err: E, _req: &HttpRequest) -> actix_web::Error {
+    GregError::BadRequest(err.to_string()).into()
+}
+
+/// Whether a query failed because of a UNIQUE constraint
+pub fn is_unique_violation(err: &(dyn Error + 'static)) -> bool {
+    #[cfg(feature = "postgres")]
+    if let Some(err) = err.downcast_ref::<tokio_postgres::Error>() {
+        return err.code() == Some(&tokio_postgres::error::SqlState::UNIQUE_VIOLATION);
+    }
+
+    matches!(
+        err.downcast_ref::<libsql::Error>(),
+        Some(libsql::Error::SqliteFailure(code, message)) if code & 0xff == 19 && message.contains("UNIQUE")
+    )
+}
+
+pub trait LogErr<T> {
+    /// Logs the error as `{context} with err: {err}` and turns it into an
+    /// internal error, so the details stay in the logs
+    fn log_err(self, context: &str) -> Result<T, GregError>;
+}
+
+impl<T, E> LogErr<T> for Result<T, E>
+where
+    E: Into<anyhow::Error> + Display,
+{
+    fn log_err(self, context: &str) -> Result<T, GregError> {
+        self.map_err(|err| {
+            error!("{context} with err: {err}");
+            GregError::Internal(err.into())
+        })
+    }
+}
@@ -0,0 +1,54 @@
+use std::{fs, time::Duration};
+
+use anyhow::Context;
+use log::info;
+use reqwest::{Certificate, Client, ClientBuilder, Proxy, redirect::Policy};
+
+use crate::config::HttpConfig;
+
+pub fn user_agent(config: &HttpConfig) -> String {
+    format!(
+        "greg/{} (+{})",
+        env!("CARGO_PKG_VERSION"),
+        config.contact_url
+    )
+}
+
+/// Builds the client shared by every outgoing request (roadmap and mail).
+pub fn build_client(config: &HttpConfig) -> anyhow::Result<Client> {
+    Ok(client_builder(config)?
+        .redirect(Policy::limited(config.max_redirects))
+        .build()?)
+}
+
+/// Builds the client used to fetch feeds. It doesn't follow redirects so that
+/// permanent ones can be recorded, see `fetch_feed`.
+pub fn build_feed_client(config: &HttpConfig) -> anyhow::Result<Client> {
+    Ok(client_builder(config)?.redirect(Policy::none()).build()?)
+}
+
+fn client_builder(config: &HttpConfig) -> anyhow::Result<ClientBuilder> {
+    let mut builder = Client::builder()
+        .user_agent(user_agent(config))
+        .connect_timeout(Duration::from_secs(config.connect_timeout_secs))
+        .read_timeout(Duration::from_secs(config.read_timeout_secs))
+        .timeout(Duration::from_secs(config.request_timeout_secs));
+
+    if let Some(proxy_url) = &config.proxy_url {
+        info!("[HTTP] Using proxy for all requests");
+        builder = builder.proxy(Proxy::all(proxy_url).context("http.proxy_url is invalid")?);
+    }
+
+    for path in &config.ca_bundles {
+        let pem = fs::read(path).with_context(|| format!("Can't read CA bundle {path}"))?;
+        let certs = Certificate::from_pem_bundle(&pem)
+            .with_context(|| format!("CA bundle {path} is invalid"))?;
+
+        info!("[HTTP] Adding {} certificates from {path}", certs.len());
+        for cert in certs {
+            builder = builder.add_root_certificate(cert);
+        }
+    }
+
+    Ok(builder)
+}
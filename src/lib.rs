@@ -1,5 +1,14 @@
+pub mod auth;
+pub mod backup;
+pub mod cli;
+pub mod config;
 pub mod db;
+pub mod error;
+pub mod http;
+pub mod openapi;
+pub mod opml;
 pub mod queries;
+pub mod repo;
 pub mod routes;
 pub mod tasks;
 pub mod types;
@@ -1,58 +1,123 @@
+use std::{sync::Mutex, time::Duration};
+
 use actix_web::{
     App, HttpServer,
-    middleware::Logger,
+    middleware::{DefaultHeaders, Logger},
     web::{self, scope},
 };
-use actix_web_httpauth::extractors::basic;
 use actix_web_lab::web::spa;
 use anyhow::Ok;
+use clap::Parser;
 use dotenvy::dotenv;
 use greg::{
+    auth::LoginLimiter,
+    cli::{self, Cli, Command},
+    config::Config,
     db,
+    error::bad_request,
+    http, openapi,
+    repo::{self, libsql::LibsqlBackend},
     routes::{
         deletes::{
-            clear_activities, clear_all_activities, delete_source, delete_watched_tab, logout,
+            clear_activities, clear_all_activities, delete_source, delete_token,
+            delete_watched_tab, logout, logout_all_sessions,
         },
         gets::{
             check_logged_in, get_activity, get_changes, get_most_recent_tabs, get_roadmap_activity,
-            get_source_activity, get_sources, get_watched_tabs, keep_alive,
+            get_source_activity, get_source_events, get_watched_tabs, keep_alive,
+        },
+        posts::{
+            accept_source_redirect, add_source, add_watched_tab, enable_source, login,
+            preview_new_source, recheck, recheck_roadmap, recheck_source,
         },
-        posts::{add_source, add_watched_tab, enable_source, login, recheck, recheck_roadmap},
+        v1,
     },
-    types::AppState,
+    tasks::sync_database::sync_database,
+    types::{AppState, DatabaseMode},
 };
 use log::info;
+use tokio::time;
 
 #[cfg(feature = "scheduler")]
 use greg::tasks::check_roadmap::check_roadmap;
 #[cfg(feature = "scheduler")]
 use greg::tasks::check_sources::check_sources;
 #[cfg(feature = "scheduler")]
+use greg::tasks::prune_data::prune_data;
+#[cfg(feature = "scheduler")]
+use greg::tasks::prune_sessions::prune_sessions;
+#[cfg(feature = "scheduler")]
 use tokio_cron_scheduler::{Job, JobScheduler};
 
-async fn do_db_migrate(database: &libsql::Database, name: &str) -> anyhow::Result<()> {
-    info!("Connecting to {name} Database");
-    let conn = database.connect().expect("Can connect to database");
-
-    info!("Connected to Database. Migrating");
-    db::migrate_db(conn).await?;
-
-    info!("Migrated Database");
-    Ok(())
-}
-
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+
     dotenv().ok();
     env_logger::init_from_env(env_logger::Env::new().default_filter_or("info"));
 
-    let app_db = db::get_database().await;
-    do_db_migrate(&app_db, "app").await?;
+    let mut config = Config::load()?;
+    info!("Loaded config");
 
-    let demo_db = db::get_demo_database().await;
-    do_db_migrate(&demo_db, "app").await?;
+    let command = cli.command.unwrap_or(Command::Serve {
+        address: None,
+        port: None,
+    });
+    if let Command::Serve { address, port } = &command {
+        if let Some(address) = address {
+            config.server.address = address.clone();
+        }
+        if let Some(port) = port {
+            config.server.port = *port;
+        }
+    }
+
+    let app = repo::open_backend(&config.database).await?;
+    let demo: Box<dyn repo::Backend> = Box::new(LibsqlBackend::new(
+        db::get_demo_database(&config.database).await?,
+    ));
+    let command = match command {
+        Command::Migrate {
+            command: Some(command),
+        } => return cli::run_migrate(command, app.as_ref(), demo.as_ref()).await,
+        command => command,
+    };
+
+    cli::migrate(app.as_ref(), "app").await?;
+    repo::bootstrap_admin(app.connect().await?.as_ref(), &config.auth).await?;
+    cli::migrate(demo.as_ref(), "demo").await?;
+
+    let client = http::build_client(&config.http)?;
+    let feed_client = http::build_feed_client(&config.http)?;
+
+    let bind_address = (config.server.address.clone(), config.server.port);
 
-    let app_data = web::Data::new(AppState { app_db, demo_db });
+    let app_data = web::Data::new(AppState {
+        app,
+        demo,
+        client,
+        feed_client,
+        login_limiter: LoginLimiter::new(&config.auth),
+        db_sync: Mutex::default(),
+        config,
+    });
+
+    if !matches!(command, Command::Serve { .. }) {
+        return cli::run(command, app_data).await;
+    }
+
+    if db::database_mode(&app_data.config.database) == DatabaseMode::Replica {
+        let sync_data = app_data.clone();
+        let period = Duration::from_secs(sync_data.config.database.sync_interval_secs);
+        tokio::spawn(async move {
+            let mut interval = time::interval(period);
+            loop {
+                interval.tick().await;
+                sync_database(&sync_data).await;
+            }
+        });
+        info!("Started Database Sync");
+    }
 
     #[cfg(feature = "scheduler")]
     {
@@ -84,6 +149,32 @@ async fn main() -> anyhow::Result<()> {
             .await?;
         info!("Added Roadmap Schedule");
 
+        // Sessions
+        let tmp_data = app_data.clone();
+        scheduler
+            .add(Job::new_async("every 1 hour", move |_uuid, _l| {
+                let sched_data = web::Data::clone(&tmp_data);
+                Box::pin(async move {
+                    let our_data = web::Data::clone(&sched_data);
+                    prune_sessions(&our_data).await;
+                })
+            })?)
+            .await?;
+        info!("Added Session Pruning Schedule");
+
+        // Retention
+        let tmp_data = app_data.clone();
+        scheduler
+            .add(Job::new_async("every day at 3:00 am", move |_uuid, _l| {
+                let sched_data = web::Data::clone(&tmp_data);
+                Box::pin(async move {
+                    let our_data = web::Data::clone(&sched_data);
+                    prune_data(&our_data).await;
+                })
+            })?)
+            .await?;
+        info!("Added Data Pruning Schedule");
+
         scheduler.start().await?;
         info!("Scheduler Started");
     }
@@ -92,13 +183,19 @@ async fn main() -> anyhow::Result<()> {
         App::new()
             .wrap(Logger::default())
             .app_data(app_data.clone())
-            .app_data(basic::Config::default().realm("Restricted"))
+            .app_data(web::JsonConfig::default().error_handler(bad_request))
+            .app_data(web::PathConfig::default().error_handler(bad_request))
+            .app_data(web::QueryConfig::default().error_handler(bad_request))
+            .service(scope("/api/v1").configure(v1::configure))
             .service(
                 scope("/api")
-                    .service(get_sources)
+                    .wrap(DefaultHeaders::new().add(("Deprecation", "true")))
+                    .service(v1::sources::list_sources)
                     .service(get_activity)
                     .service(add_source)
+                    .service(preview_new_source)
                     .service(recheck)
+                    .service(recheck_source)
                     .service(login)
                     .service(check_logged_in)
                     .service(delete_source)
@@ -114,7 +211,19 @@ async fn main() -> anyhow::Result<()> {
                     .service(get_changes)
                     .service(enable_source)
                     .service(get_source_activity)
-                    .service(logout),
+                    .service(get_source_events)
+                    .service(accept_source_redirect)
+                    .service(v1::sessions::list_sessions)
+                    .service(v1::tokens::list_tokens)
+                    .service(v1::tokens::create_token)
+                    .service(delete_token)
+                    .service(logout_all_sessions)
+                    .service(logout)
+                    .service(v1::search::search)
+                    .service(v1::roadmap::get_card_history)
+                    .service(v1::roadmap::compare_roadmaps)
+                    .service(v1::backup::download_backup)
+                    .configure(openapi::configure),
             )
             .service(
                 spa()
@@ -124,7 +233,7 @@ async fn main() -> anyhow::Result<()> {
                     .finish(),
             )
     })
-    .bind(("0.0.0.0", 10000))?
+    .bind(bind_address)?
     .run()
     .await?;
 
@@ -0,0 +1,92 @@
+use actix_web::{HttpResponse, Responder, get, web};
+use utoipa::{
+    Modify, OpenApi,
+    openapi::security::{ApiKey, ApiKeyValue, HttpAuthScheme, HttpBuilder, SecurityScheme},
+};
+
+use crate::{
+    routes::v1::{self, activities, backup, roadmap, search, sessions, sources, tokens},
+    types::LOGGED_IN_COOKIE,
+};
+
+/// The OpenAPI document for every route under `/api/v1`
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Greg",
+        description = "Self hosted email notifier for RSS and Atom feeds. Errors are returned as `{ code, message }` and timestamps are RFC 2822 strings."
+    ),
+    servers((url = "/api/v1")),
+    paths(
+        v1::health,
+        v1::database_health,
+        sessions::create_session,
+        sessions::list_sessions,
+        sessions::delete_sessions,
+        sessions::get_current_session,
+        sessions::delete_current_session,
+        sessions::delete_session,
+        tokens::list_tokens,
+        tokens::create_token,
+        tokens::delete_token,
+        sources::list_sources,
+        sources::create_source,
+        sources::preview_source,
+        sources::check_sources,
+        sources::get_source,
+        sources::update_source,
+        sources::delete_source,
+        sources::check_source,
+        sources::accept_redirect,
+        sources::list_source_events,
+        sources::list_source_activities,
+        activities::list_activities,
+        activities::delete_activities,
+        roadmap::list_activities,
+        roadmap::list_changes,
+        roadmap::get_card_history,
+        roadmap::compare_roadmaps,
+        roadmap::list_tabs,
+        roadmap::check_roadmap,
+        roadmap::list_watched_tabs,
+        roadmap::create_watched_tab,
+        roadmap::delete_watched_tab,
+        search::search,
+        backup::download_backup,
+    ),
+    modifiers(&SecuritySchemes)
+)]
+pub struct ApiDoc;
+
+struct SecuritySchemes;
+
+impl Modify for SecuritySchemes {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_default();
+        components.add_security_scheme(
+            "session",
+            SecurityScheme::ApiKey(ApiKey::Cookie(ApiKeyValue::new(LOGGED_IN_COOKIE))),
+        );
+        components.add_security_scheme(
+            "token",
+            SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+        );
+    }
+}
+
+#[get("/openapi.json")]
+pub async fn openapi_json() -> impl Responder {
+    HttpResponse::Ok().json(ApiDoc::openapi())
+}
+
+/// Serves the OpenAPI document, and the docs UI at `/docs` with the `docs`
+/// feature. Part of the `/api/v1` scope, and still served under `/api`.
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(openapi_json);
+
+    #[cfg(feature = "docs")]
+    {
+        use utoipa_scalar::{Scalar, Servable};
+        cfg.service(Scalar::with_url("/docs", ApiDoc::openapi()).title("Greg API"));
+    }
+}
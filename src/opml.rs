@@ -0,0 +1,50 @@
+use quick_xml::{Reader, escape::escape, events::Event};
+
+use crate::types::Source;
+
+/// Writes sources as an OPML 2.0 subscription list
+pub fn export_sources(sources: &[Source]) -> String {
+    let mut opml = String::from(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<opml version="2.0">
+  <head>
+    <title>Greg Sources</title>
+  </head>
+  <body>
+"#,
+    );
+
+    for source in sources {
+        let url = escape(source.url.as_str());
+        opml.push_str(&format!(
+            "    <outline type=\"rss\" text=\"{url}\" xmlUrl=\"{url}\"/>\n"
+        ));
+    }
+
+    opml.push_str("  </body>\n</opml>\n");
+    opml
+}
+
+/// Reads every feed url from an OPML file, including ones nested in categories
+pub fn parse_feed_urls(content: &str) -> anyhow::Result<Vec<String>> {
+    let mut reader = Reader::from_str(content);
+    let mut urls = Vec::new();
+
+    loop {
+        match reader.read_event()? {
+            Event::Start(e) | Event::Empty(e) if e.name().as_ref() == b"outline" => {
+                if let Some(url) = e.try_get_attribute("xmlUrl")? {
+                    urls.push(
+                        url.decode_and_unescape_value(reader.decoder())?
+                            .trim()
+                            .to_owned(),
+                    );
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    Ok(urls)
+}
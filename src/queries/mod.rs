@@ -1,3 +1,45 @@
+use libsql::{Rows, de};
+use serde::de::DeserializeOwned;
+
+use crate::types::{Cursor, Page};
+
 pub mod mail;
+pub mod retention;
 pub mod roadmap;
+pub mod search;
+pub mod sessions;
 pub mod sources;
+pub mod tokens;
+pub mod users;
+
+/// Reads a page from rows ordered newest first. The rows have to start with
+/// the id, end with the sort key and be fetched with a limit of one more than
+/// `limit`, so we know whether there's a next page.
+pub(crate) async fn read_page<T: DeserializeOwned>(
+    mut rows: Rows,
+    limit: u32,
+    total: u64,
+) -> anyhow::Result<Page<T>> {
+    let mut items = Vec::new();
+    let mut last = None;
+    let mut next_cursor = None;
+
+    while let Some(row) = rows.next().await? {
+        if items.len() == limit as usize {
+            next_cursor = last;
+            break;
+        }
+
+        last = Some(Cursor {
+            key: row.get(row.column_count() - 1)?,
+            id: row.get(0)?,
+        });
+        items.push(de::from_row(&row)?);
+    }
+
+    Ok(Page {
+        items,
+        next_cursor,
+        total,
+    })
+}
@@ -0,0 +1,124 @@
+use libsql::{Connection, params};
+use time::OffsetDateTime;
+
+use crate::db::{
+    ACTIVITIES_T, LOGIN_ATTEMPTS_T, R_ACTIVITIES_T, R_CARD_ASSIGNS_T, R_CARDS_T, R_CHANGES_T,
+    R_TAB_ASSIGNS_T, R_TABS_T, sort_key,
+};
+
+fn time_key(time: OffsetDateTime) -> i64 {
+    time.unix_timestamp_nanos() as i64
+}
+
+pub async fn delete_activities_before(
+    db: &Connection,
+    cutoff: OffsetDateTime,
+) -> anyhow::Result<u64> {
+    let key = sort_key("timestamp");
+
+    Ok(db
+        .execute(
+            &format!("DELETE FROM {ACTIVITIES_T} WHERE {key} < ?1"),
+            [time_key(cutoff)],
+        )
+        .await?)
+}
+
+pub async fn delete_login_attempts_before(
+    db: &Connection,
+    cutoff: OffsetDateTime,
+) -> anyhow::Result<u64> {
+    let key = sort_key("timestamp");
+
+    Ok(db
+        .execute(
+            &format!("DELETE FROM {LOGIN_ATTEMPTS_T} WHERE {key} < ?1"),
+            [time_key(cutoff)],
+        )
+        .await?)
+}
+
+/// Deletes all but the newest `keep` activities of every source
+pub async fn delete_activities_over_limit(db: &Connection, keep: u32) -> anyhow::Result<u64> {
+    let key = sort_key("timestamp");
+
+    Ok(db
+        .execute(
+            &format!(
+                "DELETE FROM {ACTIVITIES_T}
+                WHERE id IN (
+                    SELECT id FROM (
+                        SELECT
+                            id,
+                            ROW_NUMBER() OVER (PARTITION BY source_id ORDER BY {key} DESC, id DESC) AS n
+                        FROM {ACTIVITIES_T}
+                    )
+                    WHERE n > ?1
+                )"
+            ),
+            [keep],
+        )
+        .await?)
+}
+
+/// Deletes roadmap checks from before the cutoff along with their
+/// assignments and changes. The most recent check is always kept since new
+/// checks are compared against it.
+pub async fn delete_roadmap_activities_before(
+    db: &Connection,
+    cutoff: OffsetDateTime,
+    only_unchanged: bool,
+) -> anyhow::Result<u64> {
+    let key = sort_key("ra.timestamp");
+
+    Ok(db
+        .execute(
+            &format!(
+                "DELETE FROM {R_ACTIVITIES_T}
+                WHERE id IN (
+                    SELECT ra.id FROM {R_ACTIVITIES_T} AS ra
+                    WHERE {key} < ?1
+                        AND ra.id != (SELECT MAX(id) FROM {R_ACTIVITIES_T})
+                        AND (?2 = 0 OR NOT EXISTS (
+                            SELECT 1 FROM {R_CHANGES_T} AS rch WHERE rch.activity_id = ra.id
+                        ))
+                )"
+            ),
+            (time_key(cutoff), only_unchanged),
+        )
+        .await?)
+}
+
+/// Deletes cards no check or change points at anymore
+pub async fn delete_unused_cards(db: &Connection) -> anyhow::Result<u64> {
+    Ok(db
+        .execute(
+            &format!(
+                "DELETE FROM {R_CARDS_T}
+                WHERE id NOT IN (SELECT card_id FROM {R_CARD_ASSIGNS_T})
+                    AND id NOT IN (
+                        SELECT previous_card_id FROM {R_CHANGES_T} WHERE previous_card_id IS NOT NULL
+                    )
+                    AND id NOT IN (
+                        SELECT current_card_id FROM {R_CHANGES_T} WHERE current_card_id IS NOT NULL
+                    )"
+            ),
+            params!(),
+        )
+        .await?)
+}
+
+/// Deletes tabs no check or change points at anymore
+pub async fn delete_unused_tabs(db: &Connection) -> anyhow::Result<u64> {
+    Ok(db
+        .execute(
+            &format!(
+                "DELETE FROM {R_TABS_T}
+                WHERE id NOT IN (SELECT tab_id FROM {R_TAB_ASSIGNS_T})
+                    AND id NOT IN (SELECT tab_id FROM {R_CARD_ASSIGNS_T})
+                    AND id NOT IN (SELECT tab_id FROM {R_CHANGES_T} WHERE tab_id IS NOT NULL)"
+            ),
+            params!(),
+        )
+        .await?)
+}
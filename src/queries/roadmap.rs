@@ -1,13 +1,19 @@
 use std::collections::HashMap;
 
 use libsql::{Connection, de, params};
+use time::OffsetDateTime;
 
 use crate::{
     db::{
         R_ACTIVITIES_T, R_CARD_ASSIGNS_T, R_CARDS_T, R_CHANGES_T, R_TAB_ASSIGNS_T, R_TABS_T,
-        R_WATCHED_TABS_T,
+        R_WATCHED_TABS_T, sort_key,
     },
-    types::{RCard, RDBChangeAlt, RTab, Roadmap, RoadmapActivity, RoadmapWatchedTab},
+    queries::read_page,
+    types::{
+        Cursor, Page, RCard, RCardChange, RCardHistory, RCardRevision, RDBChangeAlt, RTab, Roadmap,
+        RoadmapActivity, RoadmapWatchedTab, StringError,
+    },
+    utils::hash_card,
 };
 
 pub async fn get_most_recent_roadmap(db: Connection) -> anyhow::Result<Option<Roadmap>> {
@@ -23,8 +29,13 @@ pub async fn get_most_recent_roadmap(db: Connection) -> anyhow::Result<Option<Ro
 
     let activity: RoadmapActivity = de::from_row(&r)?;
 
+    get_roadmap(db, activity.id).await.map(Some)
+}
+
+/// The roadmap as it was saved by a check, with cards sorted by roadmap id
+pub async fn get_roadmap(db: Connection, activity_id: u32) -> anyhow::Result<Roadmap> {
     // Get Tabs
-    let tabs = get_roadmap_tabs(db.clone(), activity.id).await?;
+    let tabs = get_roadmap_tabs(db.clone(), activity_id).await?;
 
     // Get Cards
     let mut result = db
@@ -48,7 +59,7 @@ pub async fn get_most_recent_roadmap(db: Connection) -> anyhow::Result<Option<Ro
                 WHERE ra.activity_id = ?1
                 "
             ),
-            [activity.id],
+            [activity_id],
         )
         .await?;
 
@@ -71,12 +82,29 @@ pub async fn get_most_recent_roadmap(db: Connection) -> anyhow::Result<Option<Ro
         .values_mut()
         .for_each(|c| c.sort_by_key(|c| c.id.clone()));
 
-    Ok(Some(Roadmap::with_data(tabs, cards)))
+    Ok(Roadmap::with_data(tabs, cards))
+}
+
+pub async fn roadmap_activity_exists(db: Connection, activity_id: u32) -> anyhow::Result<bool> {
+    let mut result = db
+        .query(
+            &format!("SELECT 1 FROM {R_ACTIVITIES_T} WHERE id = ?1"),
+            [activity_id],
+        )
+        .await?;
+
+    Ok(result.next().await?.is_some())
 }
 
-pub async fn get_watched_tabs(db: Connection) -> anyhow::Result<Vec<RoadmapWatchedTab>> {
+pub async fn get_watched_tabs(
+    db: Connection,
+    user_id: u32,
+) -> anyhow::Result<Vec<RoadmapWatchedTab>> {
     let mut result = db
-        .query(&format!("SELECT * FROM {R_WATCHED_TABS_T}"), params!())
+        .query(
+            &format!("SELECT * FROM {R_WATCHED_TABS_T} WHERE user_id = ?1"),
+            [user_id],
+        )
         .await?;
 
     let mut tabs = Vec::new();
@@ -88,45 +116,109 @@ pub async fn get_watched_tabs(db: Connection) -> anyhow::Result<Vec<RoadmapWatch
     Ok(tabs)
 }
 
+/// Returns the id of the new watched tab
+pub async fn add_watched_tab(db: Connection, user_id: u32, tab_id: &str) -> anyhow::Result<u32> {
+    let mut result = db
+        .query(
+            &format!(
+                "INSERT INTO {R_WATCHED_TABS_T} (user_id, tab_roadmap_id, timestamp) VALUES (?1, ?2, ?3) RETURNING id"
+            ),
+            (
+                user_id,
+                tab_id,
+                serde_json::to_string(&OffsetDateTime::now_utc()).unwrap(),
+            ),
+        )
+        .await?;
+
+    let Some(row) = result.next().await? else {
+        return Err(StringError("Insert didn't return an id".into()).into());
+    };
+
+    Ok(row.get(0)?)
+}
+
+pub async fn delete_watched_tab(db: Connection, user_id: u32, id: u32) -> anyhow::Result<u64> {
+    Ok(db
+        .execute(
+            &format!("DELETE FROM {R_WATCHED_TABS_T} WHERE id = ?1 AND user_id = ?2"),
+            [id, user_id],
+        )
+        .await?)
+}
+
+/// Tabs watched by anyone, each once
+pub async fn get_all_watched_tab_ids(db: Connection) -> anyhow::Result<Vec<String>> {
+    let mut result = db
+        .query(
+            &format!("SELECT DISTINCT tab_roadmap_id FROM {R_WATCHED_TABS_T}"),
+            params!(),
+        )
+        .await?;
+
+    let mut tab_ids = Vec::new();
+    while let Some(r) = result.next().await? {
+        tab_ids.push(r.get(0)?);
+    }
+
+    Ok(tab_ids)
+}
+
+/// Roadmap checks, counting only the changes on tabs the user watches
 pub async fn get_roadmap_activities(
     db: Connection,
+    user_id: u32,
     limit: u32,
-    skip: u32,
-) -> anyhow::Result<Vec<RoadmapActivity>> {
-    let mut result = db
+    cursor: Option<Cursor>,
+) -> anyhow::Result<Page<RoadmapActivity>> {
+    let limit = limit.max(1);
+    let cursor = cursor.unwrap_or(Cursor::START);
+    let key = sort_key("ra.timestamp");
+    let watched = watched_change_sql("?4");
+
+    let total: u64 = db
+        .query(&format!("SELECT COUNT(*) FROM {R_ACTIVITIES_T}"), params!())
+        .await?
+        .next()
+        .await?
+        .ok_or(StringError("Count didn't return a row".into()))?
+        .get(0)?;
+
+    let rows = db
         .query(
             &format!(
                 "SELECT 
                     ra.id,
                     ra.timestamp,
-                    rch.count as change_count
+                    rch.count as change_count,
+                    {key} AS sort_key
                 FROM {R_ACTIVITIES_T} as ra
                 LEFT JOIN (
-                    SELECT inrch.activity_id, COUNT(inrch.id) as count FROM {R_CHANGES_T} AS inrch
+                    SELECT rch.activity_id, COUNT(rch.id) as count FROM {R_CHANGES_T} AS rch
+                    LEFT JOIN {R_TABS_T} AS rt
+                        ON rch.tab_id = rt.id
                     WHERE
-                        inrch.type = 'tab_removed'
-                        OR inrch.type = 'tab_added'
-                        OR inrch.type = 'card_removed'
-                        OR inrch.type = 'card_added'
-                        OR inrch.type = 'card_modified'
-                    GROUP BY inrch.activity_id
+                        (
+                            rch.type = 'tab_removed'
+                            OR rch.type = 'tab_added'
+                            OR rch.type = 'card_removed'
+                            OR rch.type = 'card_added'
+                            OR rch.type = 'card_modified'
+                        )
+                        AND {watched}
+                    GROUP BY rch.activity_id
                 ) rch
                     ON ra.id = rch.activity_id
-                ORDER BY ra.id DESC
-                LIMIT ?1 OFFSET ?2
+                WHERE ({key}, ra.id) < (?1, ?2)
+                ORDER BY sort_key DESC, ra.id DESC
+                LIMIT ?3
                 "
             ),
-            [limit, skip],
+            (cursor.key, cursor.id, limit + 1, user_id),
         )
         .await?;
 
-    let mut activities = Vec::new();
-    while let Some(row) = result.next().await? {
-        let activity: RoadmapActivity = de::from_row(&row)?;
-        activities.push(activity);
-    }
-
-    Ok(activities)
+    read_page(rows, limit, total).await
 }
 
 pub async fn get_roadmap_tabs(db: Connection, activity_id: u32) -> anyhow::Result<Vec<RTab>> {
@@ -173,52 +265,401 @@ pub async fn get_most_recent_roadmap_tabs(db: Connection) -> anyhow::Result<Vec<
     get_roadmap_tabs(db.clone(), activity.id).await
 }
 
+/// A column of the tab the card of change `rch` was on. That's its assignment
+/// in the change's check, or the one before it for removed cards.
+fn card_tab_sql(column: &str) -> String {
+    format!(
+        "(
+            SELECT rct.{column}
+            FROM {R_CARD_ASSIGNS_T} AS rca
+            INNER JOIN {R_TABS_T} AS rct
+                ON rca.tab_id = rct.id
+            WHERE rca.card_id = COALESCE(rch.current_card_id, rch.previous_card_id)
+                AND rca.activity_id <= rch.activity_id
+            ORDER BY rca.activity_id DESC
+            LIMIT 1
+        )"
+    )
+}
+
+/// Roadmap ids of the tabs a user watches
+fn watched_tabs_sql(user_param: &str) -> String {
+    format!("SELECT tab_roadmap_id FROM {R_WATCHED_TABS_T} WHERE user_id = {user_param}")
+}
+
+/// Whether the user watches the tab change `rch` is about, `rt` being the tab
+/// of tab changes
+fn watched_change_sql(user_param: &str) -> String {
+    format!(
+        "COALESCE(rt.roadmap_id, {}) IN ({})",
+        card_tab_sql("roadmap_id"),
+        watched_tabs_sql(user_param)
+    )
+}
+
+/// Selects changes with the cards and tabs they point at, starting with the
+/// activity id and timestamp of each change
+fn changes_sql(filter: &str) -> String {
+    let card_tab_name = card_tab_sql("name");
+    format!(
+        "SELECT
+            rch.activity_id, rch.timestamp,
+            rch.id, rch.type,
+
+            rc1.id AS previous_card_db_id, rc1.roadmap_id AS previous_card_id,
+            rc1.name AS previous_card_name, rc1.description AS previous_card_description,
+            rc1.image_url  AS previous_card_image_url, rc1.slug AS previous_card_slug,
+
+            rc2.id AS current_card_db_id, rc2.roadmap_id AS current_card_id,
+            rc2.name AS current_card_name, rc2.description AS current_card_description,
+            rc2.image_url  AS current_card_image_url, rc2.slug AS current_card_slug,
+
+            rt.id AS tab_db_id, rt.roadmap_id AS tab_id,
+            rt.name AS tab_name, rt.slug AS tab_slug,
+
+            {card_tab_name} AS card_tab_name
+        FROM {R_CHANGES_T} AS rch
+        LEFT JOIN {R_CARDS_T} as rc1
+            ON rch.previous_card_id = rc1.id
+        LEFT JOIN {R_CARDS_T} as rc2
+            ON rch.current_card_id = rc2.id
+        LEFT JOIN {R_TABS_T} as rt
+            ON rch.tab_id = rt.id
+        WHERE {filter}
+        ORDER BY rch.id
+        "
+    )
+}
+
+/// Changes found by a check on the tabs the user watches
 pub async fn get_roadmap_changes(
     db: Connection,
+    user_id: u32,
     activity_id: u32,
 ) -> anyhow::Result<Vec<RDBChangeAlt>> {
+    let filter = format!("rch.activity_id = ?1 AND {}", watched_change_sql("?2"));
+    let mut result = db
+        .query(&changes_sql(&filter), [activity_id, user_id])
+        .await?;
+
+    let mut changes = Vec::new();
+    while let Some(r) = result.next().await? {
+        let c = de::from_row(&r)?;
+        changes.push(c);
+    }
+
+    Ok(changes)
+}
+
+/// The card as it was on the tabs the user watches. None if it has never
+/// been on one of them.
+pub async fn get_card_history(
+    db: Connection,
+    user_id: u32,
+    card_id: &str,
+) -> anyhow::Result<Option<RCardHistory>> {
+    let watched = watched_tabs_sql("?2");
+    let mut result = db
+        .query(
+            &format!(
+                "WITH revisions AS (
+                    SELECT
+                        rc.id,
+                        MIN(rca.activity_id) AS first_activity_id,
+                        MAX(rca.activity_id) AS last_activity_id
+                    FROM {R_CARDS_T} AS rc
+                    INNER JOIN {R_CARD_ASSIGNS_T} AS rca
+                        ON rca.card_id = rc.id
+                    INNER JOIN {R_TABS_T} AS rt
+                        ON rt.id = rca.tab_id
+                    WHERE rc.roadmap_id = ?1
+                        AND rt.roadmap_id IN ({watched})
+                    GROUP BY rc.id
+                )
+                SELECT
+                    rc.id AS db_id,
+                    rc.name,
+                    rc.description,
+                    rc.image_url,
+                    rc.slug,
+                    r.first_activity_id,
+                    fa.timestamp AS first_seen,
+                    r.last_activity_id,
+                    la.timestamp AS last_seen
+                FROM revisions AS r
+                INNER JOIN {R_CARDS_T} AS rc
+                    ON rc.id = r.id
+                INNER JOIN {R_ACTIVITIES_T} AS fa
+                    ON fa.id = r.first_activity_id
+                INNER JOIN {R_ACTIVITIES_T} AS la
+                    ON la.id = r.last_activity_id
+                ORDER BY r.first_activity_id, rc.id
+                "
+            ),
+            params![card_id, user_id],
+        )
+        .await?;
+
+    let mut revisions = Vec::new();
+    while let Some(r) = result.next().await? {
+        revisions.push(de::from_row::<RCardRevision>(&r)?);
+    }
+
+    // Tabs that were pruned and came back have a new row, so they're told
+    // apart by roadmap id. The name is looked up from the last check.
     let mut result = db
         .query(
             &format!(
                 "SELECT
-                    rch.id, rch.type,
-
-                    rc1.id AS previous_card_db_id, rc1.roadmap_id AS previous_card_id,
-                    rc1.name AS previous_card_name, rc1.description AS previous_card_description,
-                    rc1.image_url  AS previous_card_image_url, rc1.slug AS previous_card_slug,
-
-                    rc2.id AS current_card_db_id, rc2.roadmap_id AS current_card_id,
-                    rc2.name AS current_card_name, rc2.description AS current_card_description,
-                    rc2.image_url  AS current_card_image_url, rc2.slug AS current_card_slug,
-
-                    rt.id AS tab_db_id, rt.roadmap_id AS tab_id,
-                    rt.name AS tab_name, rt.slug AS tab_slug,
-
-                    rct.name AS card_tab_name
-                FROM {R_CHANGES_T} AS rch
-                LEFT JOIN {R_CARDS_T} as rc1
-                    ON rch.previous_card_id = rc1.id
-                LEFT JOIN {R_CARDS_T} as rc2
-                    ON rch.current_card_id = rc2.id
-                LEFT JOIN {R_CARD_ASSIGNS_T} as rca
-                    ON rch.previous_card_id = rca.card_id OR rch.current_card_id = rca.card_id
-                LEFT JOIN {R_TABS_T} as rct
-                    ON rca.tab_id = rct.id
-                LEFT JOIN {R_TABS_T} as rt
-                    ON rch.tab_id = rt.id
-                WHERE rch.activity_id = ?1
-                GROUP BY rch.id
+                    p.card_id,
+                    p.tab_id,
+                    (
+                        SELECT rt.name
+                        FROM {R_CARD_ASSIGNS_T} AS rca
+                        INNER JOIN {R_TABS_T} AS rt
+                            ON rt.id = rca.tab_id
+                        WHERE rca.card_id = p.card_id
+                            AND rca.activity_id = p.last_activity_id
+                            AND rca.section_position = p.section_position
+                            AND rt.roadmap_id = p.tab_id
+                        LIMIT 1
+                    ) AS tab_name,
+                    p.section_position,
+                    p.first_activity_id,
+                    p.last_activity_id
+                FROM (
+                    SELECT
+                        rca.card_id,
+                        rt.roadmap_id AS tab_id,
+                        rca.section_position,
+                        MIN(rca.activity_id) AS first_activity_id,
+                        MAX(rca.activity_id) AS last_activity_id
+                    FROM {R_CARD_ASSIGNS_T} AS rca
+                    INNER JOIN {R_CARDS_T} AS rc
+                        ON rc.id = rca.card_id
+                    INNER JOIN {R_TABS_T} AS rt
+                        ON rt.id = rca.tab_id
+                    WHERE rc.roadmap_id = ?1
+                        AND rt.roadmap_id IN ({watched})
+                    GROUP BY rca.card_id, rt.roadmap_id, rca.section_position
+                ) AS p
+                ORDER BY p.first_activity_id
                 "
             ),
-            [activity_id],
+            params![card_id, user_id],
         )
         .await?;
 
+    while let Some(r) = result.next().await? {
+        let revision_id: u32 = r.get(0)?;
+        if let Some(revision) = revisions.iter_mut().find(|rev| rev.db_id == revision_id) {
+            revision.placements.push(de::from_row(&r)?);
+        }
+    }
+
+    let filter = format!(
+        "(rc1.roadmap_id = ?1 OR rc2.roadmap_id = ?1) AND {}",
+        watched_change_sql("?2")
+    );
+    let mut result = db
+        .query(&changes_sql(&filter), params![card_id, user_id])
+        .await?;
+
     let mut changes = Vec::new();
     while let Some(r) = result.next().await? {
-        let c = de::from_row(&r)?;
-        changes.push(c);
+        changes.push(RCardChange {
+            activity_id: r.get(0)?,
+            timestamp: serde_json::from_str(&r.get::<String>(1)?)?,
+            change: de::from_row(&r)?,
+        });
     }
 
-    Ok(changes)
+    if revisions.is_empty() && changes.is_empty() {
+        return Ok(None);
+    }
+
+    Ok(Some(RCardHistory {
+        card_id: card_id.to_owned(),
+        revisions,
+        changes,
+    }))
+}
+
+/// Saves a card unless an identical one already is, either way returning its id
+pub async fn save_card(db: &Connection, card: &RCard) -> anyhow::Result<u32> {
+    let content_hash = hash_card(
+        &card.id,
+        &card.name,
+        &card.description,
+        card.image_url.as_deref(),
+        &card.slug,
+    );
+
+    let mut existing = db
+        .query(
+            &format!("SELECT id FROM {R_CARDS_T} WHERE content_hash = ?1"),
+            [content_hash.as_str()],
+        )
+        .await?;
+    if let Some(r) = existing.next().await? {
+        return Ok(r.get(0)?);
+    }
+
+    let mut result = db
+        .query(
+            &format!(
+                "INSERT INTO {R_CARDS_T} 
+                    (roadmap_id, name, description, image_url, slug, timestamp, content_hash)
+                VALUES 
+                    (?1,?2,?3,?4,?5,?6,?7)
+                RETURNING id
+                "
+            ),
+            (
+                card.id.as_str(),
+                card.name.as_str(),
+                card.description.as_str(),
+                card.image_url.as_deref(),
+                card.slug.as_str(),
+                serde_json::to_string(&OffsetDateTime::now_utc()).unwrap(),
+                content_hash,
+            ),
+        )
+        .await?;
+
+    let Some(r) = result.next().await? else {
+        return Err(StringError("Insert didn't return an id".into()).into());
+    };
+
+    Ok(r.get(0)?)
+}
+
+pub async fn save_tab(db: &Connection, tab: &RTab) -> anyhow::Result<u32> {
+    let mut result = db
+        .query(
+            &format!(
+                "INSERT INTO {R_TABS_T} 
+                    (roadmap_id, name, slug, timestamp) 
+                VALUES 
+                    (?1,?2,?3,?4)
+                RETURNING id
+                "
+            ),
+            (
+                tab.id.as_str(),
+                tab.name.as_str(),
+                tab.slug.as_str(),
+                serde_json::to_string(&OffsetDateTime::now_utc()).unwrap(),
+            ),
+        )
+        .await?;
+
+    let Some(r) = result.next().await? else {
+        return Err(StringError("Insert didn't return an id".into()).into());
+    };
+
+    Ok(r.get(0)?)
+}
+
+pub async fn new_roadmap(db: &Connection) -> anyhow::Result<u32> {
+    let mut result = db
+        .query(
+            &format!(
+                "INSERT INTO {R_ACTIVITIES_T} 
+                    (timestamp) 
+                VALUES
+                    (?1)
+                RETURNING id
+                "
+            ),
+            [serde_json::to_string(&OffsetDateTime::now_utc()).unwrap()],
+        )
+        .await?;
+
+    let Some(r) = result.next().await? else {
+        return Err(StringError("Insert didn't return an id".into()).into());
+    };
+
+    Ok(r.get(0)?)
+}
+
+/// * `assign_info` - activity, tab, section_pos, card_pos
+pub async fn save_card_assignment(
+    db: &Connection,
+    card_id: u32,
+    assign_info: &[u32; 4],
+) -> anyhow::Result<()> {
+    db.execute(
+        &format!(
+            "INSERT INTO {R_CARD_ASSIGNS_T} 
+                    (activity_id, tab_id, card_id, section_position, card_position, timestamp) 
+                VALUES 
+                    (?1,?2,?3,?4,?5,?6)
+                "
+        ),
+        (
+            assign_info[0],
+            assign_info[1],
+            card_id,
+            assign_info[2],
+            assign_info[3],
+            serde_json::to_string(&OffsetDateTime::now_utc()).unwrap(),
+        ),
+    )
+    .await?;
+
+    Ok(())
+}
+
+pub async fn save_tab_assignment(
+    db: &Connection,
+    activity_id: u32,
+    tab_db_id: u32,
+) -> anyhow::Result<()> {
+    db.execute(
+        &format!(
+            "INSERT INTO {R_TAB_ASSIGNS_T} 
+                    (activity_id, tab_id, timestamp) 
+                VALUES 
+                    (?1,?2,?3)
+                "
+        ),
+        (
+            activity_id,
+            tab_db_id,
+            serde_json::to_string(&OffsetDateTime::now_utc()).unwrap(),
+        ),
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// * `change_info` - previous_card, current_card, tab
+pub async fn save_change(
+    db: &Connection,
+    change_type: &str,
+    activity: u32,
+    change_info: &[Option<u32>; 3],
+) -> anyhow::Result<()> {
+    db.execute(
+        &format!(
+            "INSERT INTO {R_CHANGES_T} 
+                    (type, activity_id, previous_card_id, current_card_id, tab_id, timestamp) 
+                VALUES 
+                    (?1,?2,?3,?4,?5,?6)
+                "
+        ),
+        (
+            change_type,
+            activity,
+            change_info[0],
+            change_info[1],
+            change_info[2],
+            serde_json::to_string(&OffsetDateTime::now_utc()).unwrap(),
+        ),
+    )
+    .await?;
+
+    Ok(())
 }
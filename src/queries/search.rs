@@ -0,0 +1,183 @@
+use libsql::{Connection, Row};
+use time::OffsetDateTime;
+
+use crate::{
+    db::{
+        ACTIVITIES_FTS_T, ACTIVITIES_T, R_CARD_ASSIGNS_T, R_CARDS_FTS_T, R_CARDS_T, R_TABS_T,
+        R_WATCHED_TABS_T, SOURCES_T, sort_key,
+    },
+    repo::SearchFilters,
+    types::{Highlight, SearchHit, SearchKind},
+};
+
+/// Marks the start and end of matched terms in snippets
+pub(crate) const HIGHLIGHT_START: char = '\u{2}';
+pub(crate) const HIGHLIGHT_END: char = '\u{3}';
+pub(crate) const SNIPPET_TOKENS: u32 = 24;
+
+/// Turns what the user typed into an fts5 query, so quotes and operators are
+/// searched for instead of breaking the query. Every word has to match and
+/// the last one can be the start of a word. None if there's nothing to search.
+pub fn match_query(q: &str) -> Option<String> {
+    let terms = q
+        .split_whitespace()
+        .map(|term| format!("\"{}\"", term.replace('"', "\"\"")))
+        .collect::<Vec<_>>();
+
+    (!terms.is_empty()).then(|| terms.join(" ") + "*")
+}
+
+/// Takes the markers out of a snippet, keeping where they were
+pub(crate) fn parse_snippet(marked: &str) -> (String, Vec<Highlight>) {
+    let mut snippet = String::with_capacity(marked.len());
+    let mut highlights = Vec::new();
+    let mut start = None;
+    let mut len = 0;
+
+    for c in marked.chars() {
+        match c {
+            HIGHLIGHT_START => start = Some(len),
+            HIGHLIGHT_END => {
+                if let Some(start) = start.take() {
+                    highlights.push(Highlight { start, end: len });
+                }
+            }
+            _ => {
+                snippet.push(c);
+                len += 1;
+            }
+        }
+    }
+
+    (snippet, highlights)
+}
+
+fn time_key(time: Option<OffsetDateTime>, default: i64) -> i64 {
+    time.map_or(default, |time| time.unix_timestamp_nanos() as i64)
+}
+
+/// Rows have to be id, title, url, source id, card id, timestamp, marked
+/// snippet and rank
+fn read_hit(row: &Row, kind: SearchKind) -> anyhow::Result<SearchHit> {
+    let (snippet, highlights) = parse_snippet(&row.get::<String>(6)?);
+
+    Ok(SearchHit {
+        kind,
+        id: row.get(0)?,
+        title: row.get(1)?,
+        url: row.get(2)?,
+        source_id: row.get(3)?,
+        card_id: row.get(4)?,
+        timestamp: serde_json::from_str(&row.get::<String>(5)?)?,
+        snippet,
+        highlights,
+        rank: row.get(7)?,
+    })
+}
+
+/// The user's activities matching an fts5 query from [`match_query`], best
+/// first
+pub async fn search_activities(
+    db: Connection,
+    user_id: u32,
+    query: &str,
+    filters: &SearchFilters,
+    limit: u32,
+) -> anyhow::Result<Vec<SearchHit>> {
+    let from = time_key(filters.from, i64::MIN);
+    let to = time_key(filters.to, i64::MAX);
+    let key = sort_key("a.timestamp");
+
+    let mut hits = Vec::new();
+    let mut rows = db
+        .query(
+            &format!(
+                "SELECT
+                    a.id,
+                    COALESCE(a.title, a.post_url),
+                    a.post_url,
+                    a.source_id,
+                    NULL,
+                    a.timestamp,
+                    snippet({ACTIVITIES_FTS_T}, -1, char(2), char(3), '…', {SNIPPET_TOKENS}),
+                    bm25({ACTIVITIES_FTS_T}) AS rank
+                FROM {ACTIVITIES_FTS_T}
+                INNER JOIN {ACTIVITIES_T} AS a
+                    ON a.id = {ACTIVITIES_FTS_T}.rowid
+                INNER JOIN {SOURCES_T} AS s
+                    ON a.source_id = s.id
+                WHERE {ACTIVITIES_FTS_T} MATCH ?1
+                    AND s.user_id = ?2
+                    AND (?3 IS NULL OR a.source_id = ?3)
+                    AND {key} >= ?4 AND {key} < ?5
+                ORDER BY rank
+                LIMIT ?6
+                "
+            ),
+            (query, user_id, filters.source_id, from, to, limit),
+        )
+        .await?;
+
+    while let Some(row) = rows.next().await? {
+        hits.push(read_hit(&row, SearchKind::Activity)?);
+    }
+
+    Ok(hits)
+}
+
+/// Roadmap cards on tabs the user watches matching an fts5 query from
+/// [`match_query`], best first
+pub async fn search_cards(
+    db: Connection,
+    user_id: u32,
+    query: &str,
+    filters: &SearchFilters,
+    limit: u32,
+) -> anyhow::Result<Vec<SearchHit>> {
+    let from = time_key(filters.from, i64::MIN);
+    let to = time_key(filters.to, i64::MAX);
+    let key = sort_key("rc.timestamp");
+
+    let mut hits = Vec::new();
+    let mut rows = db
+        .query(
+            &format!(
+                "SELECT
+                    rc.id,
+                    rc.name,
+                    NULL,
+                    NULL,
+                    rc.roadmap_id,
+                    rc.timestamp,
+                    snippet({R_CARDS_FTS_T}, -1, char(2), char(3), '…', {SNIPPET_TOKENS}),
+                    bm25({R_CARDS_FTS_T}) AS rank
+                FROM {R_CARDS_FTS_T}
+                INNER JOIN {R_CARDS_T} AS rc
+                    ON rc.id = {R_CARDS_FTS_T}.rowid
+                WHERE {R_CARDS_FTS_T} MATCH ?1
+                    AND EXISTS (
+                        SELECT 1
+                        FROM {R_CARD_ASSIGNS_T} AS ca
+                        INNER JOIN {R_TABS_T} AS t
+                            ON ca.tab_id = t.id
+                        INNER JOIN {R_WATCHED_TABS_T} AS wt
+                            ON wt.tab_roadmap_id = t.roadmap_id
+                        WHERE ca.card_id = rc.id
+                            AND wt.user_id = ?6
+                            AND (?2 IS NULL OR t.roadmap_id = ?2)
+                    )
+                    AND {key} >= ?3 AND {key} < ?4
+                ORDER BY rank
+                LIMIT ?5
+                "
+            ),
+            (query, filters.tab_id.as_deref(), from, to, limit, user_id),
+        )
+        .await?;
+
+    while let Some(row) = rows.next().await? {
+        hits.push(read_hit(&row, SearchKind::Card)?);
+    }
+
+    Ok(hits)
+}
@@ -0,0 +1,185 @@
+use libsql::{Connection, de, params};
+use time::{Duration, OffsetDateTime};
+
+use crate::{
+    db::{LOGIN_ATTEMPTS_T, LOGINS_T, USERS_T},
+    repo::SessionOwner,
+    types::{Session, StringError},
+    utils::hash_key,
+};
+
+/// Stores a new session and returns its id. Only a hash of the key is kept
+pub async fn add_session(
+    db: Connection,
+    user_id: u32,
+    key: &str,
+    user_agent: Option<&str>,
+) -> anyhow::Result<u32> {
+    let now = serde_json::to_string(&OffsetDateTime::now_utc()).unwrap();
+
+    let mut result = db
+        .query(
+            &format!(
+                "INSERT INTO {LOGINS_T} (timestamp, last_seen, key, user_id, user_agent) VALUES (?1, ?1, ?2, ?3, ?4) RETURNING id"
+            ),
+            (now, hash_key(key), user_id, user_agent),
+        )
+        .await?;
+
+    let Some(row) = result.next().await? else {
+        return Err(StringError("Insert didn't return an id".into()).into());
+    };
+
+    Ok(row.get(0)?)
+}
+
+pub async fn get_session_owner(db: Connection, key: &str) -> anyhow::Result<Option<SessionOwner>> {
+    let mut result = db
+        .query(
+            &format!(
+                "SELECT l.last_seen, u.id, u.is_admin, l.id
+                FROM {LOGINS_T} AS l
+                INNER JOIN {USERS_T} AS u
+                    ON l.user_id = u.id
+                WHERE l.key = ?1
+                LIMIT 1"
+            ),
+            [hash_key(key)],
+        )
+        .await?;
+
+    let Some(row) = result.next().await? else {
+        return Ok(None);
+    };
+
+    let last_seen = row.get_str(0).unwrap_or_default();
+    let last_seen = serde_json::from_str(last_seen).map_err(|err| {
+        StringError(format!(
+            "Timestamp: {last_seen} couldn't be parsed with err: {err}"
+        ))
+    })?;
+
+    Ok(Some(SessionOwner {
+        session_id: row.get(3)?,
+        user_id: row.get(1)?,
+        is_admin: row.get(2)?,
+        last_seen,
+    }))
+}
+
+/// Moves the session's expiry forward
+pub async fn touch_session(db: Connection, session_id: u32) -> anyhow::Result<u64> {
+    Ok(db
+        .execute(
+            &format!("UPDATE {LOGINS_T} SET last_seen = ?1 WHERE id = ?2"),
+            (
+                serde_json::to_string(&OffsetDateTime::now_utc()).unwrap(),
+                session_id,
+            ),
+        )
+        .await?)
+}
+
+pub async fn get_sessions(db: Connection, user_id: u32) -> anyhow::Result<Vec<Session>> {
+    let mut result = db
+        .query(
+            &format!("SELECT * FROM {LOGINS_T} WHERE user_id = ?1 ORDER BY id DESC"),
+            [user_id],
+        )
+        .await?;
+
+    let mut sessions = Vec::new();
+    while let Some(row) = result.next().await? {
+        let session: Session = de::from_row(&row)?;
+        sessions.push(session);
+    }
+
+    Ok(sessions)
+}
+
+pub async fn delete_session(db: Connection, key: &str) -> anyhow::Result<u64> {
+    Ok(db
+        .execute(
+            &format!("DELETE FROM {LOGINS_T} WHERE key = ?1"),
+            [hash_key(key)],
+        )
+        .await?)
+}
+
+pub async fn delete_session_by_id(db: Connection, user_id: u32, id: u32) -> anyhow::Result<u64> {
+    Ok(db
+        .execute(
+            &format!("DELETE FROM {LOGINS_T} WHERE id = ?1 AND user_id = ?2"),
+            [id, user_id],
+        )
+        .await?)
+}
+
+pub async fn delete_user_sessions(db: Connection, user_id: u32) -> anyhow::Result<u64> {
+    Ok(db
+        .execute(
+            &format!("DELETE FROM {LOGINS_T} WHERE user_id = ?1"),
+            [user_id],
+        )
+        .await?)
+}
+
+pub fn is_session_expired(last_seen: OffsetDateTime, lifetime_mins: i64) -> bool {
+    OffsetDateTime::now_utc() - last_seen >= Duration::minutes(lifetime_mins)
+}
+
+/// Deletes sessions that have expired, along with any that can't be read
+pub async fn prune_sessions(db: Connection, lifetime_mins: i64) -> anyhow::Result<u64> {
+    let mut result = db
+        .query(&format!("SELECT id, last_seen FROM {LOGINS_T}"), params!())
+        .await?;
+
+    let mut expired = Vec::new();
+    while let Some(row) = result.next().await? {
+        let id: u32 = row.get(0)?;
+        let keep = row
+            .get::<Option<String>>(1)?
+            .and_then(|last_seen| serde_json::from_str::<OffsetDateTime>(&last_seen).ok())
+            .is_some_and(|last_seen| !is_session_expired(last_seen, lifetime_mins));
+
+        if !keep {
+            expired.push(id.to_string());
+        }
+    }
+
+    if expired.is_empty() {
+        return Ok(0);
+    }
+
+    Ok(db
+        .execute(
+            &format!(
+                "DELETE FROM {LOGINS_T} WHERE id IN ({})",
+                expired.join(", ")
+            ),
+            params!(),
+        )
+        .await?)
+}
+
+/// Keeps a record of a failed or refused login
+pub async fn add_login_attempt(
+    db: Connection,
+    ip: &str,
+    username: &str,
+    reason: &str,
+) -> anyhow::Result<u64> {
+    Ok(db
+        .execute(
+            &format!(
+                "INSERT INTO {LOGIN_ATTEMPTS_T} (ip, username, reason, timestamp) VALUES (?1, ?2, ?3, ?4)"
+            ),
+            (
+                ip,
+                username,
+                reason,
+                serde_json::to_string(&OffsetDateTime::now_utc()).unwrap(),
+            ),
+        )
+        .await?)
+}
@@ -1,11 +1,13 @@
 use libsql::{Connection, de, params};
+use time::{OffsetDateTime, ext::NumericalDuration};
 
 use crate::{
-    db::{ACTIVITIES_T, SOURCES_T},
-    types::{Activity, Source},
+    db::{ACTIVITIES_T, SOURCE_EVENTS_T, SOURCES_T, sort_key},
+    queries::read_page,
+    types::{Activity, Cursor, Page, Source, SourceEvent, StringError},
 };
 
-pub async fn get_sources(db: Connection) -> anyhow::Result<Vec<Source>> {
+pub async fn get_all_sources(db: Connection) -> anyhow::Result<Vec<Source>> {
     let mut result = db
         .query(&format!("SELECT * FROM {SOURCES_T}"), params!())
         .await?;
@@ -19,61 +21,381 @@ pub async fn get_sources(db: Connection) -> anyhow::Result<Vec<Source>> {
     Ok(sources)
 }
 
-pub async fn get_activity(db: Connection, limit: u32, skip: u32) -> anyhow::Result<Vec<Activity>> {
+pub async fn get_sources(db: Connection, user_id: u32) -> anyhow::Result<Vec<Source>> {
     let mut result = db
         .query(
-            &format!(
-                "SELECT 
-					a.id, 
-					a.post_url, 
-					a.timestamp, 
-					s.url as source_url
-				FROM {ACTIVITIES_T} AS a
-				INNER JOIN {SOURCES_T} AS s
-					ON a.source_id = s.id
-				ORDER BY a.id DESC
-				LIMIT ?1 OFFSET ?2
-				"
-            ),
-            [limit, skip],
+            &format!("SELECT * FROM {SOURCES_T} WHERE user_id = ?1"),
+            [user_id],
         )
         .await?;
 
-    let mut activities = Vec::new();
+    let mut sources = Vec::new();
     while let Some(row) = result.next().await? {
-        let source: Activity = de::from_row(&row)?;
-        activities.push(source);
+        let source: Source = de::from_row(&row)?;
+        sources.push(source);
+    }
+
+    Ok(sources)
+}
+
+pub async fn get_source(
+    db: Connection,
+    user_id: u32,
+    source_id: u32,
+) -> anyhow::Result<Option<Source>> {
+    let mut result = db
+        .query(
+            &format!("SELECT * FROM {SOURCES_T} WHERE id = ?1 AND user_id = ?2 LIMIT 1"),
+            [source_id, user_id],
+        )
+        .await?;
+
+    let Some(row) = result.next().await? else {
+        return Ok(None);
+    };
+
+    Ok(Some(de::from_row(&row)?))
+}
+
+/// Returns the id of the new source
+pub async fn add_source(db: Connection, user_id: u32, url: &str) -> anyhow::Result<u32> {
+    let mut result = db
+        .query(
+            &format!(
+                "INSERT INTO {SOURCES_T} (user_id, url, last_checked) VALUES (?1, ?2, ?3) RETURNING id"
+            ),
+            (
+                user_id,
+                url,
+                serde_json::to_string(&(OffsetDateTime::now_utc() - 1.hours())).unwrap(),
+            ),
+        )
+        .await?;
+
+    let Some(row) = result.next().await? else {
+        return Err(StringError("Insert didn't return an id".into()).into());
+    };
+
+    Ok(row.get(0)?)
+}
+
+pub async fn delete_source(db: Connection, user_id: u32, source_id: u32) -> anyhow::Result<u64> {
+    Ok(db
+        .execute(
+            &format!("DELETE FROM {SOURCES_T} WHERE id = ?1 AND user_id = ?2"),
+            [source_id, user_id],
+        )
+        .await?)
+}
+
+pub async fn set_source_enabled(
+    db: Connection,
+    user_id: u32,
+    source_id: u32,
+    enabled: bool,
+) -> anyhow::Result<u64> {
+    Ok(db
+        .execute(
+            &format!(
+                "UPDATE {SOURCES_T} SET failed_count = ?1, enabled = ?2 WHERE id = ?3 AND user_id = ?4"
+            ),
+            (0, if enabled { 1 } else { 0 }, source_id, user_id),
+        )
+        .await?)
+}
+
+/// Moves the source to its suggested url. Returns false if it has none
+pub async fn accept_redirect(db: Connection, user_id: u32, source_id: u32) -> anyhow::Result<bool> {
+    let tx = db.transaction().await?;
+
+    let moved = tx
+        .execute(
+            &format!(
+                "INSERT INTO {SOURCE_EVENTS_T} (source_id, type, message, timestamp)
+                SELECT id, 'url_updated', 'Accepted redirect from ' || url || ' to ' || redirect_url, ?1
+                FROM {SOURCES_T}
+                WHERE id = ?2 AND user_id = ?3 AND redirect_url IS NOT NULL"
+            ),
+            (
+                serde_json::to_string(&OffsetDateTime::now_utc()).unwrap(),
+                source_id,
+                user_id,
+            ),
+        )
+        .await?;
+    if moved == 0 {
+        return Ok(false);
     }
 
-    Ok(activities)
+    tx.execute(
+        &format!(
+            "UPDATE {SOURCES_T} SET url = redirect_url, redirect_url = NULL, failed_count = 0 WHERE id = ?1"
+        ),
+        [source_id],
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(true)
+}
+
+pub async fn get_activity(
+    db: Connection,
+    user_id: u32,
+    limit: u32,
+    cursor: Option<Cursor>,
+) -> anyhow::Result<Page<Activity>> {
+    let limit = limit.max(1);
+    let cursor = cursor.unwrap_or(Cursor::START);
+    let key = sort_key("a.timestamp");
+
+    let total: u64 = db
+        .query(
+            &format!(
+                "SELECT COUNT(*)
+                FROM {ACTIVITIES_T} AS a
+                INNER JOIN {SOURCES_T} AS s
+                    ON a.source_id = s.id
+                WHERE s.user_id = ?1"
+            ),
+            [user_id],
+        )
+        .await?
+        .next()
+        .await?
+        .ok_or(StringError("Count didn't return a row".into()))?
+        .get(0)?;
+
+    let rows = db
+        .query(
+            &format!(
+                "SELECT
+                    a.id,
+                    a.post_url,
+                    a.timestamp,
+                    s.url as source_url,
+                    {key} AS sort_key
+                FROM {ACTIVITIES_T} AS a
+                INNER JOIN {SOURCES_T} AS s
+                    ON a.source_id = s.id
+                WHERE s.user_id = ?1 AND ({key}, a.id) < (?2, ?3)
+                ORDER BY sort_key DESC, a.id DESC
+                LIMIT ?4
+                "
+            ),
+            (user_id, cursor.key, cursor.id, limit + 1),
+        )
+        .await?;
+
+    read_page(rows, limit, total).await
+}
+
+pub async fn get_source_activity(
+    db: Connection,
+    user_id: u32,
+    limit: u32,
+    cursor: Option<Cursor>,
+    source_id: u32,
+) -> anyhow::Result<Page<Activity>> {
+    let limit = limit.max(1);
+    let cursor = cursor.unwrap_or(Cursor::START);
+    let key = sort_key("a.timestamp");
+
+    let total: u64 = db
+        .query(
+            &format!(
+                "SELECT COUNT(*)
+                FROM {ACTIVITIES_T} AS a
+                INNER JOIN {SOURCES_T} AS s
+                    ON a.source_id = s.id
+                WHERE a.source_id = ?1 AND s.user_id = ?2"
+            ),
+            [source_id, user_id],
+        )
+        .await?
+        .next()
+        .await?
+        .ok_or(StringError("Count didn't return a row".into()))?
+        .get(0)?;
+
+    let rows = db
+        .query(
+            &format!(
+                "SELECT
+                    a.id,
+                    a.post_url,
+                    a.timestamp,
+                    s.url as source_url,
+                    {key} AS sort_key
+                FROM {ACTIVITIES_T} AS a
+                INNER JOIN {SOURCES_T} AS s
+                    ON a.source_id = s.id
+                WHERE a.source_id = ?1 AND s.user_id = ?2 AND ({key}, a.id) < (?3, ?4)
+                ORDER BY sort_key DESC, a.id DESC
+                LIMIT ?5
+                "
+            ),
+            (source_id, user_id, cursor.key, cursor.id, limit + 1),
+        )
+        .await?;
+
+    read_page(rows, limit, total).await
+}
+
+pub async fn delete_activities(db: Connection, user_id: u32) -> anyhow::Result<u64> {
+    Ok(db
+        .execute(
+            &format!(
+                "DELETE FROM {ACTIVITIES_T}
+                WHERE source_id IN (SELECT id FROM {SOURCES_T} WHERE user_id = ?1)"
+            ),
+            [user_id],
+        )
+        .await?)
+}
+
+/// Deletes up to `count` of the user's oldest activities
+pub async fn delete_oldest_activities(
+    db: Connection,
+    user_id: u32,
+    count: u32,
+) -> anyhow::Result<u64> {
+    Ok(db
+        .execute(
+            &format!(
+                "DELETE FROM {ACTIVITIES_T}
+                WHERE id IN (
+                    SELECT a.id
+                    FROM {ACTIVITIES_T} AS a
+                    INNER JOIN {SOURCES_T} AS s
+                        ON a.source_id = s.id
+                    WHERE s.user_id = ?2
+                    ORDER BY a.id ASC
+                    LIMIT ?1
+                )"
+            ),
+            [count, user_id],
+        )
+        .await?)
 }
 
-pub async fn get_source_activity(db: Connection, limit: u32, skip: u32, source_id: u32) -> anyhow::Result<Vec<Activity>> {
+pub async fn get_source_events(
+    db: Connection,
+    user_id: u32,
+    source_id: u32,
+) -> anyhow::Result<Vec<SourceEvent>> {
     let mut result = db
         .query(
             &format!(
-                "SELECT 
-					a.id, 
-					a.post_url, 
-					a.timestamp, 
-					s.url as source_url
-				FROM {ACTIVITIES_T} AS a
-				INNER JOIN {SOURCES_T} AS s
-					ON a.source_id = s.id
-                WHERE a.source_id = ?3
-				ORDER BY a.id DESC
-				LIMIT ?1 OFFSET ?2
-				"
-            ),
-            [limit, skip, source_id],
+                "SELECT e.* FROM {SOURCE_EVENTS_T} AS e
+                INNER JOIN {SOURCES_T} AS s
+                    ON e.source_id = s.id
+                WHERE e.source_id = ?1 AND s.user_id = ?2
+                ORDER BY e.id DESC"
+            ),
+            [source_id, user_id],
         )
         .await?;
 
-    let mut activities = Vec::new();
+    let mut events = Vec::new();
     while let Some(row) = result.next().await? {
-        let source: Activity = de::from_row(&row)?;
-        activities.push(source);
+        let event: SourceEvent = de::from_row(&row)?;
+        events.push(event);
     }
 
-    Ok(activities)
+    Ok(events)
+}
+
+pub async fn add_source_event(
+    db: Connection,
+    source_id: u32,
+    event_type: &str,
+    message: &str,
+) -> anyhow::Result<u64> {
+    Ok(db
+        .execute(
+            &format!(
+                "INSERT INTO {SOURCE_EVENTS_T} (source_id, type, message, timestamp) VALUES (?1, ?2, ?3, ?4)"
+            ),
+            (
+                source_id,
+                event_type,
+                message,
+                serde_json::to_string(&OffsetDateTime::now_utc()).unwrap(),
+            ),
+        )
+        .await?)
+}
+
+/// Points the source at a new url, dropping any suggested redirect
+pub async fn move_source(db: Connection, source_id: u32, url: &str) -> anyhow::Result<u64> {
+    Ok(db
+        .execute(
+            &format!("UPDATE {SOURCES_T} SET url = ?1, redirect_url = NULL WHERE id = ?2"),
+            (url, source_id),
+        )
+        .await?)
+}
+
+/// Suggests a redirect for the source. Returns 0 if it already was
+pub async fn suggest_redirect(db: Connection, source_id: u32, url: &str) -> anyhow::Result<u64> {
+    Ok(db
+        .execute(
+            &format!(
+                "UPDATE {SOURCES_T} SET redirect_url = ?1 WHERE id = ?2 AND redirect_url IS NOT ?1"
+            ),
+            (url, source_id),
+        )
+        .await?)
+}
+
+pub async fn set_failed_count(
+    db: Connection,
+    source_id: u32,
+    failed_count: u32,
+    enabled: bool,
+) -> anyhow::Result<u64> {
+    Ok(db
+        .execute(
+            &format!("UPDATE {SOURCES_T} SET failed_count = ?1, enabled = ?2 WHERE id = ?3"),
+            (failed_count, enabled, source_id),
+        )
+        .await?)
+}
+
+/// Records a successful check, resetting the failure count
+pub async fn set_last_checked(
+    db: Connection,
+    source_id: u32,
+    last_checked: OffsetDateTime,
+) -> anyhow::Result<u64> {
+    Ok(db
+        .execute(
+            &format!("UPDATE {SOURCES_T} SET last_checked = ?1, failed_count = ?2 WHERE id = ?3"),
+            (serde_json::to_string(&last_checked).unwrap(), 0, source_id),
+        )
+        .await?)
+}
+
+pub async fn add_activity(
+    db: Connection,
+    source_id: u32,
+    post_url: &str,
+    title: &str,
+    body: &str,
+) -> anyhow::Result<u64> {
+    Ok(db
+        .execute(
+            &format!(
+                "INSERT INTO {ACTIVITIES_T} (source_id, post_url, timestamp, title, body) VALUES (?1, ?2, ?3, ?4, ?5)"
+            ),
+            (
+                source_id,
+                post_url,
+                serde_json::to_string(&OffsetDateTime::now_utc()).unwrap(),
+                title,
+                body,
+            ),
+        )
+        .await?)
 }
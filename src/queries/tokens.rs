@@ -0,0 +1,111 @@
+use libsql::{Connection, de};
+use time::OffsetDateTime;
+
+use crate::{
+    db::{API_TOKENS_T, USERS_T},
+    types::{ApiToken, StringError, TokenScope},
+    utils::{hash_key, random_key},
+};
+
+pub(crate) const TOKEN_PREFIX: &str = "greg_";
+
+/// Creates a token and returns its id and the token itself, which isn't stored
+pub async fn add_token(
+    db: Connection,
+    user_id: u32,
+    name: &str,
+    scope: TokenScope,
+) -> anyhow::Result<(u32, String)> {
+    let token = format!("{TOKEN_PREFIX}{}", random_key());
+
+    let mut result = db
+        .query(
+            &format!(
+                "INSERT INTO {API_TOKENS_T} (user_id, name, token_hash, scope, timestamp) VALUES (?1, ?2, ?3, ?4, ?5) RETURNING id"
+            ),
+            (
+                user_id,
+                name,
+                hash_key(&token),
+                scope.as_str(),
+                serde_json::to_string(&OffsetDateTime::now_utc()).unwrap(),
+            ),
+        )
+        .await?;
+
+    let Some(row) = result.next().await? else {
+        return Err(StringError("Insert didn't return an id".into()).into());
+    };
+
+    Ok((row.get(0)?, token))
+}
+
+pub async fn get_tokens(db: Connection, user_id: u32) -> anyhow::Result<Vec<ApiToken>> {
+    let mut result = db
+        .query(
+            &format!("SELECT * FROM {API_TOKENS_T} WHERE user_id = ?1 ORDER BY id DESC"),
+            [user_id],
+        )
+        .await?;
+
+    let mut tokens = Vec::new();
+    while let Some(row) = result.next().await? {
+        let token: ApiToken = de::from_row(&row)?;
+        tokens.push(token);
+    }
+
+    Ok(tokens)
+}
+
+pub async fn delete_token(db: Connection, user_id: u32, token_id: u32) -> anyhow::Result<u64> {
+    Ok(db
+        .execute(
+            &format!("DELETE FROM {API_TOKENS_T} WHERE id = ?1 AND user_id = ?2"),
+            [token_id, user_id],
+        )
+        .await?)
+}
+
+/// Looks up who a token belongs to, returning their id, whether they're an
+/// admin and the token's scope. Marks the token as used.
+pub async fn get_token_owner(
+    db: Connection,
+    token: &str,
+) -> anyhow::Result<Option<(u32, bool, TokenScope)>> {
+    if !token.starts_with(TOKEN_PREFIX) {
+        return Ok(None);
+    }
+
+    let token_hash = hash_key(token);
+    let mut result = db
+        .query(
+            &format!(
+                "SELECT t.id, t.scope, u.id, u.is_admin
+                FROM {API_TOKENS_T} AS t
+                INNER JOIN {USERS_T} AS u
+                    ON t.user_id = u.id
+                WHERE t.token_hash = ?1
+                LIMIT 1"
+            ),
+            [token_hash],
+        )
+        .await?;
+
+    let Some(row) = result.next().await? else {
+        return Ok(None);
+    };
+
+    let token_id: u32 = row.get(0)?;
+    let scope = TokenScope::try_from(row.get::<String>(1)?).map_err(StringError)?;
+
+    db.execute(
+        &format!("UPDATE {API_TOKENS_T} SET last_used = ?1 WHERE id = ?2"),
+        (
+            serde_json::to_string(&OffsetDateTime::now_utc()).unwrap(),
+            token_id,
+        ),
+    )
+    .await?;
+
+    Ok(Some((row.get(2)?, row.get(3)?, scope)))
+}
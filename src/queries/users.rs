@@ -0,0 +1,102 @@
+use std::sync::LazyLock;
+
+use argon2::{
+    Argon2, PasswordHash, PasswordHasher, PasswordVerifier,
+    password_hash::{SaltString, rand_core::OsRng},
+};
+use libsql::{Connection, de, params};
+use log::error;
+use time::OffsetDateTime;
+
+use crate::{
+    db::USERS_T,
+    types::{StringError, User},
+};
+
+/// Checked against when a login names a user that doesn't exist, so it takes
+/// as long as one that does
+pub static DUMMY_HASH: LazyLock<String> =
+    LazyLock::new(|| hash_password("greg").unwrap_or_default());
+
+pub fn hash_password(password: &str) -> anyhow::Result<String> {
+    let salt = SaltString::generate(&mut OsRng);
+    Ok(Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map_err(|err| StringError(format!("Couldn't hash password. Err: {err}")))?
+        .to_string())
+}
+
+pub fn verify_password(password_hash: &str, password: &str) -> bool {
+    match PasswordHash::new(password_hash) {
+        Ok(hash) => Argon2::default()
+            .verify_password(password.as_bytes(), &hash)
+            .is_ok(),
+        Err(err) => {
+            error!("[Verify Password] Stored hash couldn't be parsed. Err: {err}");
+            false
+        }
+    }
+}
+
+pub async fn get_users(db: Connection) -> anyhow::Result<Vec<User>> {
+    let mut result = db
+        .query(&format!("SELECT * FROM {USERS_T} ORDER BY id"), params!())
+        .await?;
+
+    let mut users = Vec::new();
+    while let Some(row) = result.next().await? {
+        let user: User = de::from_row(&row)?;
+        users.push(user);
+    }
+
+    Ok(users)
+}
+
+pub async fn get_user_by_username(db: Connection, username: &str) -> anyhow::Result<Option<User>> {
+    let mut result = db
+        .query(
+            &format!("SELECT * FROM {USERS_T} WHERE username = ?1 LIMIT 1"),
+            [username],
+        )
+        .await?;
+
+    let Some(row) = result.next().await? else {
+        return Ok(None);
+    };
+
+    Ok(Some(de::from_row(&row)?))
+}
+
+pub async fn add_user(
+    db: Connection,
+    username: &str,
+    password: &str,
+    is_admin: bool,
+) -> anyhow::Result<u64> {
+    let password_hash = hash_password(password)?;
+
+    Ok(db
+        .execute(
+            &format!(
+                "INSERT INTO {USERS_T} (username, password_hash, is_admin, timestamp) VALUES (?1, ?2, ?3, ?4)"
+            ),
+            (
+                username,
+                password_hash,
+                is_admin,
+                serde_json::to_string(&OffsetDateTime::now_utc()).unwrap(),
+            ),
+        )
+        .await?)
+}
+
+pub async fn set_password(db: Connection, username: &str, password: &str) -> anyhow::Result<u64> {
+    let password_hash = hash_password(password)?;
+
+    Ok(db
+        .execute(
+            &format!("UPDATE {USERS_T} SET password_hash = ?1 WHERE username = ?2"),
+            [password_hash, username.to_owned()],
+        )
+        .await?)
+}
@@ -0,0 +1,441 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use libsql::{Connection, Database, Transaction};
+use time::OffsetDateTime;
+
+use crate::{
+    db::{self, migrations},
+    queries::{retention, roadmap, search, sessions, sources, tokens, users},
+    repo::{
+        ActivityRepo, Backend, Repo, RoadmapCheck, RoadmapRepo, SearchFilters, SessionOwner,
+        SessionRepo, SourcesRepo, UserRepo,
+    },
+    types::{
+        Activity, ApiToken, Cursor, Page, RCard, RCardHistory, RDBChangeAlt, RTab, Roadmap,
+        RoadmapActivity, RoadmapWatchedTab, SearchHit, Session, Source, SourceEvent, TokenScope,
+        User,
+    },
+};
+
+/// A local file, remote server or embedded replica, queried through
+/// [`crate::queries`]
+pub struct LibsqlBackend {
+    db: Database,
+}
+
+impl LibsqlBackend {
+    pub fn new(db: Database) -> Self {
+        LibsqlBackend { db }
+    }
+}
+
+#[async_trait]
+impl Backend for LibsqlBackend {
+    async fn connect(&self) -> anyhow::Result<Arc<dyn Repo>> {
+        Ok(Arc::new(LibsqlRepo {
+            conn: db::connect(&self.db).await?,
+        }))
+    }
+
+    async fn migrate(&self) -> anyhow::Result<()> {
+        migrations::migrate_up(db::connect(&self.db).await?).await
+    }
+
+    fn libsql(&self) -> Option<&Database> {
+        Some(&self.db)
+    }
+}
+
+pub struct LibsqlRepo {
+    conn: Connection,
+}
+
+impl LibsqlRepo {
+    pub fn new(conn: Connection) -> Self {
+        LibsqlRepo { conn }
+    }
+}
+
+#[async_trait]
+impl SourcesRepo for LibsqlRepo {
+    async fn get_all_sources(&self) -> anyhow::Result<Vec<Source>> {
+        sources::get_all_sources(self.conn.clone()).await
+    }
+
+    async fn get_sources(&self, user_id: u32) -> anyhow::Result<Vec<Source>> {
+        sources::get_sources(self.conn.clone(), user_id).await
+    }
+
+    async fn get_source(&self, user_id: u32, source_id: u32) -> anyhow::Result<Option<Source>> {
+        sources::get_source(self.conn.clone(), user_id, source_id).await
+    }
+
+    async fn add_source(&self, user_id: u32, url: &str) -> anyhow::Result<u32> {
+        sources::add_source(self.conn.clone(), user_id, url).await
+    }
+
+    async fn delete_source(&self, user_id: u32, source_id: u32) -> anyhow::Result<u64> {
+        sources::delete_source(self.conn.clone(), user_id, source_id).await
+    }
+
+    async fn set_source_enabled(
+        &self,
+        user_id: u32,
+        source_id: u32,
+        enabled: bool,
+    ) -> anyhow::Result<u64> {
+        sources::set_source_enabled(self.conn.clone(), user_id, source_id, enabled).await
+    }
+
+    async fn accept_redirect(&self, user_id: u32, source_id: u32) -> anyhow::Result<bool> {
+        sources::accept_redirect(self.conn.clone(), user_id, source_id).await
+    }
+
+    async fn get_source_events(
+        &self,
+        user_id: u32,
+        source_id: u32,
+    ) -> anyhow::Result<Vec<SourceEvent>> {
+        sources::get_source_events(self.conn.clone(), user_id, source_id).await
+    }
+
+    async fn add_source_event(
+        &self,
+        source_id: u32,
+        event_type: &str,
+        message: &str,
+    ) -> anyhow::Result<u64> {
+        sources::add_source_event(self.conn.clone(), source_id, event_type, message).await
+    }
+
+    async fn move_source(&self, source_id: u32, url: &str) -> anyhow::Result<u64> {
+        sources::move_source(self.conn.clone(), source_id, url).await
+    }
+
+    async fn suggest_redirect(&self, source_id: u32, url: &str) -> anyhow::Result<u64> {
+        sources::suggest_redirect(self.conn.clone(), source_id, url).await
+    }
+
+    async fn set_failed_count(
+        &self,
+        source_id: u32,
+        failed_count: u32,
+        enabled: bool,
+    ) -> anyhow::Result<u64> {
+        sources::set_failed_count(self.conn.clone(), source_id, failed_count, enabled).await
+    }
+
+    async fn set_last_checked(
+        &self,
+        source_id: u32,
+        last_checked: OffsetDateTime,
+    ) -> anyhow::Result<u64> {
+        sources::set_last_checked(self.conn.clone(), source_id, last_checked).await
+    }
+}
+
+#[async_trait]
+impl ActivityRepo for LibsqlRepo {
+    async fn get_activity(
+        &self,
+        user_id: u32,
+        limit: u32,
+        cursor: Option<Cursor>,
+    ) -> anyhow::Result<Page<Activity>> {
+        sources::get_activity(self.conn.clone(), user_id, limit, cursor).await
+    }
+
+    async fn get_source_activity(
+        &self,
+        user_id: u32,
+        limit: u32,
+        cursor: Option<Cursor>,
+        source_id: u32,
+    ) -> anyhow::Result<Page<Activity>> {
+        sources::get_source_activity(self.conn.clone(), user_id, limit, cursor, source_id).await
+    }
+
+    async fn add_activity(
+        &self,
+        source_id: u32,
+        post_url: &str,
+        title: &str,
+        body: &str,
+    ) -> anyhow::Result<u64> {
+        sources::add_activity(self.conn.clone(), source_id, post_url, title, body).await
+    }
+
+    async fn delete_activities(&self, user_id: u32) -> anyhow::Result<u64> {
+        sources::delete_activities(self.conn.clone(), user_id).await
+    }
+
+    async fn delete_oldest_activities(&self, user_id: u32, count: u32) -> anyhow::Result<u64> {
+        sources::delete_oldest_activities(self.conn.clone(), user_id, count).await
+    }
+
+    async fn search_activities(
+        &self,
+        user_id: u32,
+        q: &str,
+        filters: &SearchFilters,
+        limit: u32,
+    ) -> anyhow::Result<Vec<SearchHit>> {
+        let Some(query) = search::match_query(q) else {
+            return Ok(Vec::new());
+        };
+        search::search_activities(self.conn.clone(), user_id, &query, filters, limit).await
+    }
+
+    async fn delete_activities_before(&self, cutoff: OffsetDateTime) -> anyhow::Result<u64> {
+        retention::delete_activities_before(&self.conn, cutoff).await
+    }
+
+    async fn delete_activities_over_limit(&self, keep: u32) -> anyhow::Result<u64> {
+        retention::delete_activities_over_limit(&self.conn, keep).await
+    }
+}
+
+#[async_trait]
+impl RoadmapRepo for LibsqlRepo {
+    async fn get_most_recent_roadmap(&self) -> anyhow::Result<Option<Roadmap>> {
+        roadmap::get_most_recent_roadmap(self.conn.clone()).await
+    }
+
+    async fn get_roadmap(&self, activity_id: u32) -> anyhow::Result<Roadmap> {
+        roadmap::get_roadmap(self.conn.clone(), activity_id).await
+    }
+
+    async fn roadmap_activity_exists(&self, activity_id: u32) -> anyhow::Result<bool> {
+        roadmap::roadmap_activity_exists(self.conn.clone(), activity_id).await
+    }
+
+    async fn get_roadmap_activities(
+        &self,
+        user_id: u32,
+        limit: u32,
+        cursor: Option<Cursor>,
+    ) -> anyhow::Result<Page<RoadmapActivity>> {
+        roadmap::get_roadmap_activities(self.conn.clone(), user_id, limit, cursor).await
+    }
+
+    async fn get_most_recent_roadmap_tabs(&self) -> anyhow::Result<Vec<RTab>> {
+        roadmap::get_most_recent_roadmap_tabs(self.conn.clone()).await
+    }
+
+    async fn get_roadmap_changes(
+        &self,
+        user_id: u32,
+        activity_id: u32,
+    ) -> anyhow::Result<Vec<RDBChangeAlt>> {
+        roadmap::get_roadmap_changes(self.conn.clone(), user_id, activity_id).await
+    }
+
+    async fn get_card_history(
+        &self,
+        user_id: u32,
+        card_id: &str,
+    ) -> anyhow::Result<Option<RCardHistory>> {
+        roadmap::get_card_history(self.conn.clone(), user_id, card_id).await
+    }
+
+    async fn get_watched_tabs(&self, user_id: u32) -> anyhow::Result<Vec<RoadmapWatchedTab>> {
+        roadmap::get_watched_tabs(self.conn.clone(), user_id).await
+    }
+
+    async fn add_watched_tab(&self, user_id: u32, tab_id: &str) -> anyhow::Result<u32> {
+        roadmap::add_watched_tab(self.conn.clone(), user_id, tab_id).await
+    }
+
+    async fn delete_watched_tab(&self, user_id: u32, id: u32) -> anyhow::Result<u64> {
+        roadmap::delete_watched_tab(self.conn.clone(), user_id, id).await
+    }
+
+    async fn get_all_watched_tab_ids(&self) -> anyhow::Result<Vec<String>> {
+        roadmap::get_all_watched_tab_ids(self.conn.clone()).await
+    }
+
+    async fn search_cards(
+        &self,
+        user_id: u32,
+        q: &str,
+        filters: &SearchFilters,
+        limit: u32,
+    ) -> anyhow::Result<Vec<SearchHit>> {
+        let Some(query) = search::match_query(q) else {
+            return Ok(Vec::new());
+        };
+        search::search_cards(self.conn.clone(), user_id, &query, filters, limit).await
+    }
+
+    async fn begin_check(&self) -> anyhow::Result<Box<dyn RoadmapCheck>> {
+        Ok(Box::new(LibsqlCheck {
+            tx: self.conn.transaction().await?,
+        }))
+    }
+
+    async fn delete_roadmap_activities_before(
+        &self,
+        cutoff: OffsetDateTime,
+        only_unchanged: bool,
+    ) -> anyhow::Result<u64> {
+        retention::delete_roadmap_activities_before(&self.conn, cutoff, only_unchanged).await
+    }
+
+    async fn delete_unused_cards(&self) -> anyhow::Result<u64> {
+        retention::delete_unused_cards(&self.conn).await
+    }
+
+    async fn delete_unused_tabs(&self) -> anyhow::Result<u64> {
+        retention::delete_unused_tabs(&self.conn).await
+    }
+}
+
+pub struct LibsqlCheck {
+    tx: Transaction,
+}
+
+#[async_trait]
+impl RoadmapCheck for LibsqlCheck {
+    async fn new_roadmap(&self) -> anyhow::Result<u32> {
+        roadmap::new_roadmap(&self.tx).await
+    }
+
+    async fn save_card(&self, card: &RCard) -> anyhow::Result<u32> {
+        roadmap::save_card(&self.tx, card).await
+    }
+
+    async fn save_tab(&self, tab: &RTab) -> anyhow::Result<u32> {
+        roadmap::save_tab(&self.tx, tab).await
+    }
+
+    async fn save_card_assignment(
+        &self,
+        card_id: u32,
+        assign_info: &[u32; 4],
+    ) -> anyhow::Result<()> {
+        roadmap::save_card_assignment(&self.tx, card_id, assign_info).await
+    }
+
+    async fn save_tab_assignment(&self, activity_id: u32, tab_id: u32) -> anyhow::Result<()> {
+        roadmap::save_tab_assignment(&self.tx, activity_id, tab_id).await
+    }
+
+    async fn save_change(
+        &self,
+        change_type: &str,
+        activity_id: u32,
+        change_info: &[Option<u32>; 3],
+    ) -> anyhow::Result<()> {
+        roadmap::save_change(&self.tx, change_type, activity_id, change_info).await
+    }
+
+    async fn commit(self: Box<Self>) -> anyhow::Result<()> {
+        Ok(self.tx.commit().await?)
+    }
+
+    async fn rollback(self: Box<Self>) -> anyhow::Result<()> {
+        Ok(self.tx.rollback().await?)
+    }
+}
+
+#[async_trait]
+impl SessionRepo for LibsqlRepo {
+    async fn add_session(
+        &self,
+        user_id: u32,
+        key: &str,
+        user_agent: Option<&str>,
+    ) -> anyhow::Result<u32> {
+        sessions::add_session(self.conn.clone(), user_id, key, user_agent).await
+    }
+
+    async fn get_session_owner(&self, key: &str) -> anyhow::Result<Option<SessionOwner>> {
+        sessions::get_session_owner(self.conn.clone(), key).await
+    }
+
+    async fn touch_session(&self, session_id: u32) -> anyhow::Result<u64> {
+        sessions::touch_session(self.conn.clone(), session_id).await
+    }
+
+    async fn get_sessions(&self, user_id: u32) -> anyhow::Result<Vec<Session>> {
+        sessions::get_sessions(self.conn.clone(), user_id).await
+    }
+
+    async fn delete_session(&self, key: &str) -> anyhow::Result<u64> {
+        sessions::delete_session(self.conn.clone(), key).await
+    }
+
+    async fn delete_session_by_id(&self, user_id: u32, id: u32) -> anyhow::Result<u64> {
+        sessions::delete_session_by_id(self.conn.clone(), user_id, id).await
+    }
+
+    async fn delete_user_sessions(&self, user_id: u32) -> anyhow::Result<u64> {
+        sessions::delete_user_sessions(self.conn.clone(), user_id).await
+    }
+
+    async fn prune_sessions(&self, lifetime_mins: i64) -> anyhow::Result<u64> {
+        sessions::prune_sessions(self.conn.clone(), lifetime_mins).await
+    }
+
+    async fn add_login_attempt(
+        &self,
+        ip: &str,
+        username: &str,
+        reason: &str,
+    ) -> anyhow::Result<u64> {
+        sessions::add_login_attempt(self.conn.clone(), ip, username, reason).await
+    }
+
+    async fn delete_login_attempts_before(&self, cutoff: OffsetDateTime) -> anyhow::Result<u64> {
+        retention::delete_login_attempts_before(&self.conn, cutoff).await
+    }
+}
+
+#[async_trait]
+impl UserRepo for LibsqlRepo {
+    async fn get_users(&self) -> anyhow::Result<Vec<User>> {
+        users::get_users(self.conn.clone()).await
+    }
+
+    async fn get_user_by_username(&self, username: &str) -> anyhow::Result<Option<User>> {
+        users::get_user_by_username(self.conn.clone(), username).await
+    }
+
+    async fn add_user(
+        &self,
+        username: &str,
+        password: &str,
+        is_admin: bool,
+    ) -> anyhow::Result<u64> {
+        users::add_user(self.conn.clone(), username, password, is_admin).await
+    }
+
+    async fn set_password(&self, username: &str, password: &str) -> anyhow::Result<u64> {
+        users::set_password(self.conn.clone(), username, password).await
+    }
+
+    async fn add_token(
+        &self,
+        user_id: u32,
+        name: &str,
+        scope: TokenScope,
+    ) -> anyhow::Result<(u32, String)> {
+        tokens::add_token(self.conn.clone(), user_id, name, scope).await
+    }
+
+    async fn get_tokens(&self, user_id: u32) -> anyhow::Result<Vec<ApiToken>> {
+        tokens::get_tokens(self.conn.clone(), user_id).await
+    }
+
+    async fn delete_token(&self, user_id: u32, token_id: u32) -> anyhow::Result<u64> {
+        tokens::delete_token(self.conn.clone(), user_id, token_id).await
+    }
+
+    async fn get_token_owner(
+        &self,
+        token: &str,
+    ) -> anyhow::Result<Option<(u32, bool, TokenScope)>> {
+        tokens::get_token_owner(self.conn.clone(), token).await
+    }
+}
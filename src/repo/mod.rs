@@ -0,0 +1,340 @@
+//! Storage behind traits, so Greg can run on libsql or PostgreSQL. A
+//! [`Backend`] opens a [`Repo`] for each request or task, which is what routes
+//! and tasks query through.
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use log::info;
+use time::OffsetDateTime;
+
+use crate::{
+    config::{AuthConfig, DatabaseConfig},
+    db,
+    types::{
+        Activity, ApiToken, Cursor, Page, RCard, RCardHistory, RDBChangeAlt, RTab, Roadmap,
+        RoadmapActivity, RoadmapWatchedTab, SearchHit, Session, Source, SourceEvent, TokenScope,
+        User,
+    },
+};
+
+pub mod libsql;
+#[cfg(feature = "postgres")]
+pub mod postgres;
+
+/// A database Greg can run on
+#[async_trait]
+pub trait Backend: Send + Sync {
+    /// Opens a handle for one request or task
+    async fn connect(&self) -> anyhow::Result<Arc<dyn Repo>>;
+
+    /// Applies every pending migration
+    async fn migrate(&self) -> anyhow::Result<()>;
+
+    /// The libsql database, for what only works there like backups and
+    /// rolling back migrations
+    fn libsql(&self) -> Option<&::libsql::Database> {
+        None
+    }
+}
+
+/// Opens the app database the config points at
+pub async fn open_backend(config: &DatabaseConfig) -> anyhow::Result<Box<dyn Backend>> {
+    #[cfg(feature = "postgres")]
+    if let Some(ref url) = config.postgres_url {
+        return Ok(Box::new(postgres::PostgresBackend::new(url)?));
+    }
+
+    Ok(Box::new(libsql::LibsqlBackend::new(
+        db::get_database(config).await?,
+    )))
+}
+
+/// Everything a route or task can ask of the database
+pub trait Repo: SourcesRepo + ActivityRepo + RoadmapRepo + SessionRepo + UserRepo {}
+
+impl<T: SourcesRepo + ActivityRepo + RoadmapRepo + SessionRepo + UserRepo> Repo for T {}
+
+#[async_trait]
+pub trait SourcesRepo: Send + Sync {
+    async fn get_all_sources(&self) -> anyhow::Result<Vec<Source>>;
+    async fn get_sources(&self, user_id: u32) -> anyhow::Result<Vec<Source>>;
+    async fn get_source(&self, user_id: u32, source_id: u32) -> anyhow::Result<Option<Source>>;
+    /// Returns the id of the new source
+    async fn add_source(&self, user_id: u32, url: &str) -> anyhow::Result<u32>;
+    async fn delete_source(&self, user_id: u32, source_id: u32) -> anyhow::Result<u64>;
+    /// Enabling also resets the failure count
+    async fn set_source_enabled(
+        &self,
+        user_id: u32,
+        source_id: u32,
+        enabled: bool,
+    ) -> anyhow::Result<u64>;
+    /// Moves the source to its suggested url. Returns false if it has none,
+    /// and fails with a unique violation if the user already has that url
+    async fn accept_redirect(&self, user_id: u32, source_id: u32) -> anyhow::Result<bool>;
+    async fn get_source_events(
+        &self,
+        user_id: u32,
+        source_id: u32,
+    ) -> anyhow::Result<Vec<SourceEvent>>;
+    async fn add_source_event(
+        &self,
+        source_id: u32,
+        event_type: &str,
+        message: &str,
+    ) -> anyhow::Result<u64>;
+    /// Points the source at a new url, dropping any suggested redirect
+    async fn move_source(&self, source_id: u32, url: &str) -> anyhow::Result<u64>;
+    /// Suggests a redirect for the source. Returns 0 if it already was
+    async fn suggest_redirect(&self, source_id: u32, url: &str) -> anyhow::Result<u64>;
+    async fn set_failed_count(
+        &self,
+        source_id: u32,
+        failed_count: u32,
+        enabled: bool,
+    ) -> anyhow::Result<u64>;
+    /// Records a successful check, resetting the failure count
+    async fn set_last_checked(
+        &self,
+        source_id: u32,
+        last_checked: OffsetDateTime,
+    ) -> anyhow::Result<u64>;
+}
+
+#[async_trait]
+pub trait ActivityRepo: Send + Sync {
+    async fn get_activity(
+        &self,
+        user_id: u32,
+        limit: u32,
+        cursor: Option<Cursor>,
+    ) -> anyhow::Result<Page<Activity>>;
+    async fn get_source_activity(
+        &self,
+        user_id: u32,
+        limit: u32,
+        cursor: Option<Cursor>,
+        source_id: u32,
+    ) -> anyhow::Result<Page<Activity>>;
+    async fn add_activity(
+        &self,
+        source_id: u32,
+        post_url: &str,
+        title: &str,
+        body: &str,
+    ) -> anyhow::Result<u64>;
+    async fn delete_activities(&self, user_id: u32) -> anyhow::Result<u64>;
+    /// Deletes up to `count` of the user's oldest activities
+    async fn delete_oldest_activities(&self, user_id: u32, count: u32) -> anyhow::Result<u64>;
+    /// The user's activities matching every word of `q`, best first. The last
+    /// word can be the start of a word
+    async fn search_activities(
+        &self,
+        user_id: u32,
+        q: &str,
+        filters: &SearchFilters,
+        limit: u32,
+    ) -> anyhow::Result<Vec<SearchHit>>;
+    async fn delete_activities_before(&self, cutoff: OffsetDateTime) -> anyhow::Result<u64>;
+    /// Deletes all but the newest `keep` activities of every source
+    async fn delete_activities_over_limit(&self, keep: u32) -> anyhow::Result<u64>;
+}
+
+/// Checks save the cards of every tab anyone watches, so reads that return
+/// cards or changes take the user and only cover the tabs they watch
+#[async_trait]
+pub trait RoadmapRepo: Send + Sync {
+    async fn get_most_recent_roadmap(&self) -> anyhow::Result<Option<Roadmap>>;
+    /// The roadmap as it was saved by a check, with cards sorted by roadmap id
+    async fn get_roadmap(&self, activity_id: u32) -> anyhow::Result<Roadmap>;
+    async fn roadmap_activity_exists(&self, activity_id: u32) -> anyhow::Result<bool>;
+    /// Every check, counting only the changes on the user's tabs
+    async fn get_roadmap_activities(
+        &self,
+        user_id: u32,
+        limit: u32,
+        cursor: Option<Cursor>,
+    ) -> anyhow::Result<Page<RoadmapActivity>>;
+    async fn get_most_recent_roadmap_tabs(&self) -> anyhow::Result<Vec<RTab>>;
+    async fn get_roadmap_changes(
+        &self,
+        user_id: u32,
+        activity_id: u32,
+    ) -> anyhow::Result<Vec<RDBChangeAlt>>;
+    /// None if the card has never been on one of the user's tabs
+    async fn get_card_history(
+        &self,
+        user_id: u32,
+        card_id: &str,
+    ) -> anyhow::Result<Option<RCardHistory>>;
+    async fn get_watched_tabs(&self, user_id: u32) -> anyhow::Result<Vec<RoadmapWatchedTab>>;
+    /// Returns the id of the new watched tab
+    async fn add_watched_tab(&self, user_id: u32, tab_id: &str) -> anyhow::Result<u32>;
+    async fn delete_watched_tab(&self, user_id: u32, id: u32) -> anyhow::Result<u64>;
+    /// Tabs watched by anyone, each once
+    async fn get_all_watched_tab_ids(&self) -> anyhow::Result<Vec<String>>;
+    /// Cards matching every word of `q`, best first. The last word can be the
+    /// start of a word
+    async fn search_cards(
+        &self,
+        user_id: u32,
+        q: &str,
+        filters: &SearchFilters,
+        limit: u32,
+    ) -> anyhow::Result<Vec<SearchHit>>;
+    /// Starts saving a roadmap check, nothing is kept until it's committed
+    async fn begin_check(&self) -> anyhow::Result<Box<dyn RoadmapCheck>>;
+    /// Deletes roadmap checks from before the cutoff along with their
+    /// assignments and changes. The most recent check is always kept since
+    /// new checks are compared against it.
+    async fn delete_roadmap_activities_before(
+        &self,
+        cutoff: OffsetDateTime,
+        only_unchanged: bool,
+    ) -> anyhow::Result<u64>;
+    /// Deletes cards no check or change points at anymore
+    async fn delete_unused_cards(&self) -> anyhow::Result<u64>;
+    /// Deletes tabs no check or change points at anymore
+    async fn delete_unused_tabs(&self) -> anyhow::Result<u64>;
+}
+
+/// A roadmap check being saved in one transaction
+#[async_trait]
+pub trait RoadmapCheck: Send + Sync {
+    /// Returns the id of the new roadmap activity
+    async fn new_roadmap(&self) -> anyhow::Result<u32>;
+    /// Saves a card unless an identical one already is, either way returning
+    /// its id
+    async fn save_card(&self, card: &RCard) -> anyhow::Result<u32>;
+    async fn save_tab(&self, tab: &RTab) -> anyhow::Result<u32>;
+    /// * `assign_info` - activity, tab, section_pos, card_pos
+    async fn save_card_assignment(
+        &self,
+        card_id: u32,
+        assign_info: &[u32; 4],
+    ) -> anyhow::Result<()>;
+    async fn save_tab_assignment(&self, activity_id: u32, tab_id: u32) -> anyhow::Result<()>;
+    /// * `change_info` - previous_card, current_card, tab
+    async fn save_change(
+        &self,
+        change_type: &str,
+        activity_id: u32,
+        change_info: &[Option<u32>; 3],
+    ) -> anyhow::Result<()>;
+    async fn commit(self: Box<Self>) -> anyhow::Result<()>;
+    async fn rollback(self: Box<Self>) -> anyhow::Result<()>;
+}
+
+/// Who a session belongs to
+pub struct SessionOwner {
+    pub session_id: u32,
+    pub user_id: u32,
+    pub is_admin: bool,
+    pub last_seen: OffsetDateTime,
+}
+
+#[async_trait]
+pub trait SessionRepo: Send + Sync {
+    /// Stores a new session and returns its id. Only a hash of the key is kept
+    async fn add_session(
+        &self,
+        user_id: u32,
+        key: &str,
+        user_agent: Option<&str>,
+    ) -> anyhow::Result<u32>;
+    async fn get_session_owner(&self, key: &str) -> anyhow::Result<Option<SessionOwner>>;
+    /// Moves the session's expiry forward
+    async fn touch_session(&self, session_id: u32) -> anyhow::Result<u64>;
+    async fn get_sessions(&self, user_id: u32) -> anyhow::Result<Vec<Session>>;
+    async fn delete_session(&self, key: &str) -> anyhow::Result<u64>;
+    async fn delete_session_by_id(&self, user_id: u32, id: u32) -> anyhow::Result<u64>;
+    async fn delete_user_sessions(&self, user_id: u32) -> anyhow::Result<u64>;
+    /// Deletes sessions that have expired, along with any that can't be read
+    async fn prune_sessions(&self, lifetime_mins: i64) -> anyhow::Result<u64>;
+    /// Keeps a record of a failed login
+    async fn add_login_attempt(
+        &self,
+        ip: &str,
+        username: &str,
+        reason: &str,
+    ) -> anyhow::Result<u64>;
+    async fn delete_login_attempts_before(&self, cutoff: OffsetDateTime) -> anyhow::Result<u64>;
+}
+
+#[async_trait]
+pub trait UserRepo: Send + Sync {
+    async fn get_users(&self) -> anyhow::Result<Vec<User>>;
+    async fn get_user_by_username(&self, username: &str) -> anyhow::Result<Option<User>>;
+    async fn add_user(&self, username: &str, password: &str, is_admin: bool)
+    -> anyhow::Result<u64>;
+    async fn set_password(&self, username: &str, password: &str) -> anyhow::Result<u64>;
+    /// Creates a token and returns its id and the token itself, which isn't
+    /// stored
+    async fn add_token(
+        &self,
+        user_id: u32,
+        name: &str,
+        scope: TokenScope,
+    ) -> anyhow::Result<(u32, String)>;
+    async fn get_tokens(&self, user_id: u32) -> anyhow::Result<Vec<ApiToken>>;
+    async fn delete_token(&self, user_id: u32, token_id: u32) -> anyhow::Result<u64>;
+    /// Looks up who a token belongs to, returning their id, whether they're an
+    /// admin and the token's scope. Marks the token as used.
+    async fn get_token_owner(&self, token: &str)
+    -> anyhow::Result<Option<(u32, bool, TokenScope)>>;
+}
+
+pub struct SearchFilters {
+    pub source_id: Option<u32>,
+    /// Roadmap id of the tab
+    pub tab_id: Option<String>,
+    pub from: Option<OffsetDateTime>,
+    /// Exclusive
+    pub to: Option<OffsetDateTime>,
+}
+
+/// Searches the user's activities and the roadmap cards, best matches first.
+/// Filtering by source leaves out cards and filtering by tab leaves out
+/// activities.
+pub async fn search_all(
+    db: &dyn Repo,
+    user_id: u32,
+    q: &str,
+    filters: &SearchFilters,
+    limit: u32,
+) -> anyhow::Result<Vec<SearchHit>> {
+    let mut hits = Vec::new();
+
+    if filters.tab_id.is_none() {
+        hits.extend(db.search_activities(user_id, q, filters, limit).await?);
+    }
+    if filters.source_id.is_none() {
+        hits.extend(db.search_cards(user_id, q, filters, limit).await?);
+    }
+
+    hits.sort_by(|a, b| a.rank.total_cmp(&b.rank));
+    hits.truncate(limit as usize);
+
+    Ok(hits)
+}
+
+/// Creates the configured admin when there are no users yet. As the first user
+/// it gets id 1 and so owns any sources and watched tabs from before users.
+pub async fn bootstrap_admin(db: &dyn Repo, auth: &AuthConfig) -> anyhow::Result<()> {
+    if !db.get_users().await?.is_empty() {
+        return Ok(());
+    }
+
+    let Some(ref password) = auth.password else {
+        info!(
+            "[Bootstrap Admin] No users and auth.password is not set. Add one with `greg user add`"
+        );
+        return Ok(());
+    };
+
+    info!("[Bootstrap Admin] Creating admin {}", auth.admin_username);
+    db.add_user(&auth.admin_username, password, true).await?;
+
+    Ok(())
+}
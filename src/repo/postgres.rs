@@ -0,0 +1,2058 @@
+//! The app database on a PostgreSQL server. It has its own schema and
+//! migrations, timestamps are TIMESTAMPTZ and search uses tsvector columns
+//! instead of fts5 tables.
+
+use std::{collections::HashMap, sync::Arc};
+
+use async_trait::async_trait;
+use deadpool_postgres::{Config, Object, Pool, Runtime};
+use log::info;
+use time::{OffsetDateTime, ext::NumericalDuration};
+use tokio_postgres::{NoTls, Row};
+
+use crate::{
+    db::{
+        ACTIVITIES_T, API_TOKENS_T, LOGIN_ATTEMPTS_T, LOGINS_T, MIGRATIONS_T, R_ACTIVITIES_T,
+        R_CARD_ASSIGNS_T, R_CARDS_T, R_CHANGES_T, R_TAB_ASSIGNS_T, R_TABS_T, R_WATCHED_TABS_T,
+        SOURCE_EVENTS_T, SOURCES_T, USERS_T,
+    },
+    queries::{
+        search::{HIGHLIGHT_END, HIGHLIGHT_START, SNIPPET_TOKENS, parse_snippet},
+        tokens::TOKEN_PREFIX,
+        users::hash_password,
+    },
+    repo::{
+        ActivityRepo, Backend, Repo, RoadmapCheck, RoadmapRepo, SearchFilters, SessionOwner,
+        SessionRepo, SourcesRepo, UserRepo,
+    },
+    types::{
+        Activity, ApiToken, Cursor, Page, RCard, RCardChange, RCardHistory, RCardPlacement,
+        RCardRevision, RDBChangeAlt, RTab, Roadmap, RoadmapActivity, RoadmapWatchedTab, SearchHit,
+        SearchKind, Session, Source, SourceEvent, StringError, TokenScope, User,
+    },
+    utils::{hash_card, hash_key, random_key},
+};
+
+struct Migration {
+    version: i64,
+    name: &'static str,
+    up_sql: fn() -> String,
+}
+
+/// Every PostgreSQL migration, oldest first. These can't be rolled back
+const MIGRATIONS: &[Migration] = &[Migration {
+    version: 1,
+    name: "initial_schema",
+    up_sql: initial_schema,
+}];
+
+/// The libsql schema as of its latest migration. Ids and counts are BIGINT
+/// since PostgreSQL has no unsigned types.
+fn initial_schema() -> String {
+    format!(
+        "
+        CREATE TABLE {USERS_T} (
+            id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+            username TEXT NOT NULL UNIQUE,
+            password_hash TEXT NOT NULL,
+            is_admin BOOLEAN NOT NULL DEFAULT FALSE,
+            timestamp TIMESTAMPTZ NOT NULL
+        );
+
+        CREATE TABLE {LOGINS_T} (
+            id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+            timestamp TIMESTAMPTZ NOT NULL,
+            key TEXT NOT NULL UNIQUE,
+            user_id BIGINT NOT NULL REFERENCES {USERS_T} (id) ON DELETE CASCADE,
+            last_seen TIMESTAMPTZ NOT NULL,
+            user_agent TEXT
+        );
+        CREATE INDEX idx_{LOGINS_T}_user_id ON {LOGINS_T} (user_id);
+
+        CREATE TABLE {API_TOKENS_T} (
+            id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+            user_id BIGINT NOT NULL REFERENCES {USERS_T} (id) ON DELETE CASCADE,
+            name TEXT NOT NULL,
+            token_hash TEXT NOT NULL UNIQUE,
+            scope TEXT NOT NULL,
+            timestamp TIMESTAMPTZ NOT NULL,
+            last_used TIMESTAMPTZ
+        );
+        CREATE INDEX idx_{API_TOKENS_T}_user_id ON {API_TOKENS_T} (user_id);
+
+        CREATE TABLE {LOGIN_ATTEMPTS_T} (
+            id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+            ip TEXT NOT NULL,
+            username TEXT NOT NULL,
+            reason TEXT NOT NULL,
+            timestamp TIMESTAMPTZ NOT NULL
+        );
+
+        CREATE TABLE {SOURCES_T} (
+            id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+            user_id BIGINT NOT NULL REFERENCES {USERS_T} (id) ON DELETE CASCADE,
+            url TEXT NOT NULL,
+            last_checked TIMESTAMPTZ NOT NULL,
+            enabled BOOLEAN NOT NULL DEFAULT TRUE,
+            failed_count BIGINT NOT NULL DEFAULT 0,
+            redirect_url TEXT,
+            UNIQUE (user_id, url)
+        );
+
+        CREATE TABLE {SOURCE_EVENTS_T} (
+            id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+            source_id BIGINT NOT NULL REFERENCES {SOURCES_T} (id) ON DELETE CASCADE,
+            type TEXT NOT NULL,
+            message TEXT NOT NULL,
+            timestamp TIMESTAMPTZ NOT NULL
+        );
+        CREATE INDEX idx_{SOURCE_EVENTS_T}_source_id ON {SOURCE_EVENTS_T} (source_id);
+
+        CREATE TABLE {ACTIVITIES_T} (
+            id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+            source_id BIGINT NOT NULL REFERENCES {SOURCES_T} (id) ON DELETE CASCADE,
+            post_url TEXT NOT NULL,
+            timestamp TIMESTAMPTZ NOT NULL,
+            title TEXT,
+            body TEXT,
+            search TSVECTOR GENERATED ALWAYS AS (
+                to_tsvector('simple', COALESCE(title, '') || ' ' || COALESCE(body, '') || ' ' || post_url)
+            ) STORED
+        );
+        CREATE INDEX idx_{ACTIVITIES_T}_source_id ON {ACTIVITIES_T} (source_id);
+        CREATE INDEX idx_{ACTIVITIES_T}_timestamp ON {ACTIVITIES_T} (timestamp, id);
+        CREATE INDEX idx_{ACTIVITIES_T}_search ON {ACTIVITIES_T} USING GIN (search);
+
+        CREATE TABLE {R_ACTIVITIES_T} (
+            id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+            timestamp TIMESTAMPTZ NOT NULL
+        );
+        CREATE INDEX idx_{R_ACTIVITIES_T}_timestamp ON {R_ACTIVITIES_T} (timestamp, id);
+
+        CREATE TABLE {R_TABS_T} (
+            id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+            roadmap_id TEXT NOT NULL UNIQUE,
+            name TEXT NOT NULL,
+            slug TEXT NOT NULL,
+            timestamp TIMESTAMPTZ NOT NULL
+        );
+
+        CREATE TABLE {R_CARDS_T} (
+            id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+            roadmap_id TEXT NOT NULL,
+            name TEXT NOT NULL,
+            description TEXT NOT NULL,
+            image_url TEXT,
+            slug TEXT NOT NULL,
+            timestamp TIMESTAMPTZ NOT NULL,
+            content_hash TEXT NOT NULL UNIQUE,
+            search TSVECTOR GENERATED ALWAYS AS (
+                to_tsvector('simple', name || ' ' || description)
+            ) STORED
+        );
+        CREATE INDEX idx_{R_CARDS_T}_roadmap_id ON {R_CARDS_T} (roadmap_id);
+        CREATE INDEX idx_{R_CARDS_T}_search ON {R_CARDS_T} USING GIN (search);
+
+        CREATE TABLE {R_CARD_ASSIGNS_T} (
+            id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+            activity_id BIGINT NOT NULL REFERENCES {R_ACTIVITIES_T} (id) ON DELETE CASCADE,
+            tab_id BIGINT NOT NULL REFERENCES {R_TABS_T} (id) ON DELETE CASCADE,
+            card_id BIGINT NOT NULL REFERENCES {R_CARDS_T} (id) ON DELETE CASCADE,
+            section_position BIGINT NOT NULL,
+            card_position BIGINT NOT NULL,
+            timestamp TIMESTAMPTZ NOT NULL
+        );
+        CREATE INDEX idx_{R_CARD_ASSIGNS_T}_activity_id ON {R_CARD_ASSIGNS_T} (activity_id);
+        CREATE INDEX idx_{R_CARD_ASSIGNS_T}_tab_id ON {R_CARD_ASSIGNS_T} (tab_id);
+        CREATE INDEX idx_{R_CARD_ASSIGNS_T}_card_id ON {R_CARD_ASSIGNS_T} (card_id);
+
+        CREATE TABLE {R_TAB_ASSIGNS_T} (
+            id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+            activity_id BIGINT NOT NULL REFERENCES {R_ACTIVITIES_T} (id) ON DELETE CASCADE,
+            tab_id BIGINT NOT NULL REFERENCES {R_TABS_T} (id) ON DELETE CASCADE,
+            timestamp TIMESTAMPTZ NOT NULL
+        );
+        CREATE INDEX idx_{R_TAB_ASSIGNS_T}_activity_id ON {R_TAB_ASSIGNS_T} (activity_id);
+        CREATE INDEX idx_{R_TAB_ASSIGNS_T}_tab_id ON {R_TAB_ASSIGNS_T} (tab_id);
+
+        CREATE TABLE {R_CHANGES_T} (
+            id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+            type TEXT NOT NULL,
+            activity_id BIGINT NOT NULL REFERENCES {R_ACTIVITIES_T} (id) ON DELETE CASCADE,
+            previous_card_id BIGINT REFERENCES {R_CARDS_T} (id) ON DELETE CASCADE,
+            current_card_id BIGINT REFERENCES {R_CARDS_T} (id) ON DELETE CASCADE,
+            tab_id BIGINT REFERENCES {R_TABS_T} (id) ON DELETE CASCADE,
+            timestamp TIMESTAMPTZ NOT NULL
+        );
+        CREATE INDEX idx_{R_CHANGES_T}_activity_id ON {R_CHANGES_T} (activity_id);
+        CREATE INDEX idx_{R_CHANGES_T}_previous_card_id ON {R_CHANGES_T} (previous_card_id);
+        CREATE INDEX idx_{R_CHANGES_T}_current_card_id ON {R_CHANGES_T} (current_card_id);
+        CREATE INDEX idx_{R_CHANGES_T}_tab_id ON {R_CHANGES_T} (tab_id);
+
+        CREATE TABLE {R_WATCHED_TABS_T} (
+            id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+            user_id BIGINT NOT NULL REFERENCES {USERS_T} (id) ON DELETE CASCADE,
+            tab_roadmap_id TEXT NOT NULL,
+            timestamp TIMESTAMPTZ NOT NULL,
+            UNIQUE (user_id, tab_roadmap_id)
+        );
+        "
+    )
+}
+
+/// A PostgreSQL server, connected to without TLS
+pub struct PostgresBackend {
+    pool: Pool,
+}
+
+impl PostgresBackend {
+    /// Connections are opened as they're needed, so this doesn't check the
+    /// server is reachable
+    pub fn new(url: &str) -> anyhow::Result<Self> {
+        let config = Config {
+            url: Some(url.to_owned()),
+            ..Default::default()
+        };
+
+        Ok(PostgresBackend {
+            pool: config.create_pool(Some(Runtime::Tokio1), NoTls)?,
+        })
+    }
+}
+
+#[async_trait]
+impl Backend for PostgresBackend {
+    async fn connect(&self) -> anyhow::Result<Arc<dyn Repo>> {
+        Ok(Arc::new(PostgresRepo {
+            pool: self.pool.clone(),
+        }))
+    }
+
+    /// Runs every migration that hasn't been yet, refusing to if the server
+    /// has ones this build doesn't know about
+    async fn migrate(&self) -> anyhow::Result<()> {
+        let mut client = self.pool.get().await?;
+        let tx = client.transaction().await?;
+
+        // Keeps two instances starting at once from both migrating
+        tx.batch_execute(&format!(
+            "SELECT pg_advisory_xact_lock(hashtext('{MIGRATIONS_T}'));
+            CREATE TABLE IF NOT EXISTS {MIGRATIONS_T} (
+                version BIGINT PRIMARY KEY,
+                name TEXT NOT NULL,
+                applied_at TIMESTAMPTZ NOT NULL
+            );"
+        ))
+        .await?;
+
+        let applied = tx
+            .query(
+                &format!("SELECT version, name FROM {MIGRATIONS_T} ORDER BY version"),
+                &[],
+            )
+            .await?;
+
+        for row in &applied {
+            let version: i64 = row.try_get("version")?;
+            if !MIGRATIONS.iter().any(|m| m.version == version) {
+                return Err(StringError(format!(
+                    "Database has migration {version} ({}) which this build doesn't know about. Run a newer build",
+                    row.try_get::<_, String>("name")?
+                ))
+                .into());
+            }
+        }
+
+        for migration in MIGRATIONS.iter().filter(|m| {
+            !applied
+                .iter()
+                .any(|row| row.get::<_, i64>("version") == m.version)
+        }) {
+            info!(
+                "[Migrate] Applying {} ({})",
+                migration.version, migration.name
+            );
+            tx.batch_execute(&(migration.up_sql)()).await?;
+            tx.execute(
+                &format!(
+                    "INSERT INTO {MIGRATIONS_T} (version, name, applied_at) VALUES ($1, $2, $3)"
+                ),
+                &[
+                    &migration.version,
+                    &migration.name,
+                    &OffsetDateTime::now_utc(),
+                ],
+            )
+            .await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+}
+
+pub struct PostgresRepo {
+    pool: Pool,
+}
+
+impl PostgresRepo {
+    async fn client(&self) -> anyhow::Result<Object> {
+        Ok(self.pool.get().await?)
+    }
+}
+
+fn get_u32(row: &Row, column: &str) -> anyhow::Result<u32> {
+    Ok(u32::try_from(row.try_get::<_, i64>(column)?)?)
+}
+
+fn get_optional_u32(row: &Row, column: &str) -> anyhow::Result<Option<u32>> {
+    Ok(row
+        .try_get::<_, Option<i64>>(column)?
+        .map(u32::try_from)
+        .transpose()?)
+}
+
+fn get_count(row: &Row) -> anyhow::Result<u64> {
+    Ok(u64::try_from(row.try_get::<_, i64>(0)?)?)
+}
+
+fn time_key(time: OffsetDateTime) -> i64 {
+    time.unix_timestamp_nanos() as i64
+}
+
+/// The timestamp and id to page from, turning the sort key back into a time
+fn cursor_bounds(cursor: Option<Cursor>) -> anyhow::Result<(OffsetDateTime, i64)> {
+    let cursor = cursor.unwrap_or(Cursor::START);
+    Ok((
+        OffsetDateTime::from_unix_timestamp_nanos(cursor.key.into())?,
+        cursor.id.into(),
+    ))
+}
+
+/// Like [`crate::queries::read_page`], for rows fetched with `limit + 1` that
+/// have `id` and `timestamp` columns
+fn read_page<T>(
+    rows: &[Row],
+    limit: u32,
+    total: u64,
+    read: impl Fn(&Row) -> anyhow::Result<T>,
+) -> anyhow::Result<Page<T>> {
+    let mut items = Vec::new();
+    let mut next_cursor = None;
+
+    for (i, row) in rows.iter().enumerate() {
+        if i == limit as usize {
+            let last = &rows[i - 1];
+            next_cursor = Some(Cursor {
+                key: time_key(last.try_get("timestamp")?),
+                id: get_u32(last, "id")?,
+            });
+            break;
+        }
+        items.push(read(row)?);
+    }
+
+    Ok(Page {
+        items,
+        next_cursor,
+        total,
+    })
+}
+
+fn read_source(row: &Row) -> anyhow::Result<Source> {
+    Ok(Source {
+        id: get_u32(row, "id")?,
+        user_id: get_u32(row, "user_id")?,
+        url: row.try_get("url")?,
+        last_checked: row.try_get("last_checked")?,
+        enabled: row.try_get("enabled")?,
+        failed_count: get_u32(row, "failed_count")?,
+        redirect_url: row.try_get("redirect_url")?,
+    })
+}
+
+fn read_activity(row: &Row) -> anyhow::Result<Activity> {
+    Ok(Activity {
+        id: get_u32(row, "id")?,
+        source_url: row.try_get("source_url")?,
+        post_url: row.try_get("post_url")?,
+        timestamp: row.try_get("timestamp")?,
+    })
+}
+
+fn read_tab(row: &Row) -> anyhow::Result<RTab> {
+    Ok(RTab {
+        id: row.try_get("id")?,
+        name: row.try_get("name")?,
+        slug: row.try_get("slug")?,
+        db_id: get_optional_u32(row, "db_id")?,
+    })
+}
+
+fn read_change(row: &Row) -> anyhow::Result<RDBChangeAlt> {
+    Ok(RDBChangeAlt {
+        id: get_u32(row, "id")?,
+        r#type: row.try_get("type")?,
+        previous_card_id: row.try_get("previous_card_id")?,
+        previous_card_name: row.try_get("previous_card_name")?,
+        previous_card_description: row.try_get("previous_card_description")?,
+        previous_card_image_url: row.try_get("previous_card_image_url")?,
+        previous_card_slug: row.try_get("previous_card_slug")?,
+        previous_card_db_id: get_optional_u32(row, "previous_card_db_id")?,
+        current_card_id: row.try_get("current_card_id")?,
+        current_card_name: row.try_get("current_card_name")?,
+        current_card_description: row.try_get("current_card_description")?,
+        current_card_image_url: row.try_get("current_card_image_url")?,
+        current_card_slug: row.try_get("current_card_slug")?,
+        current_card_db_id: get_optional_u32(row, "current_card_db_id")?,
+        tab_id: row.try_get("tab_id")?,
+        tab_name: row.try_get("tab_name")?,
+        tab_slug: row.try_get("tab_slug")?,
+        tab_db_id: get_optional_u32(row, "tab_db_id")?,
+        card_tab_name: row.try_get("card_tab_name")?,
+    })
+}
+
+fn read_hit(row: &Row, kind: SearchKind) -> anyhow::Result<SearchHit> {
+    let (snippet, highlights) = parse_snippet(&row.try_get::<_, String>("snippet")?);
+
+    Ok(SearchHit {
+        kind,
+        id: get_u32(row, "id")?,
+        title: row.try_get("title")?,
+        url: row.try_get("url")?,
+        source_id: get_optional_u32(row, "source_id")?,
+        card_id: row.try_get("card_id")?,
+        snippet,
+        highlights,
+        rank: row.try_get("rank")?,
+        timestamp: row.try_get("timestamp")?,
+    })
+}
+
+/// Turns a search into a tsquery needing every word, where the last word can
+/// be the start of a word. None if there are no words.
+fn ts_query(q: &str) -> Option<String> {
+    let terms = q
+        .split_whitespace()
+        .map(|term| format!("'{}'", term.replace('\\', "\\\\").replace('\'', "''")))
+        .collect::<Vec<_>>();
+
+    (!terms.is_empty()).then(|| terms.join(" & ") + ":*")
+}
+
+/// ts_headline options marking matches the way fts5's snippet does
+fn headline_options() -> String {
+    format!(
+        "StartSel={HIGHLIGHT_START}, StopSel={HIGHLIGHT_END}, MaxWords={SNIPPET_TOKENS}, MinWords={}",
+        SNIPPET_TOKENS / 2
+    )
+}
+
+#[async_trait]
+impl SourcesRepo for PostgresRepo {
+    async fn get_all_sources(&self) -> anyhow::Result<Vec<Source>> {
+        self.client()
+            .await?
+            .query(&format!("SELECT * FROM {SOURCES_T} ORDER BY id"), &[])
+            .await?
+            .iter()
+            .map(read_source)
+            .collect()
+    }
+
+    async fn get_sources(&self, user_id: u32) -> anyhow::Result<Vec<Source>> {
+        self.client()
+            .await?
+            .query(
+                &format!("SELECT * FROM {SOURCES_T} WHERE user_id = $1 ORDER BY id"),
+                &[&i64::from(user_id)],
+            )
+            .await?
+            .iter()
+            .map(read_source)
+            .collect()
+    }
+
+    async fn get_source(&self, user_id: u32, source_id: u32) -> anyhow::Result<Option<Source>> {
+        self.client()
+            .await?
+            .query_opt(
+                &format!("SELECT * FROM {SOURCES_T} WHERE id = $1 AND user_id = $2"),
+                &[&i64::from(source_id), &i64::from(user_id)],
+            )
+            .await?
+            .as_ref()
+            .map(read_source)
+            .transpose()
+    }
+
+    async fn add_source(&self, user_id: u32, url: &str) -> anyhow::Result<u32> {
+        let row = self
+            .client()
+            .await?
+            .query_one(
+                &format!(
+                    "INSERT INTO {SOURCES_T} (user_id, url, last_checked) VALUES ($1, $2, $3) RETURNING id"
+                ),
+                &[
+                    &i64::from(user_id),
+                    &url,
+                    &(OffsetDateTime::now_utc() - 1.hours()),
+                ],
+            )
+            .await?;
+
+        get_u32(&row, "id")
+    }
+
+    async fn delete_source(&self, user_id: u32, source_id: u32) -> anyhow::Result<u64> {
+        Ok(self
+            .client()
+            .await?
+            .execute(
+                &format!("DELETE FROM {SOURCES_T} WHERE id = $1 AND user_id = $2"),
+                &[&i64::from(source_id), &i64::from(user_id)],
+            )
+            .await?)
+    }
+
+    async fn set_source_enabled(
+        &self,
+        user_id: u32,
+        source_id: u32,
+        enabled: bool,
+    ) -> anyhow::Result<u64> {
+        Ok(self
+            .client()
+            .await?
+            .execute(
+                &format!(
+                    "UPDATE {SOURCES_T} SET failed_count = 0, enabled = $1 WHERE id = $2 AND user_id = $3"
+                ),
+                &[&enabled, &i64::from(source_id), &i64::from(user_id)],
+            )
+            .await?)
+    }
+
+    async fn accept_redirect(&self, user_id: u32, source_id: u32) -> anyhow::Result<bool> {
+        // The joined row still has the url from before the update
+        let moved = self
+            .client()
+            .await?
+            .execute(
+                &format!(
+                    "WITH moved AS (
+                        UPDATE {SOURCES_T} AS s
+                        SET url = s.redirect_url, redirect_url = NULL, failed_count = 0
+                        FROM {SOURCES_T} AS old
+                        WHERE old.id = s.id
+                            AND s.id = $1
+                            AND s.user_id = $2
+                            AND s.redirect_url IS NOT NULL
+                        RETURNING s.id, old.url AS old_url, s.url AS new_url
+                    )
+                    INSERT INTO {SOURCE_EVENTS_T} (source_id, type, message, timestamp)
+                    SELECT id, 'url_updated', 'Accepted redirect from ' || old_url || ' to ' || new_url, $3
+                    FROM moved"
+                ),
+                &[
+                    &i64::from(source_id),
+                    &i64::from(user_id),
+                    &OffsetDateTime::now_utc(),
+                ],
+            )
+            .await?;
+
+        Ok(moved > 0)
+    }
+
+    async fn get_source_events(
+        &self,
+        user_id: u32,
+        source_id: u32,
+    ) -> anyhow::Result<Vec<SourceEvent>> {
+        self.client()
+            .await?
+            .query(
+                &format!(
+                    "SELECT e.* FROM {SOURCE_EVENTS_T} AS e
+                    INNER JOIN {SOURCES_T} AS s
+                        ON e.source_id = s.id
+                    WHERE e.source_id = $1 AND s.user_id = $2
+                    ORDER BY e.id DESC"
+                ),
+                &[&i64::from(source_id), &i64::from(user_id)],
+            )
+            .await?
+            .iter()
+            .map(|row| {
+                Ok(SourceEvent {
+                    id: get_u32(row, "id")?,
+                    source_id: get_u32(row, "source_id")?,
+                    r#type: row.try_get("type")?,
+                    message: row.try_get("message")?,
+                    timestamp: row.try_get("timestamp")?,
+                })
+            })
+            .collect()
+    }
+
+    async fn add_source_event(
+        &self,
+        source_id: u32,
+        event_type: &str,
+        message: &str,
+    ) -> anyhow::Result<u64> {
+        Ok(self
+            .client()
+            .await?
+            .execute(
+                &format!(
+                    "INSERT INTO {SOURCE_EVENTS_T} (source_id, type, message, timestamp) VALUES ($1, $2, $3, $4)"
+                ),
+                &[
+                    &i64::from(source_id),
+                    &event_type,
+                    &message,
+                    &OffsetDateTime::now_utc(),
+                ],
+            )
+            .await?)
+    }
+
+    async fn move_source(&self, source_id: u32, url: &str) -> anyhow::Result<u64> {
+        Ok(self
+            .client()
+            .await?
+            .execute(
+                &format!("UPDATE {SOURCES_T} SET url = $1, redirect_url = NULL WHERE id = $2"),
+                &[&url, &i64::from(source_id)],
+            )
+            .await?)
+    }
+
+    async fn suggest_redirect(&self, source_id: u32, url: &str) -> anyhow::Result<u64> {
+        Ok(self
+            .client()
+            .await?
+            .execute(
+                &format!(
+                    "UPDATE {SOURCES_T} SET redirect_url = $1 WHERE id = $2 AND redirect_url IS DISTINCT FROM $1"
+                ),
+                &[&url, &i64::from(source_id)],
+            )
+            .await?)
+    }
+
+    async fn set_failed_count(
+        &self,
+        source_id: u32,
+        failed_count: u32,
+        enabled: bool,
+    ) -> anyhow::Result<u64> {
+        Ok(self
+            .client()
+            .await?
+            .execute(
+                &format!("UPDATE {SOURCES_T} SET failed_count = $1, enabled = $2 WHERE id = $3"),
+                &[&i64::from(failed_count), &enabled, &i64::from(source_id)],
+            )
+            .await?)
+    }
+
+    async fn set_last_checked(
+        &self,
+        source_id: u32,
+        last_checked: OffsetDateTime,
+    ) -> anyhow::Result<u64> {
+        Ok(self
+            .client()
+            .await?
+            .execute(
+                &format!(
+                    "UPDATE {SOURCES_T} SET last_checked = $1, failed_count = 0 WHERE id = $2"
+                ),
+                &[&last_checked, &i64::from(source_id)],
+            )
+            .await?)
+    }
+}
+
+#[async_trait]
+impl ActivityRepo for PostgresRepo {
+    async fn get_activity(
+        &self,
+        user_id: u32,
+        limit: u32,
+        cursor: Option<Cursor>,
+    ) -> anyhow::Result<Page<Activity>> {
+        let limit = limit.max(1);
+        let (before, before_id) = cursor_bounds(cursor)?;
+        let client = self.client().await?;
+
+        let total = get_count(
+            &client
+                .query_one(
+                    &format!(
+                        "SELECT COUNT(*)
+                        FROM {ACTIVITIES_T} AS a
+                        INNER JOIN {SOURCES_T} AS s
+                            ON a.source_id = s.id
+                        WHERE s.user_id = $1"
+                    ),
+                    &[&i64::from(user_id)],
+                )
+                .await?,
+        )?;
+
+        let rows = client
+            .query(
+                &format!(
+                    "SELECT a.id, a.post_url, a.timestamp, s.url AS source_url
+                    FROM {ACTIVITIES_T} AS a
+                    INNER JOIN {SOURCES_T} AS s
+                        ON a.source_id = s.id
+                    WHERE s.user_id = $1 AND (a.timestamp, a.id) < ($2, $3)
+                    ORDER BY a.timestamp DESC, a.id DESC
+                    LIMIT $4"
+                ),
+                &[
+                    &i64::from(user_id),
+                    &before,
+                    &before_id,
+                    &i64::from(limit + 1),
+                ],
+            )
+            .await?;
+
+        read_page(&rows, limit, total, read_activity)
+    }
+
+    async fn get_source_activity(
+        &self,
+        user_id: u32,
+        limit: u32,
+        cursor: Option<Cursor>,
+        source_id: u32,
+    ) -> anyhow::Result<Page<Activity>> {
+        let limit = limit.max(1);
+        let (before, before_id) = cursor_bounds(cursor)?;
+        let client = self.client().await?;
+
+        let total = get_count(
+            &client
+                .query_one(
+                    &format!(
+                        "SELECT COUNT(*)
+                        FROM {ACTIVITIES_T} AS a
+                        INNER JOIN {SOURCES_T} AS s
+                            ON a.source_id = s.id
+                        WHERE a.source_id = $1 AND s.user_id = $2"
+                    ),
+                    &[&i64::from(source_id), &i64::from(user_id)],
+                )
+                .await?,
+        )?;
+
+        let rows = client
+            .query(
+                &format!(
+                    "SELECT a.id, a.post_url, a.timestamp, s.url AS source_url
+                    FROM {ACTIVITIES_T} AS a
+                    INNER JOIN {SOURCES_T} AS s
+                        ON a.source_id = s.id
+                    WHERE a.source_id = $1 AND s.user_id = $2 AND (a.timestamp, a.id) < ($3, $4)
+                    ORDER BY a.timestamp DESC, a.id DESC
+                    LIMIT $5"
+                ),
+                &[
+                    &i64::from(source_id),
+                    &i64::from(user_id),
+                    &before,
+                    &before_id,
+                    &i64::from(limit + 1),
+                ],
+            )
+            .await?;
+
+        read_page(&rows, limit, total, read_activity)
+    }
+
+    async fn add_activity(
+        &self,
+        source_id: u32,
+        post_url: &str,
+        title: &str,
+        body: &str,
+    ) -> anyhow::Result<u64> {
+        Ok(self
+            .client()
+            .await?
+            .execute(
+                &format!(
+                    "INSERT INTO {ACTIVITIES_T} (source_id, post_url, timestamp, title, body) VALUES ($1, $2, $3, $4, $5)"
+                ),
+                &[
+                    &i64::from(source_id),
+                    &post_url,
+                    &OffsetDateTime::now_utc(),
+                    &title,
+                    &body,
+                ],
+            )
+            .await?)
+    }
+
+    async fn delete_activities(&self, user_id: u32) -> anyhow::Result<u64> {
+        Ok(self
+            .client()
+            .await?
+            .execute(
+                &format!(
+                    "DELETE FROM {ACTIVITIES_T}
+                    WHERE source_id IN (SELECT id FROM {SOURCES_T} WHERE user_id = $1)"
+                ),
+                &[&i64::from(user_id)],
+            )
+            .await?)
+    }
+
+    async fn delete_oldest_activities(&self, user_id: u32, count: u32) -> anyhow::Result<u64> {
+        Ok(self
+            .client()
+            .await?
+            .execute(
+                &format!(
+                    "DELETE FROM {ACTIVITIES_T}
+                    WHERE id IN (
+                        SELECT a.id
+                        FROM {ACTIVITIES_T} AS a
+                        INNER JOIN {SOURCES_T} AS s
+                            ON a.source_id = s.id
+                        WHERE s.user_id = $1
+                        ORDER BY a.id ASC
+                        LIMIT $2
+                    )"
+                ),
+                &[&i64::from(user_id), &i64::from(count)],
+            )
+            .await?)
+    }
+
+    async fn search_activities(
+        &self,
+        user_id: u32,
+        q: &str,
+        filters: &SearchFilters,
+        limit: u32,
+    ) -> anyhow::Result<Vec<SearchHit>> {
+        let Some(query) = ts_query(q) else {
+            return Ok(Vec::new());
+        };
+
+        self.client()
+            .await?
+            .query(
+                &format!(
+                    "SELECT
+                        a.id,
+                        COALESCE(a.title, a.post_url) AS title,
+                        a.post_url AS url,
+                        a.source_id,
+                        NULL::TEXT AS card_id,
+                        a.timestamp,
+                        ts_headline(
+                            'simple',
+                            COALESCE(a.title, '') || ' ' || COALESCE(a.body, '') || ' ' || a.post_url,
+                            q,
+                            $2
+                        ) AS snippet,
+                        -ts_rank(a.search, q)::FLOAT8 AS rank
+                    FROM {ACTIVITIES_T} AS a
+                    INNER JOIN {SOURCES_T} AS s
+                        ON a.source_id = s.id
+                    CROSS JOIN to_tsquery('simple', $1) AS q
+                    WHERE a.search @@ q
+                        AND s.user_id = $3
+                        AND ($4::BIGINT IS NULL OR a.source_id = $4)
+                        AND ($5::TIMESTAMPTZ IS NULL OR a.timestamp >= $5)
+                        AND ($6::TIMESTAMPTZ IS NULL OR a.timestamp < $6)
+                    ORDER BY rank
+                    LIMIT $7"
+                ),
+                &[
+                    &query,
+                    &headline_options(),
+                    &i64::from(user_id),
+                    &filters.source_id.map(i64::from),
+                    &filters.from,
+                    &filters.to,
+                    &i64::from(limit),
+                ],
+            )
+            .await?
+            .iter()
+            .map(|row| read_hit(row, SearchKind::Activity))
+            .collect()
+    }
+
+    async fn delete_activities_before(&self, cutoff: OffsetDateTime) -> anyhow::Result<u64> {
+        Ok(self
+            .client()
+            .await?
+            .execute(
+                &format!("DELETE FROM {ACTIVITIES_T} WHERE timestamp < $1"),
+                &[&cutoff],
+            )
+            .await?)
+    }
+
+    async fn delete_activities_over_limit(&self, keep: u32) -> anyhow::Result<u64> {
+        Ok(self
+            .client()
+            .await?
+            .execute(
+                &format!(
+                    "DELETE FROM {ACTIVITIES_T}
+                    WHERE id IN (
+                        SELECT id FROM (
+                            SELECT
+                                id,
+                                ROW_NUMBER() OVER (PARTITION BY source_id ORDER BY timestamp DESC, id DESC) AS n
+                            FROM {ACTIVITIES_T}
+                        ) AS numbered
+                        WHERE n > $1
+                    )"
+                ),
+                &[&i64::from(keep)],
+            )
+            .await?)
+    }
+}
+
+/// A column of the tab the card of change `rch` was on. That's its assignment
+/// in the change's check, or the one before it for removed cards.
+fn card_tab_sql(column: &str) -> String {
+    format!(
+        "(
+            SELECT rct.{column}
+            FROM {R_CARD_ASSIGNS_T} AS rca
+            INNER JOIN {R_TABS_T} AS rct
+                ON rca.tab_id = rct.id
+            WHERE rca.card_id = COALESCE(rch.current_card_id, rch.previous_card_id)
+                AND rca.activity_id <= rch.activity_id
+            ORDER BY rca.activity_id DESC
+            LIMIT 1
+        )"
+    )
+}
+
+/// Roadmap ids of the tabs a user watches
+fn watched_tabs_sql(user_param: &str) -> String {
+    format!("SELECT tab_roadmap_id FROM {R_WATCHED_TABS_T} WHERE user_id = {user_param}")
+}
+
+/// Whether the user watches the tab change `rch` is about, `rt` being the tab
+/// of tab changes
+fn watched_change_sql(user_param: &str) -> String {
+    format!(
+        "COALESCE(rt.roadmap_id, {}) IN ({})",
+        card_tab_sql("roadmap_id"),
+        watched_tabs_sql(user_param)
+    )
+}
+
+/// Selects changes with the cards and tabs they point at
+fn changes_sql(filter: &str) -> String {
+    let card_tab_name = card_tab_sql("name");
+    format!(
+        "SELECT
+            rch.activity_id, rch.timestamp,
+            rch.id, rch.type,
+
+            rc1.id AS previous_card_db_id, rc1.roadmap_id AS previous_card_id,
+            rc1.name AS previous_card_name, rc1.description AS previous_card_description,
+            rc1.image_url AS previous_card_image_url, rc1.slug AS previous_card_slug,
+
+            rc2.id AS current_card_db_id, rc2.roadmap_id AS current_card_id,
+            rc2.name AS current_card_name, rc2.description AS current_card_description,
+            rc2.image_url AS current_card_image_url, rc2.slug AS current_card_slug,
+
+            rt.id AS tab_db_id, rt.roadmap_id AS tab_id,
+            rt.name AS tab_name, rt.slug AS tab_slug,
+
+            {card_tab_name} AS card_tab_name
+        FROM {R_CHANGES_T} AS rch
+        LEFT JOIN {R_CARDS_T} AS rc1
+            ON rch.previous_card_id = rc1.id
+        LEFT JOIN {R_CARDS_T} AS rc2
+            ON rch.current_card_id = rc2.id
+        LEFT JOIN {R_TABS_T} AS rt
+            ON rch.tab_id = rt.id
+        WHERE {filter}
+        ORDER BY rch.id
+        "
+    )
+}
+
+impl PostgresRepo {
+    async fn get_roadmap_tabs(
+        &self,
+        client: &Object,
+        activity_id: u32,
+    ) -> anyhow::Result<Vec<RTab>> {
+        client
+            .query(
+                &format!(
+                    "SELECT
+                        ra.tab_id AS db_id,
+                        rt.roadmap_id AS id,
+                        rt.name,
+                        rt.slug
+                    FROM {R_TAB_ASSIGNS_T} AS ra
+                    INNER JOIN {R_TABS_T} AS rt
+                        ON ra.tab_id = rt.id
+                    WHERE ra.activity_id = $1"
+                ),
+                &[&i64::from(activity_id)],
+            )
+            .await?
+            .iter()
+            .map(read_tab)
+            .collect()
+    }
+
+    async fn most_recent_roadmap_id(&self, client: &Object) -> anyhow::Result<Option<u32>> {
+        client
+            .query_opt(
+                &format!("SELECT id FROM {R_ACTIVITIES_T} ORDER BY id DESC LIMIT 1"),
+                &[],
+            )
+            .await?
+            .map(|row| get_u32(&row, "id"))
+            .transpose()
+    }
+}
+
+#[async_trait]
+impl RoadmapRepo for PostgresRepo {
+    async fn get_most_recent_roadmap(&self) -> anyhow::Result<Option<Roadmap>> {
+        let client = self.client().await?;
+        let Some(activity_id) = self.most_recent_roadmap_id(&client).await? else {
+            return Ok(None);
+        };
+        drop(client);
+
+        self.get_roadmap(activity_id).await.map(Some)
+    }
+
+    async fn get_roadmap(&self, activity_id: u32) -> anyhow::Result<Roadmap> {
+        let client = self.client().await?;
+        let tabs = self.get_roadmap_tabs(&client, activity_id).await?;
+
+        let rows = client
+            .query(
+                &format!(
+                    "SELECT
+                        ra.id AS assign_db_id,
+                        ra.tab_id,
+                        ra.card_id AS db_id,
+                        ra.section_position,
+                        ra.card_position,
+
+                        rc.roadmap_id AS id,
+                        rc.name,
+                        rc.description,
+                        rc.image_url,
+                        rc.slug
+                    FROM {R_CARD_ASSIGNS_T} AS ra
+                    INNER JOIN {R_CARDS_T} AS rc
+                        ON ra.card_id = rc.id
+                    WHERE ra.activity_id = $1"
+                ),
+                &[&i64::from(activity_id)],
+            )
+            .await?;
+
+        let mut cards: HashMap<String, Vec<RCard>> = HashMap::new();
+        for row in &rows {
+            let tab_db_id = get_u32(row, "tab_id")?;
+            let Some(tab) = tabs.iter().find(|t| t.db_id == Some(tab_db_id)) else {
+                return Err(StringError(format!(
+                    "Card assignment points at tab {tab_db_id} which isn't in check {activity_id}"
+                ))
+                .into());
+            };
+
+            cards.entry(tab.id.clone()).or_default().push(RCard {
+                id: row.try_get("id")?,
+                name: row.try_get("name")?,
+                description: row.try_get("description")?,
+                image_url: row.try_get("image_url")?,
+                slug: row.try_get("slug")?,
+                db_id: Some(get_u32(row, "db_id")?),
+                section_position: Some(get_u32(row, "section_position")?),
+                card_position: Some(get_u32(row, "card_position")?),
+                assign_db_id: Some(get_u32(row, "assign_db_id")?),
+                tab_id: Some(tab_db_id),
+            });
+        }
+
+        cards
+            .values_mut()
+            .for_each(|c| c.sort_by_key(|c| c.id.clone()));
+
+        Ok(Roadmap::with_data(tabs, cards))
+    }
+
+    async fn roadmap_activity_exists(&self, activity_id: u32) -> anyhow::Result<bool> {
+        Ok(self
+            .client()
+            .await?
+            .query_opt(
+                &format!("SELECT 1 FROM {R_ACTIVITIES_T} WHERE id = $1"),
+                &[&i64::from(activity_id)],
+            )
+            .await?
+            .is_some())
+    }
+
+    async fn get_roadmap_activities(
+        &self,
+        user_id: u32,
+        limit: u32,
+        cursor: Option<Cursor>,
+    ) -> anyhow::Result<Page<RoadmapActivity>> {
+        let limit = limit.max(1);
+        let (before, before_id) = cursor_bounds(cursor)?;
+        let client = self.client().await?;
+        let watched = watched_change_sql("$4");
+
+        let total = get_count(
+            &client
+                .query_one(&format!("SELECT COUNT(*) FROM {R_ACTIVITIES_T}"), &[])
+                .await?,
+        )?;
+
+        let rows = client
+            .query(
+                &format!(
+                    "SELECT
+                        ra.id,
+                        ra.timestamp,
+                        rch.count AS change_count
+                    FROM {R_ACTIVITIES_T} AS ra
+                    LEFT JOIN (
+                        SELECT rch.activity_id, COUNT(rch.id) AS count FROM {R_CHANGES_T} AS rch
+                        LEFT JOIN {R_TABS_T} AS rt
+                            ON rch.tab_id = rt.id
+                        WHERE rch.type IN ('tab_removed', 'tab_added', 'card_removed', 'card_added', 'card_modified')
+                            AND {watched}
+                        GROUP BY rch.activity_id
+                    ) rch
+                        ON ra.id = rch.activity_id
+                    WHERE (ra.timestamp, ra.id) < ($1, $2)
+                    ORDER BY ra.timestamp DESC, ra.id DESC
+                    LIMIT $3"
+                ),
+                &[
+                    &before,
+                    &before_id,
+                    &i64::from(limit + 1),
+                    &i64::from(user_id),
+                ],
+            )
+            .await?;
+
+        read_page(&rows, limit, total, |row| {
+            Ok(RoadmapActivity {
+                id: get_u32(row, "id")?,
+                change_count: get_optional_u32(row, "change_count")?,
+                timestamp: row.try_get("timestamp")?,
+            })
+        })
+    }
+
+    async fn get_most_recent_roadmap_tabs(&self) -> anyhow::Result<Vec<RTab>> {
+        let client = self.client().await?;
+        match self.most_recent_roadmap_id(&client).await? {
+            Some(activity_id) => self.get_roadmap_tabs(&client, activity_id).await,
+            None => Ok(Vec::new()),
+        }
+    }
+
+    async fn get_roadmap_changes(
+        &self,
+        user_id: u32,
+        activity_id: u32,
+    ) -> anyhow::Result<Vec<RDBChangeAlt>> {
+        let filter = format!("rch.activity_id = $1 AND {}", watched_change_sql("$2"));
+        self.client()
+            .await?
+            .query(
+                &changes_sql(&filter),
+                &[&i64::from(activity_id), &i64::from(user_id)],
+            )
+            .await?
+            .iter()
+            .map(read_change)
+            .collect()
+    }
+
+    async fn get_card_history(
+        &self,
+        user_id: u32,
+        card_id: &str,
+    ) -> anyhow::Result<Option<RCardHistory>> {
+        let client = self.client().await?;
+        let watched = watched_tabs_sql("$2");
+
+        let mut revisions = client
+            .query(
+                &format!(
+                    "WITH revisions AS (
+                        SELECT
+                            rc.id,
+                            MIN(rca.activity_id) AS first_activity_id,
+                            MAX(rca.activity_id) AS last_activity_id
+                        FROM {R_CARDS_T} AS rc
+                        INNER JOIN {R_CARD_ASSIGNS_T} AS rca
+                            ON rca.card_id = rc.id
+                        INNER JOIN {R_TABS_T} AS rt
+                            ON rt.id = rca.tab_id
+                        WHERE rc.roadmap_id = $1
+                            AND rt.roadmap_id IN ({watched})
+                        GROUP BY rc.id
+                    )
+                    SELECT
+                        rc.id AS db_id,
+                        rc.name,
+                        rc.description,
+                        rc.image_url,
+                        rc.slug,
+                        r.first_activity_id,
+                        fa.timestamp AS first_seen,
+                        r.last_activity_id,
+                        la.timestamp AS last_seen
+                    FROM revisions AS r
+                    INNER JOIN {R_CARDS_T} AS rc
+                        ON rc.id = r.id
+                    INNER JOIN {R_ACTIVITIES_T} AS fa
+                        ON fa.id = r.first_activity_id
+                    INNER JOIN {R_ACTIVITIES_T} AS la
+                        ON la.id = r.last_activity_id
+                    ORDER BY r.first_activity_id, rc.id"
+                ),
+                &[&card_id, &i64::from(user_id)],
+            )
+            .await?
+            .iter()
+            .map(|row| {
+                Ok(RCardRevision {
+                    db_id: get_u32(row, "db_id")?,
+                    name: row.try_get("name")?,
+                    description: row.try_get("description")?,
+                    image_url: row.try_get("image_url")?,
+                    slug: row.try_get("slug")?,
+                    first_activity_id: get_u32(row, "first_activity_id")?,
+                    first_seen: row.try_get("first_seen")?,
+                    last_activity_id: get_u32(row, "last_activity_id")?,
+                    last_seen: row.try_get("last_seen")?,
+                    placements: Vec::new(),
+                })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        // Tabs that were pruned and came back have a new row, so they're told
+        // apart by roadmap id. The name is looked up from the last check.
+        let placements = client
+            .query(
+                &format!(
+                    "SELECT
+                        p.card_id,
+                        p.tab_id,
+                        (
+                            SELECT rt.name
+                            FROM {R_CARD_ASSIGNS_T} AS rca
+                            INNER JOIN {R_TABS_T} AS rt
+                                ON rt.id = rca.tab_id
+                            WHERE rca.card_id = p.card_id
+                                AND rca.activity_id = p.last_activity_id
+                                AND rca.section_position = p.section_position
+                                AND rt.roadmap_id = p.tab_id
+                            LIMIT 1
+                        ) AS tab_name,
+                        p.section_position,
+                        p.first_activity_id,
+                        p.last_activity_id
+                    FROM (
+                        SELECT
+                            rca.card_id,
+                            rt.roadmap_id AS tab_id,
+                            rca.section_position,
+                            MIN(rca.activity_id) AS first_activity_id,
+                            MAX(rca.activity_id) AS last_activity_id
+                        FROM {R_CARD_ASSIGNS_T} AS rca
+                        INNER JOIN {R_CARDS_T} AS rc
+                            ON rc.id = rca.card_id
+                        INNER JOIN {R_TABS_T} AS rt
+                            ON rt.id = rca.tab_id
+                        WHERE rc.roadmap_id = $1
+                            AND rt.roadmap_id IN ({watched})
+                        GROUP BY rca.card_id, rt.roadmap_id, rca.section_position
+                    ) AS p
+                    ORDER BY p.first_activity_id"
+                ),
+                &[&card_id, &i64::from(user_id)],
+            )
+            .await?;
+
+        for row in &placements {
+            let revision_id = get_u32(row, "card_id")?;
+            if let Some(revision) = revisions.iter_mut().find(|rev| rev.db_id == revision_id) {
+                revision.placements.push(RCardPlacement {
+                    tab_id: row.try_get("tab_id")?,
+                    tab_name: row.try_get("tab_name")?,
+                    section_position: get_u32(row, "section_position")?,
+                    first_activity_id: get_u32(row, "first_activity_id")?,
+                    last_activity_id: get_u32(row, "last_activity_id")?,
+                });
+            }
+        }
+
+        let filter = format!(
+            "(rc1.roadmap_id = $1 OR rc2.roadmap_id = $1) AND {}",
+            watched_change_sql("$2")
+        );
+        let changes = client
+            .query(&changes_sql(&filter), &[&card_id, &i64::from(user_id)])
+            .await?
+            .iter()
+            .map(|row| {
+                Ok(RCardChange {
+                    activity_id: get_u32(row, "activity_id")?,
+                    timestamp: row.try_get("timestamp")?,
+                    change: read_change(row)?,
+                })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        if revisions.is_empty() && changes.is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some(RCardHistory {
+            card_id: card_id.to_owned(),
+            revisions,
+            changes,
+        }))
+    }
+
+    async fn get_watched_tabs(&self, user_id: u32) -> anyhow::Result<Vec<RoadmapWatchedTab>> {
+        self.client()
+            .await?
+            .query(
+                &format!("SELECT * FROM {R_WATCHED_TABS_T} WHERE user_id = $1 ORDER BY id"),
+                &[&i64::from(user_id)],
+            )
+            .await?
+            .iter()
+            .map(|row| {
+                Ok(RoadmapWatchedTab {
+                    id: get_u32(row, "id")?,
+                    tab_id: row.try_get("tab_roadmap_id")?,
+                    timestamp: row.try_get("timestamp")?,
+                })
+            })
+            .collect()
+    }
+
+    async fn add_watched_tab(&self, user_id: u32, tab_id: &str) -> anyhow::Result<u32> {
+        let row = self
+            .client()
+            .await?
+            .query_one(
+                &format!(
+                    "INSERT INTO {R_WATCHED_TABS_T} (user_id, tab_roadmap_id, timestamp) VALUES ($1, $2, $3) RETURNING id"
+                ),
+                &[&i64::from(user_id), &tab_id, &OffsetDateTime::now_utc()],
+            )
+            .await?;
+
+        get_u32(&row, "id")
+    }
+
+    async fn delete_watched_tab(&self, user_id: u32, id: u32) -> anyhow::Result<u64> {
+        Ok(self
+            .client()
+            .await?
+            .execute(
+                &format!("DELETE FROM {R_WATCHED_TABS_T} WHERE id = $1 AND user_id = $2"),
+                &[&i64::from(id), &i64::from(user_id)],
+            )
+            .await?)
+    }
+
+    async fn get_all_watched_tab_ids(&self) -> anyhow::Result<Vec<String>> {
+        self.client()
+            .await?
+            .query(
+                &format!("SELECT DISTINCT tab_roadmap_id FROM {R_WATCHED_TABS_T}"),
+                &[],
+            )
+            .await?
+            .iter()
+            .map(|row| Ok(row.try_get(0)?))
+            .collect()
+    }
+
+    async fn search_cards(
+        &self,
+        user_id: u32,
+        q: &str,
+        filters: &SearchFilters,
+        limit: u32,
+    ) -> anyhow::Result<Vec<SearchHit>> {
+        let Some(query) = ts_query(q) else {
+            return Ok(Vec::new());
+        };
+
+        self.client()
+            .await?
+            .query(
+                &format!(
+                    "SELECT
+                        rc.id,
+                        rc.name AS title,
+                        NULL::TEXT AS url,
+                        NULL::BIGINT AS source_id,
+                        rc.roadmap_id AS card_id,
+                        rc.timestamp,
+                        ts_headline('simple', rc.name || ' ' || rc.description, q, $2) AS snippet,
+                        -ts_rank(rc.search, q)::FLOAT8 AS rank
+                    FROM {R_CARDS_T} AS rc
+                    CROSS JOIN to_tsquery('simple', $1) AS q
+                    WHERE rc.search @@ q
+                        AND EXISTS (
+                            SELECT 1
+                            FROM {R_CARD_ASSIGNS_T} AS ca
+                            INNER JOIN {R_TABS_T} AS t
+                                ON ca.tab_id = t.id
+                            INNER JOIN {R_WATCHED_TABS_T} AS wt
+                                ON wt.tab_roadmap_id = t.roadmap_id
+                            WHERE ca.card_id = rc.id
+                                AND wt.user_id = $7
+                                AND ($3::TEXT IS NULL OR t.roadmap_id = $3)
+                        )
+                        AND ($4::TIMESTAMPTZ IS NULL OR rc.timestamp >= $4)
+                        AND ($5::TIMESTAMPTZ IS NULL OR rc.timestamp < $5)
+                    ORDER BY rank
+                    LIMIT $6"
+                ),
+                &[
+                    &query,
+                    &headline_options(),
+                    &filters.tab_id,
+                    &filters.from,
+                    &filters.to,
+                    &i64::from(limit),
+                    &i64::from(user_id),
+                ],
+            )
+            .await?
+            .iter()
+            .map(|row| read_hit(row, SearchKind::Card))
+            .collect()
+    }
+
+    async fn begin_check(&self) -> anyhow::Result<Box<dyn RoadmapCheck>> {
+        let client = self.client().await?;
+        client.batch_execute("BEGIN").await?;
+
+        Ok(Box::new(PostgresCheck {
+            client: Some(client),
+        }))
+    }
+
+    async fn delete_roadmap_activities_before(
+        &self,
+        cutoff: OffsetDateTime,
+        only_unchanged: bool,
+    ) -> anyhow::Result<u64> {
+        Ok(self
+            .client()
+            .await?
+            .execute(
+                &format!(
+                    "DELETE FROM {R_ACTIVITIES_T} AS ra
+                    WHERE ra.timestamp < $1
+                        AND ra.id != (SELECT MAX(id) FROM {R_ACTIVITIES_T})
+                        AND (NOT $2 OR NOT EXISTS (
+                            SELECT 1 FROM {R_CHANGES_T} AS rch WHERE rch.activity_id = ra.id
+                        ))"
+                ),
+                &[&cutoff, &only_unchanged],
+            )
+            .await?)
+    }
+
+    async fn delete_unused_cards(&self) -> anyhow::Result<u64> {
+        Ok(self
+            .client()
+            .await?
+            .execute(
+                &format!(
+                    "DELETE FROM {R_CARDS_T} AS rc
+                    WHERE NOT EXISTS (SELECT 1 FROM {R_CARD_ASSIGNS_T} WHERE card_id = rc.id)
+                        AND NOT EXISTS (
+                            SELECT 1 FROM {R_CHANGES_T}
+                            WHERE previous_card_id = rc.id OR current_card_id = rc.id
+                        )"
+                ),
+                &[],
+            )
+            .await?)
+    }
+
+    async fn delete_unused_tabs(&self) -> anyhow::Result<u64> {
+        Ok(self
+            .client()
+            .await?
+            .execute(
+                &format!(
+                    "DELETE FROM {R_TABS_T} AS rt
+                    WHERE NOT EXISTS (SELECT 1 FROM {R_TAB_ASSIGNS_T} WHERE tab_id = rt.id)
+                        AND NOT EXISTS (SELECT 1 FROM {R_CARD_ASSIGNS_T} WHERE tab_id = rt.id)
+                        AND NOT EXISTS (SELECT 1 FROM {R_CHANGES_T} WHERE tab_id = rt.id)"
+                ),
+                &[],
+            )
+            .await?)
+    }
+}
+
+/// A roadmap check saved on one pooled connection inside BEGIN and COMMIT
+pub struct PostgresCheck {
+    client: Option<Object>,
+}
+
+impl PostgresCheck {
+    fn client(&self) -> anyhow::Result<&Object> {
+        self.client
+            .as_ref()
+            .ok_or_else(|| StringError("Roadmap check already finished".into()).into())
+    }
+
+    async fn finish(mut self: Box<Self>, statement: &str) -> anyhow::Result<()> {
+        let client = self
+            .client
+            .take()
+            .ok_or(StringError("Roadmap check already finished".into()))?;
+
+        if let Err(err) = client.batch_execute(statement).await {
+            // Closing the connection rolls back whatever is left
+            drop(Object::take(client));
+            return Err(err.into());
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for PostgresCheck {
+    /// A check dropped before it finished still has a transaction open, so
+    /// its connection is closed instead of going back to the pool
+    fn drop(&mut self) {
+        if let Some(client) = self.client.take() {
+            drop(Object::take(client));
+        }
+    }
+}
+
+#[async_trait]
+impl RoadmapCheck for PostgresCheck {
+    async fn new_roadmap(&self) -> anyhow::Result<u32> {
+        let row = self
+            .client()?
+            .query_one(
+                &format!("INSERT INTO {R_ACTIVITIES_T} (timestamp) VALUES ($1) RETURNING id"),
+                &[&OffsetDateTime::now_utc()],
+            )
+            .await?;
+
+        get_u32(&row, "id")
+    }
+
+    async fn save_card(&self, card: &RCard) -> anyhow::Result<u32> {
+        let content_hash = hash_card(
+            &card.id,
+            &card.name,
+            &card.description,
+            card.image_url.as_deref(),
+            &card.slug,
+        );
+        let client = self.client()?;
+
+        if let Some(row) = client
+            .query_opt(
+                &format!("SELECT id FROM {R_CARDS_T} WHERE content_hash = $1"),
+                &[&content_hash],
+            )
+            .await?
+        {
+            return get_u32(&row, "id");
+        }
+
+        let row = client
+            .query_one(
+                &format!(
+                    "INSERT INTO {R_CARDS_T}
+                        (roadmap_id, name, description, image_url, slug, timestamp, content_hash)
+                    VALUES
+                        ($1, $2, $3, $4, $5, $6, $7)
+                    RETURNING id"
+                ),
+                &[
+                    &card.id,
+                    &card.name,
+                    &card.description,
+                    &card.image_url,
+                    &card.slug,
+                    &OffsetDateTime::now_utc(),
+                    &content_hash,
+                ],
+            )
+            .await?;
+
+        get_u32(&row, "id")
+    }
+
+    async fn save_tab(&self, tab: &RTab) -> anyhow::Result<u32> {
+        let row = self
+            .client()?
+            .query_one(
+                &format!(
+                    "INSERT INTO {R_TABS_T} (roadmap_id, name, slug, timestamp) VALUES ($1, $2, $3, $4) RETURNING id"
+                ),
+                &[&tab.id, &tab.name, &tab.slug, &OffsetDateTime::now_utc()],
+            )
+            .await?;
+
+        get_u32(&row, "id")
+    }
+
+    async fn save_card_assignment(
+        &self,
+        card_id: u32,
+        assign_info: &[u32; 4],
+    ) -> anyhow::Result<()> {
+        self.client()?
+            .execute(
+                &format!(
+                    "INSERT INTO {R_CARD_ASSIGNS_T}
+                        (activity_id, tab_id, card_id, section_position, card_position, timestamp)
+                    VALUES
+                        ($1, $2, $3, $4, $5, $6)"
+                ),
+                &[
+                    &i64::from(assign_info[0]),
+                    &i64::from(assign_info[1]),
+                    &i64::from(card_id),
+                    &i64::from(assign_info[2]),
+                    &i64::from(assign_info[3]),
+                    &OffsetDateTime::now_utc(),
+                ],
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn save_tab_assignment(&self, activity_id: u32, tab_id: u32) -> anyhow::Result<()> {
+        self.client()?
+            .execute(
+                &format!(
+                    "INSERT INTO {R_TAB_ASSIGNS_T} (activity_id, tab_id, timestamp) VALUES ($1, $2, $3)"
+                ),
+                &[
+                    &i64::from(activity_id),
+                    &i64::from(tab_id),
+                    &OffsetDateTime::now_utc(),
+                ],
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn save_change(
+        &self,
+        change_type: &str,
+        activity_id: u32,
+        change_info: &[Option<u32>; 3],
+    ) -> anyhow::Result<()> {
+        self.client()?
+            .execute(
+                &format!(
+                    "INSERT INTO {R_CHANGES_T}
+                        (type, activity_id, previous_card_id, current_card_id, tab_id, timestamp)
+                    VALUES
+                        ($1, $2, $3, $4, $5, $6)"
+                ),
+                &[
+                    &change_type,
+                    &i64::from(activity_id),
+                    &change_info[0].map(i64::from),
+                    &change_info[1].map(i64::from),
+                    &change_info[2].map(i64::from),
+                    &OffsetDateTime::now_utc(),
+                ],
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn commit(self: Box<Self>) -> anyhow::Result<()> {
+        self.finish("COMMIT").await
+    }
+
+    async fn rollback(self: Box<Self>) -> anyhow::Result<()> {
+        self.finish("ROLLBACK").await
+    }
+}
+
+#[async_trait]
+impl SessionRepo for PostgresRepo {
+    async fn add_session(
+        &self,
+        user_id: u32,
+        key: &str,
+        user_agent: Option<&str>,
+    ) -> anyhow::Result<u32> {
+        let row = self
+            .client()
+            .await?
+            .query_one(
+                &format!(
+                    "INSERT INTO {LOGINS_T} (timestamp, last_seen, key, user_id, user_agent) VALUES ($1, $1, $2, $3, $4) RETURNING id"
+                ),
+                &[
+                    &OffsetDateTime::now_utc(),
+                    &hash_key(key),
+                    &i64::from(user_id),
+                    &user_agent,
+                ],
+            )
+            .await?;
+
+        get_u32(&row, "id")
+    }
+
+    async fn get_session_owner(&self, key: &str) -> anyhow::Result<Option<SessionOwner>> {
+        let Some(row) = self
+            .client()
+            .await?
+            .query_opt(
+                &format!(
+                    "SELECT l.id AS session_id, l.last_seen, u.id AS user_id, u.is_admin
+                    FROM {LOGINS_T} AS l
+                    INNER JOIN {USERS_T} AS u
+                        ON l.user_id = u.id
+                    WHERE l.key = $1"
+                ),
+                &[&hash_key(key)],
+            )
+            .await?
+        else {
+            return Ok(None);
+        };
+
+        Ok(Some(SessionOwner {
+            session_id: get_u32(&row, "session_id")?,
+            user_id: get_u32(&row, "user_id")?,
+            is_admin: row.try_get("is_admin")?,
+            last_seen: row.try_get("last_seen")?,
+        }))
+    }
+
+    async fn touch_session(&self, session_id: u32) -> anyhow::Result<u64> {
+        Ok(self
+            .client()
+            .await?
+            .execute(
+                &format!("UPDATE {LOGINS_T} SET last_seen = $1 WHERE id = $2"),
+                &[&OffsetDateTime::now_utc(), &i64::from(session_id)],
+            )
+            .await?)
+    }
+
+    async fn get_sessions(&self, user_id: u32) -> anyhow::Result<Vec<Session>> {
+        self.client()
+            .await?
+            .query(
+                &format!("SELECT * FROM {LOGINS_T} WHERE user_id = $1 ORDER BY id DESC"),
+                &[&i64::from(user_id)],
+            )
+            .await?
+            .iter()
+            .map(|row| {
+                Ok(Session {
+                    id: get_u32(row, "id")?,
+                    key: row.try_get("key")?,
+                    timestamp: row.try_get("timestamp")?,
+                    last_seen: row.try_get("last_seen")?,
+                    user_agent: row.try_get("user_agent")?,
+                    current: false,
+                })
+            })
+            .collect()
+    }
+
+    async fn delete_session(&self, key: &str) -> anyhow::Result<u64> {
+        Ok(self
+            .client()
+            .await?
+            .execute(
+                &format!("DELETE FROM {LOGINS_T} WHERE key = $1"),
+                &[&hash_key(key)],
+            )
+            .await?)
+    }
+
+    async fn delete_session_by_id(&self, user_id: u32, id: u32) -> anyhow::Result<u64> {
+        Ok(self
+            .client()
+            .await?
+            .execute(
+                &format!("DELETE FROM {LOGINS_T} WHERE id = $1 AND user_id = $2"),
+                &[&i64::from(id), &i64::from(user_id)],
+            )
+            .await?)
+    }
+
+    async fn delete_user_sessions(&self, user_id: u32) -> anyhow::Result<u64> {
+        Ok(self
+            .client()
+            .await?
+            .execute(
+                &format!("DELETE FROM {LOGINS_T} WHERE user_id = $1"),
+                &[&i64::from(user_id)],
+            )
+            .await?)
+    }
+
+    async fn prune_sessions(&self, lifetime_mins: i64) -> anyhow::Result<u64> {
+        Ok(self
+            .client()
+            .await?
+            .execute(
+                &format!("DELETE FROM {LOGINS_T} WHERE last_seen <= $1"),
+                &[&(OffsetDateTime::now_utc() - lifetime_mins.minutes())],
+            )
+            .await?)
+    }
+
+    async fn add_login_attempt(
+        &self,
+        ip: &str,
+        username: &str,
+        reason: &str,
+    ) -> anyhow::Result<u64> {
+        Ok(self
+            .client()
+            .await?
+            .execute(
+                &format!(
+                    "INSERT INTO {LOGIN_ATTEMPTS_T} (ip, username, reason, timestamp) VALUES ($1, $2, $3, $4)"
+                ),
+                &[&ip, &username, &reason, &OffsetDateTime::now_utc()],
+            )
+            .await?)
+    }
+
+    async fn delete_login_attempts_before(&self, cutoff: OffsetDateTime) -> anyhow::Result<u64> {
+        Ok(self
+            .client()
+            .await?
+            .execute(
+                &format!("DELETE FROM {LOGIN_ATTEMPTS_T} WHERE timestamp < $1"),
+                &[&cutoff],
+            )
+            .await?)
+    }
+}
+
+fn read_user(row: &Row) -> anyhow::Result<User> {
+    Ok(User {
+        id: get_u32(row, "id")?,
+        username: row.try_get("username")?,
+        password_hash: row.try_get("password_hash")?,
+        is_admin: row.try_get("is_admin")?,
+        timestamp: row.try_get("timestamp")?,
+    })
+}
+
+#[async_trait]
+impl UserRepo for PostgresRepo {
+    async fn get_users(&self) -> anyhow::Result<Vec<User>> {
+        self.client()
+            .await?
+            .query(&format!("SELECT * FROM {USERS_T} ORDER BY id"), &[])
+            .await?
+            .iter()
+            .map(read_user)
+            .collect()
+    }
+
+    async fn get_user_by_username(&self, username: &str) -> anyhow::Result<Option<User>> {
+        self.client()
+            .await?
+            .query_opt(
+                &format!("SELECT * FROM {USERS_T} WHERE username = $1"),
+                &[&username],
+            )
+            .await?
+            .as_ref()
+            .map(read_user)
+            .transpose()
+    }
+
+    async fn add_user(
+        &self,
+        username: &str,
+        password: &str,
+        is_admin: bool,
+    ) -> anyhow::Result<u64> {
+        let password_hash = hash_password(password)?;
+
+        Ok(self
+            .client()
+            .await?
+            .execute(
+                &format!(
+                    "INSERT INTO {USERS_T} (username, password_hash, is_admin, timestamp) VALUES ($1, $2, $3, $4)"
+                ),
+                &[
+                    &username,
+                    &password_hash,
+                    &is_admin,
+                    &OffsetDateTime::now_utc(),
+                ],
+            )
+            .await?)
+    }
+
+    async fn set_password(&self, username: &str, password: &str) -> anyhow::Result<u64> {
+        let password_hash = hash_password(password)?;
+
+        Ok(self
+            .client()
+            .await?
+            .execute(
+                &format!("UPDATE {USERS_T} SET password_hash = $1 WHERE username = $2"),
+                &[&password_hash, &username],
+            )
+            .await?)
+    }
+
+    async fn add_token(
+        &self,
+        user_id: u32,
+        name: &str,
+        scope: TokenScope,
+    ) -> anyhow::Result<(u32, String)> {
+        let token = format!("{TOKEN_PREFIX}{}", random_key());
+
+        let row = self
+            .client()
+            .await?
+            .query_one(
+                &format!(
+                    "INSERT INTO {API_TOKENS_T} (user_id, name, token_hash, scope, timestamp) VALUES ($1, $2, $3, $4, $5) RETURNING id"
+                ),
+                &[
+                    &i64::from(user_id),
+                    &name,
+                    &hash_key(&token),
+                    &scope.as_str(),
+                    &OffsetDateTime::now_utc(),
+                ],
+            )
+            .await?;
+
+        Ok((get_u32(&row, "id")?, token))
+    }
+
+    async fn get_tokens(&self, user_id: u32) -> anyhow::Result<Vec<ApiToken>> {
+        self.client()
+            .await?
+            .query(
+                &format!("SELECT * FROM {API_TOKENS_T} WHERE user_id = $1 ORDER BY id DESC"),
+                &[&i64::from(user_id)],
+            )
+            .await?
+            .iter()
+            .map(|row| {
+                Ok(ApiToken {
+                    id: get_u32(row, "id")?,
+                    name: row.try_get("name")?,
+                    token_hash: row.try_get("token_hash")?,
+                    scope: TokenScope::try_from(row.try_get::<_, String>("scope")?)
+                        .map_err(StringError)?,
+                    timestamp: row.try_get("timestamp")?,
+                    last_used: row.try_get("last_used")?,
+                })
+            })
+            .collect()
+    }
+
+    async fn delete_token(&self, user_id: u32, token_id: u32) -> anyhow::Result<u64> {
+        Ok(self
+            .client()
+            .await?
+            .execute(
+                &format!("DELETE FROM {API_TOKENS_T} WHERE id = $1 AND user_id = $2"),
+                &[&i64::from(token_id), &i64::from(user_id)],
+            )
+            .await?)
+    }
+
+    async fn get_token_owner(
+        &self,
+        token: &str,
+    ) -> anyhow::Result<Option<(u32, bool, TokenScope)>> {
+        if !token.starts_with(TOKEN_PREFIX) {
+            return Ok(None);
+        }
+
+        let Some(row) = self
+            .client()
+            .await?
+            .query_opt(
+                &format!(
+                    "UPDATE {API_TOKENS_T} AS t
+                    SET last_used = $1
+                    FROM {USERS_T} AS u
+                    WHERE t.user_id = u.id AND t.token_hash = $2
+                    RETURNING t.scope, u.id AS user_id, u.is_admin"
+                ),
+                &[&OffsetDateTime::now_utc(), &hash_key(token)],
+            )
+            .await?
+        else {
+            return Ok(None);
+        };
+
+        let scope =
+            TokenScope::try_from(row.try_get::<_, String>("scope")?).map_err(StringError)?;
+
+        Ok(Some((
+            get_u32(&row, "user_id")?,
+            row.try_get("is_admin")?,
+            scope,
+        )))
+    }
+}
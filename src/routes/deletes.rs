@@ -1,193 +1,128 @@
-use actix_web::{HttpRequest, HttpResponse, Responder, cookie::Cookie, delete, web};
-use libsql::params;
+use actix_web::{HttpRequest, HttpResponse, delete, web};
+use anyhow::anyhow;
 use log::{error, info};
 
 use crate::{
-    db::{ACTIVITIES_T, R_WATCHED_TABS_T, SOURCES_T},
-    types::{AppData, Failure, LOGGED_IN_COOKIE, Success},
-    utils::{is_logged_in, return_password_error},
+    auth::{AuthUser, SessionUser},
+    error::{GregError, LogErr, RouteResult},
+    routes::v1::{roadmap::unwatch_tab, tokens::revoke_token},
+    types::{AppData, LOGGED_IN_COOKIE, Success},
+    utils::removal_cookie,
 };
 
 #[delete("/logout")]
-pub async fn logout() -> impl Responder {
-    let mut c = Cookie::build(LOGGED_IN_COOKIE, "")
-        .path("/")
-        .secure(true)
-        .http_only(true)
-        .expires(None)
-        .finish();
-
-    c.make_removal();
+pub async fn logout(data: AppData, req: HttpRequest) -> RouteResult {
+    if let Some(key) = req.cookie(LOGGED_IN_COOKIE) {
+        let db = data.connect(false).await?;
+        db.delete_session(key.value())
+            .await
+            .log_err("[Logout] Deleting session failed")?;
+    }
 
-    HttpResponse::Ok().cookie(c).json(Success {
+    Ok(HttpResponse::Ok().cookie(removal_cookie()).json(Success {
         message: "Successfully logged out".into(),
-    })
+    }))
 }
 
-#[delete("/source/{id}")]
-pub async fn delete_source(
-    path: web::Path<i32>,
+#[delete("/sessions")]
+pub async fn logout_all_sessions(data: AppData, user: AuthUser) -> RouteResult {
+    let db = data.connect(false).await?;
+
+    let deleted = db
+        .delete_user_sessions(user.id)
+        .await
+        .log_err("[Logout All] Deleting sessions failed")?;
+
+    info!("[Logout All] Deleted {deleted} sessions");
+    Ok(HttpResponse::Ok().cookie(removal_cookie()).json(Success {
+        message: "Logged out of all sessions".into(),
+    }))
+}
+
+#[delete("/tokens/{id}")]
+pub async fn delete_token(
+    path: web::Path<u32>,
     data: AppData,
-    req: HttpRequest,
-) -> impl Responder {
-    let db = data.app_db.connect().unwrap();
+    SessionUser(user): SessionUser,
+) -> RouteResult {
+    revoke_token(&data, user.id, path.into_inner()).await?;
+
+    Ok(HttpResponse::Ok().json(Success {
+        message: "Token revoked successfully".into(),
+    }))
+}
+
+#[delete("/source/{id}")]
+pub async fn delete_source(path: web::Path<u32>, data: AppData, user: AuthUser) -> RouteResult {
+    let db = data.connect(false).await?;
     let id = path.into_inner();
 
-    if is_logged_in(&req, db.clone()).await {
-        let result = db
-            .execute(&format!("DELETE FROM {SOURCES_T} WHERE id = ?1"), [id])
-            .await;
-
-        match result {
-            Ok(success) => {
-                if success == 1 {
-                    info!("[Delete Source] Deleted source successfully");
-                    HttpResponse::Ok().json(Success {
-                        message: "Source deleted successfully".into(),
-                    })
-                } else {
-                    error!("[Delete Source] Rows affected in deletion not 1, is: {success}");
-                    HttpResponse::InternalServerError().json(Failure {
-                        message: "Unexpected issue deleting source".into(),
-                    })
-                }
-            }
-            Err(err) => {
-                error!("[Delete Source] Deleting source failed with err: {err}");
-                HttpResponse::InternalServerError().json(Failure {
-                    message: format!("Couldn't delete source. Err: {err}"),
-                })
-            }
+    let success = db
+        .delete_source(user.id, id)
+        .await
+        .log_err("[Delete Source] Deleting source failed")?;
+
+    match success {
+        1 => {
+            info!("[Delete Source] Deleted source successfully");
+            Ok(HttpResponse::Ok().json(Success {
+                message: "Source deleted successfully".into(),
+            }))
         }
-    } else {
-        error!("[Delete Source] Failed due to auth error");
-        return_password_error()
+        0 => {
+            error!("[Delete Source] Source {id} does not exist");
+            Err(GregError::NotFound(format!("Source {id} does not exist")))
+        }
+        _ => Err(anyhow!("Rows affected in deletion not 1, is: {success}"))
+            .log_err("[Delete Source] Deleting source failed"),
     }
 }
 
 #[delete("/activity")]
-pub async fn clear_all_activities(data: AppData, req: HttpRequest) -> impl Responder {
-    let db = data.app_db.connect().unwrap();
-    if is_logged_in(&req, db.clone()).await {
-        let result = db
-            .execute(&format!("DELETE FROM {ACTIVITIES_T}"), params!())
-            .await;
-
-        match result {
-            Ok(_) => {
-                info!("[Delete All Activities] Deleted activities successfully");
-                HttpResponse::Ok().json(Success {
-                    message: "Activities deleted successfully".into(),
-                })
-            }
-            Err(err) => {
-                error!("[Delete All Activities] Deleting activities failed with err: {err}");
-                HttpResponse::InternalServerError().json(Failure {
-                    message: format!("Couldn't delete all activities. Err: {err}"),
-                })
-            }
-        }
-    } else {
-        error!("[Delete All Activities] Failed due to auth error");
-        return_password_error()
-    }
+pub async fn clear_all_activities(data: AppData, user: AuthUser) -> RouteResult {
+    let db = data.connect(false).await?;
+    db.delete_activities(user.id)
+        .await
+        .log_err("[Delete All Activities] Deleting activities failed")?;
+
+    info!("[Delete All Activities] Deleted activities successfully");
+    Ok(HttpResponse::Ok().json(Success {
+        message: "Activities deleted successfully".into(),
+    }))
 }
 
 #[delete("/activity/{num}")]
-pub async fn clear_activities(
-    path: web::Path<i32>,
-    data: AppData,
-    req: HttpRequest,
-) -> impl Responder {
-    let db = data.app_db.connect().unwrap();
+pub async fn clear_activities(path: web::Path<u32>, data: AppData, user: AuthUser) -> RouteResult {
+    let db = data.connect(false).await?;
     let num = path.into_inner();
 
-    if is_logged_in(&req, db.clone()).await {
-        let result = db
-            .execute(
-                &format!(
-                    "DELETE FROM {ACTIVITIES_T} 
-                    WHERE id IN (
-                        SELECT id 
-                        FROM {ACTIVITIES_T} 
-                        ORDER BY id ASC 
-                        LIMIT ?1
-                    )
-                    "
-                ),
-                [num],
-            )
-            .await;
-
-        match result {
-            Ok(success) => {
-                if success == (num as u64) {
-                    info!("[Delete Activities] Deleted activities successfully");
-                    HttpResponse::Ok().json(Success {
-                        message: "Activities deleted successfully".into(),
-                    })
-                } else {
-                    error!(
-                        "[Delete Activities] Rows affected in deletion not {num}, is: {success}"
-                    );
-                    HttpResponse::InternalServerError().json(Failure {
-                        message: "Unexpected issue adding source".into(),
-                    })
-                }
-            }
-            Err(err) => {
-                error!("[Delete Activities] Deleting activities failed with err: {err}");
-                HttpResponse::InternalServerError().json(Failure {
-                    message: format!("Couldn't delete activities. Err: {err}"),
-                })
-            }
-        }
-    } else {
-        error!("[Delete Activities] Failed due to auth error");
-        return_password_error()
+    let success = db
+        .delete_oldest_activities(user.id, num)
+        .await
+        .log_err("[Delete Activities] Deleting activities failed")?;
+
+    if success != u64::from(num) {
+        return Err(anyhow!(
+            "Rows affected in deletion not {num}, is: {success}"
+        ))
+        .log_err("[Delete Activities] Deleting activities failed");
     }
+
+    info!("[Delete Activities] Deleted activities successfully");
+    Ok(HttpResponse::Ok().json(Success {
+        message: "Activities deleted successfully".into(),
+    }))
 }
 
 #[delete("/watched_tabs/{id}")]
 pub async fn delete_watched_tab(
-    path: web::Path<i32>,
+    path: web::Path<u32>,
     data: AppData,
-    req: HttpRequest,
-) -> impl Responder {
-    let db = data.app_db.connect().unwrap();
-    let id = path.into_inner();
+    user: AuthUser,
+) -> RouteResult {
+    unwatch_tab(&data, user.id, path.into_inner()).await?;
 
-    if is_logged_in(&req, db.clone()).await {
-        let result = db
-            .execute(
-                &format!("DELETE FROM {R_WATCHED_TABS_T} WHERE id = ?1"),
-                [id],
-            )
-            .await;
-
-        match result {
-            Ok(success) => {
-                if success == 1 {
-                    info!("[Delete Watched Tab] Deleted watched tab successfully");
-                    HttpResponse::Ok().json(Success {
-                        message: "Watched tab deleted successfully".into(),
-                    })
-                } else {
-                    error!("[Delete Watched Tab] Rows affected in deletion not 1, is: {success}");
-                    HttpResponse::InternalServerError().json(Failure {
-                        message: "Unexpected issue deleting watched tabs".into(),
-                    })
-                }
-            }
-            Err(err) => {
-                error!("[Delete Watched Tab] Deleting source failed with err: {err}");
-                HttpResponse::InternalServerError().json(Failure {
-                    message: format!("Couldn't delete watched tab. Err: {err}"),
-                })
-            }
-        }
-    } else {
-        error!("[Delete Watched Tab] Failed due to auth error");
-        return_password_error()
-    }
+    Ok(HttpResponse::Ok().json(Success {
+        message: "Watched tab deleted successfully".into(),
+    }))
 }
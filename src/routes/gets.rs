@@ -1,42 +1,20 @@
 use crate::{
-    queries::{
-        roadmap::{
-            self, get_most_recent_roadmap_tabs, get_roadmap_activities, get_roadmap_changes,
-        },
-        sources,
-    },
-    types::{AppData, Failure, LOGGED_IN_COOKIE},
-    utils::{is_logged_in, return_password_error},
+    auth::{AuthUser, Viewer},
+    error::{LogErr, RouteResult},
+    routes::v1::PaginationQuery,
+    utils::removal_cookie,
 };
 
-use actix_web::{HttpRequest, HttpResponse, Responder, cookie::Cookie, get, web};
-use log::{error, info};
-use serde::Deserialize;
+use actix_web::{HttpResponse, Responder, get, web};
+use log::info;
 use serde_json::json;
 
-#[derive(Debug, Deserialize)]
-struct Query {
-    #[serde(default)]
-    demo: bool,
-}
-
-#[derive(Debug, Deserialize)]
-struct PaginationQuery {
-    #[serde(default)]
-    demo: bool,
-    count: Option<u32>,
-    skip: Option<u32>,
-}
-
 #[get("/check-logged-in")]
-pub async fn check_logged_in(data: AppData, req: HttpRequest) -> impl Responder {
-    let db = data.app_db.connect().unwrap();
-    let logged_in = is_logged_in(&req, db).await;
+pub async fn check_logged_in(user: Option<AuthUser>) -> impl Responder {
+    let logged_in = user.is_some();
     let mut res = HttpResponse::Ok();
     if !logged_in {
-        let mut c = Cookie::build(LOGGED_IN_COOKIE, "").finish();
-        c.make_removal();
-        res.cookie(c);
+        res.cookie(removal_cookie());
     }
 
     res.json(logged_in)
@@ -49,240 +27,111 @@ pub async fn keep_alive() -> impl Responder {
     }))
 }
 
-#[get("/sources")]
-pub async fn get_sources(
-    data: AppData,
-    query: web::Query<Query>,
-    req: HttpRequest,
-) -> impl Responder {
-    let db = if query.demo {
-        data.demo_db.connect().unwrap()
-    } else {
-        data.app_db.connect().unwrap()
-    };
-
-    if query.demo || is_logged_in(&req, db.clone()).await {
-        info!("[Get Sources] Getting sources from db");
-        match sources::get_sources(db).await {
-            Ok(sources) => {
-                info!("[Get Sources] Got sources successfully");
-                HttpResponse::Ok().json(sources)
-            }
-            Err(err) => {
-                error!("[Get Sources] Getting sources failed with err: {err}");
-                HttpResponse::InternalServerError().json(Failure {
-                    message: format!("Couldn't get sources. Err: {err}"),
-                })
-            }
-        }
-    } else {
-        error!("[Get Sources] Failed due to auth error");
-        return_password_error()
-    }
-}
-
 #[get("/activity")]
-pub async fn get_activity(
-    data: AppData,
-    query: web::Query<PaginationQuery>,
-    req: HttpRequest,
-) -> impl Responder {
-    let db = if query.demo {
-        data.demo_db.connect().unwrap()
-    } else {
-        data.app_db.connect().unwrap()
-    };
+pub async fn get_activity(viewer: Viewer, query: web::Query<PaginationQuery>) -> RouteResult {
+    info!("[Get Activity] Getting activities from db");
+    let activities = viewer
+        .db
+        .get_activity(viewer.user_id, query.count.unwrap_or(35), query.cursor)
+        .await
+        .log_err("[Get Activity] Getting activities failed")?;
 
-    if query.demo || is_logged_in(&req, db.clone()).await {
-        info!("[Get Activity] Getting activities from db");
-        match sources::get_activity(db, query.count.unwrap_or(35), query.skip.unwrap_or(0)).await {
-            Ok(activities) => {
-                info!("[Get Activity] Got activities successfully");
-                HttpResponse::Ok().json(activities)
-            }
-            Err(err) => {
-                error!("[Get Activity] Getting activities failed with err: {err}");
-                HttpResponse::InternalServerError().json(Failure {
-                    message: format!("Couldn't get activities. Err: {err}"),
-                })
-            }
-        }
-    } else {
-        error!("[Get Activity] Failed due to auth error");
-        return_password_error()
-    }
+    info!("[Get Activity] Got activities successfully");
+    Ok(HttpResponse::Ok().json(activities))
 }
 
 #[get("/activity/{source_id}")]
 pub async fn get_source_activity(
-    data: AppData,
+    viewer: Viewer,
     path: web::Path<u32>,
     query: web::Query<PaginationQuery>,
-    req: HttpRequest,
-) -> impl Responder {
-    let db = if query.demo {
-        data.demo_db.connect().unwrap()
-    } else {
-        data.app_db.connect().unwrap()
-    };
+) -> RouteResult {
     let source_id = path.into_inner();
 
-    if query.demo || is_logged_in(&req, db.clone()).await {
-        info!("[Get Activity] Getting activities from db");
-        match sources::get_source_activity(
-            db,
+    info!("[Get Activity] Getting activities from db");
+    let activities = viewer
+        .db
+        .get_source_activity(
+            viewer.user_id,
             query.count.unwrap_or(35),
-            query.skip.unwrap_or(0),
+            query.cursor,
             source_id,
         )
         .await
-        {
-            Ok(activities) => {
-                info!("[Get Activity] Got activities successfully");
-                HttpResponse::Ok().json(activities)
-            }
-            Err(err) => {
-                error!("[Get Activity] Getting activities failed with err: {err}");
-                HttpResponse::InternalServerError().json(Failure {
-                    message: format!("Couldn't get activities. Err: {err}"),
-                })
-            }
-        }
-    } else {
-        error!("[Get Activity] Failed due to auth error");
-        return_password_error()
-    }
+        .log_err("[Get Activity] Getting activities failed")?;
+
+    info!("[Get Activity] Got activities successfully");
+    Ok(HttpResponse::Ok().json(activities))
+}
+
+#[get("/source/{id}/events")]
+pub async fn get_source_events(viewer: Viewer, path: web::Path<u32>) -> RouteResult {
+    let source_id = path.into_inner();
+
+    info!("[Get Source Events] Getting events from db");
+    let events = viewer
+        .db
+        .get_source_events(viewer.user_id, source_id)
+        .await
+        .log_err("[Get Source Events] Getting events failed")?;
+
+    info!("[Get Source Events] Got events successfully");
+    Ok(HttpResponse::Ok().json(events))
 }
 
 #[get("/roadmap_activity")]
 pub async fn get_roadmap_activity(
-    data: AppData,
+    viewer: Viewer,
     query: web::Query<PaginationQuery>,
-    req: HttpRequest,
-) -> impl Responder {
-    let db = if query.demo {
-        data.demo_db.connect().unwrap()
-    } else {
-        data.app_db.connect().unwrap()
-    };
-
-    if query.demo || is_logged_in(&req, db.clone()).await {
-        info!("[Get Roadmap Activity] Getting activities from db");
+) -> RouteResult {
+    info!("[Get Roadmap Activity] Getting activities from db");
+    let activities = viewer
+        .db
+        .get_roadmap_activities(viewer.user_id, query.count.unwrap_or(35), query.cursor)
+        .await
+        .log_err("[Get Roadmap Activity] Getting roadmap activities failed")?;
 
-        match get_roadmap_activities(db, query.count.unwrap_or(35), query.skip.unwrap_or(0)).await {
-            Ok(activities) => {
-                info!("[Get Roadmap Activity] Got activities successfully");
-                HttpResponse::Ok().json(activities)
-            }
-            Err(err) => {
-                error!("[Get Roadmap Activity] Getting roadmap activities failed with err: {err}");
-                HttpResponse::InternalServerError().json(Failure {
-                    message: format!("Couldn't get roadmap activities. Err: {err}"),
-                })
-            }
-        }
-    } else {
-        error!("[Get Roadmap Activity] Failed due to auth error");
-        return_password_error()
-    }
+    info!("[Get Roadmap Activity] Got activities successfully");
+    Ok(HttpResponse::Ok().json(activities))
 }
 
 #[get("/most_recent_tabs")]
-pub async fn get_most_recent_tabs(
-    data: AppData,
-    query: web::Query<Query>,
-    req: HttpRequest,
-) -> impl Responder {
-    let db = if query.demo {
-        data.demo_db.connect().unwrap()
-    } else {
-        data.app_db.connect().unwrap()
-    };
-    if query.demo || is_logged_in(&req, db.clone()).await {
-        info!("[Get Roadmap Tabs] Getting most recent tabs from db");
+pub async fn get_most_recent_tabs(viewer: Viewer) -> RouteResult {
+    info!("[Get Roadmap Tabs] Getting most recent tabs from db");
+    let tabs = viewer
+        .db
+        .get_most_recent_roadmap_tabs()
+        .await
+        .log_err("[Get Roadmap Tabs] Getting roadmap tabs failed")?;
 
-        match get_most_recent_roadmap_tabs(db).await {
-            Ok(tabs) => {
-                info!("[Get Roadmap Tabs] Got tabs successfully");
-                HttpResponse::Ok().json(tabs)
-            }
-            Err(err) => {
-                error!("[Get Roadmap Tabs] Getting roadmap tabs failed with err: {err}");
-                HttpResponse::InternalServerError().json(Failure {
-                    message: format!("Couldn't get roadmap tabs. Err: {err}"),
-                })
-            }
-        }
-    } else {
-        error!("[Get Roadmap Tabs] Failed due to auth error");
-        return_password_error()
-    }
+    info!("[Get Roadmap Tabs] Got tabs successfully");
+    Ok(HttpResponse::Ok().json(tabs))
 }
 
 #[get("/watched_tabs")]
-pub async fn get_watched_tabs(
-    data: AppData,
-    query: web::Query<Query>,
-    req: HttpRequest,
-) -> impl Responder {
-    let db = if query.demo {
-        data.demo_db.connect().unwrap()
-    } else {
-        data.app_db.connect().unwrap()
-    };
-    if query.demo || is_logged_in(&req, db.clone()).await {
-        info!("[Get Watched Tabs] Getting watched tabs from db");
+pub async fn get_watched_tabs(viewer: Viewer) -> RouteResult {
+    info!("[Get Watched Tabs] Getting watched tabs from db");
+    let watched_tabs = viewer
+        .db
+        .get_watched_tabs(viewer.user_id)
+        .await
+        .log_err("[Get Watched Tabs] Getting watched tabs failed")?;
 
-        match roadmap::get_watched_tabs(db).await {
-            Ok(watched_tabs) => {
-                info!("[Get Watched Tabs] Got watched tabs successfully");
-                HttpResponse::Ok().json(watched_tabs)
-            }
-            Err(err) => {
-                error!("[Get Watched Tabs] Getting watched tabs failed with err: {err}");
-                HttpResponse::InternalServerError().json(Failure {
-                    message: format!("Couldn't get watched tabs. Err: {err}"),
-                })
-            }
-        }
-    } else {
-        error!("[Get Watched Tabs] Failed due to auth error");
-        return_password_error()
-    }
+    info!("[Get Watched Tabs] Got watched tabs successfully");
+    Ok(HttpResponse::Ok().json(watched_tabs))
 }
 
 #[get("/roadmap_activity/{id}")]
-pub async fn get_changes(
-    data: AppData,
-    path: web::Path<u32>,
-    query: web::Query<Query>,
-    req: HttpRequest,
-) -> impl Responder {
+pub async fn get_changes(viewer: Viewer, path: web::Path<u32>) -> RouteResult {
     let activity_id = path.into_inner();
 
-    let db = if query.demo {
-        data.demo_db.connect().unwrap()
-    } else {
-        data.app_db.connect().unwrap()
-    };
-
-    if query.demo || is_logged_in(&req, db.clone()).await {
-        info!("[Get Roadmap Changes] Getting changes from db");
+    info!("[Get Roadmap Changes] Getting changes from db");
+    let changes = viewer
+        .db
+        .get_roadmap_changes(viewer.user_id, activity_id)
+        .await
+        .log_err("[Get Roadmap Changes] Getting roadmap changes failed")?;
 
-        match get_roadmap_changes(db, activity_id).await {
-            Ok(changes) => {
-                info!("[Get Roadmap Changes] Got changes successfully");
-                HttpResponse::Ok().json(changes)
-            }
-            Err(err) => {
-                error!("[Get Roadmap Changes] Getting roadmap changes failed with err: {err}");
-                HttpResponse::InternalServerError().json(Failure {
-                    message: format!("Couldn't get roadmap changes. Err: {err}"),
-                })
-            }
-        }
-    } else {
-        error!("[Get Roadmap Changes] Failed due to auth error");
-        return_password_error()
-    }
+    info!("[Get Roadmap Changes] Got changes successfully");
+    Ok(HttpResponse::Ok().json(changes))
 }
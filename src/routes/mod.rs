@@ -1,3 +1,7 @@
+//! `gets`, `posts` and `deletes` are the original routes under `/api`. They're
+//! kept as deprecated aliases of [`v1`] until clients have moved over.
+
 pub mod deletes;
 pub mod gets;
 pub mod posts;
+pub mod v1;
@@ -1,293 +1,181 @@
-use std::env;
-
 use crate::{
-    db::{LOGINS_T, R_WATCHED_TABS_T, SOURCES_T},
-    tasks::{check_roadmap::check_roadmap, check_sources::check_sources},
-    types::{AddSource, AppData, Failure, LOGGED_IN_COOKIE, LoginInfo, Success},
-    utils::{is_logged_in, return_password_error},
+    auth::{AdminUser, AuthUser},
+    error::{GregError, LogErr, RouteResult, is_unique_violation},
+    routes::v1::{roadmap::watch_tab, sessions::log_in, sources::insert_source},
+    tasks::{
+        check_roadmap::check_roadmap,
+        check_sources::{check_single_source, check_sources, preview_source},
+    },
+    types::{AddSource, AppData, LoginInfo, Success},
+    utils::session_cookie,
 };
-use actix_web::{HttpRequest, HttpResponse, Responder, cookie::Cookie, post, web};
-use feed_rs::parser;
+use actix_web::{HttpRequest, HttpResponse, Responder, post, web};
 use log::{error, info};
-use time::{OffsetDateTime, ext::NumericalDuration};
 use url::Url;
-use uuid::Uuid;
 
 #[post("/login")]
-pub async fn login(login_info: web::Json<LoginInfo>, data: AppData) -> impl Responder {
-    let password = match env::var("PASSWORD") {
-        Ok(x) => x,
-        Err(err) => {
-            error!(
-                "[Login] PASSWORD is not set. Env get failed with err: {err}"
-            );
-            return return_password_error();
-        }
-    };
-
-    if password == login_info.password {
-        let id = Uuid::new_v4();
-
-        info!("[Login] Inserting login key");
-        let db = data.app_db.connect().unwrap();
-
-        let result = db
-            .execute(
-                &format!("INSERT INTO {LOGINS_T} (timestamp, key) VALUES (?1, ?2)"),
-                [
-                    serde_json::to_string(&OffsetDateTime::now_utc()).unwrap(),
-                    id.to_string(),
-                ],
-            )
-            .await;
-
-        match result {
-            Ok(_x) => {
-                info!("[Login] Insert successful");
-            }
-            Err(err) => {
-                error!("[Login] Inserting login key failed with err: {err}");
-                return HttpResponse::InternalServerError().json(Failure {
-                    message: "Issue logging in".into(),
-                });
-            }
-        };
-
-        let c = Cookie::build(LOGGED_IN_COOKIE, id.to_string())
-            .path("/")
-            .secure(true)
-            .http_only(true)
-            .expires(None)
-            .finish();
+pub async fn login(
+    login_info: web::Json<LoginInfo>,
+    data: AppData,
+    req: HttpRequest,
+) -> RouteResult {
+    let (_, key) = log_in(&data, &req, &login_info).await?;
 
-        HttpResponse::Ok().cookie(c).json(Success {
+    Ok(HttpResponse::Ok()
+        .cookie(session_cookie(key))
+        .json(Success {
             message: "Log in Successful".into(),
-        })
-    } else {
-        error!(
-            "[Login] Login failed with wrong password: {}",
-            login_info.password
-        );
-        return_password_error()
-    }
+        }))
 }
 
 #[post("/recheck")]
-pub async fn recheck(data: AppData, req: HttpRequest) -> impl Responder {
-    let db = data.app_db.connect().unwrap();
-    if is_logged_in(&req, db).await {
-        check_sources(&data).await;
-        HttpResponse::Ok().json(Success {
-            message: "Rechecked Sources Successfully".into(),
-        })
-    } else {
-        return_password_error()
-    }
-}
-
-#[post("/recheck_roadmap")]
-pub async fn recheck_roadmap(data: AppData, req: HttpRequest) -> impl Responder {
-    let db = data.app_db.connect().unwrap();
-    if is_logged_in(&req, db).await {
-        check_roadmap(&data).await;
-        HttpResponse::Ok().json(Success {
-            message: "Rechecked Roadmap Successfully".into(),
-        })
-    } else {
-        return_password_error()
-    }
+pub async fn recheck(data: AppData, _admin: AdminUser) -> impl Responder {
+    check_sources(&data).await;
+    HttpResponse::Ok().json(Success {
+        message: "Rechecked Sources Successfully".into(),
+    })
 }
 
-async fn test_source(url: &str) -> Option<HttpResponse> {
-    let _url = match Url::parse(url) {
-        Ok(x) => x,
-        Err(err) => {
-            error!("[Add Source] Failed with error: {err} for url: {url}");
-            return Some(HttpResponse::InternalServerError().json(Failure {
-                message: format!("Couldn't add source. Err: {err}"),
-            }));
-        }
-    };
-    let res = match reqwest::get(url).await {
-        Ok(x) => x,
-        Err(err) => {
-            error!(
-                "[Add Source] Failed due to network error: {err} for url: {url}"
-            );
-            return Some(HttpResponse::InternalServerError().json(Failure {
-                message: format!("Couldn't add source. Err: {err}"),
-            }));
-        }
-    };
-    let _chan = match parser::parse(&(res.bytes().await.unwrap())[..]) {
-        Ok(x) => x,
-        Err(err) => {
-            error!(
-                "[Add Source] Failed due to result parse error: {err} for url: {url}"
-            );
-            return Some(HttpResponse::InternalServerError().json(Failure {
-                message: format!("Couldn't add source. Err: {err}"),
-            }));
-        }
+#[post("/source/{id}/recheck")]
+pub async fn recheck_source(path: web::Path<u32>, data: AppData, user: AuthUser) -> RouteResult {
+    let source_id = path.into_inner();
+    let db = data.connect(false).await?;
+
+    info!("[Recheck Source] Getting source {source_id} from db");
+    let Some(source) = db
+        .get_source(user.id, source_id)
+        .await
+        .log_err("[Recheck Source] Getting source failed")?
+    else {
+        error!("[Recheck Source] Source {source_id} does not exist");
+        return Err(GregError::NotFound(format!(
+            "Source {source_id} does not exist"
+        )));
     };
 
-    None
+    let activity = check_single_source(&data, db, source).await;
+    info!("[Recheck Source] Rechecked source {source_id}");
+    Ok(HttpResponse::Ok().json(activity))
+}
+
+#[post("/recheck_roadmap")]
+pub async fn recheck_roadmap(data: AppData, _admin: AdminUser) -> impl Responder {
+    check_roadmap(&data).await;
+    HttpResponse::Ok().json(Success {
+        message: "Rechecked Roadmap Successfully".into(),
+    })
 }
 
 #[post("/source/new")]
 pub async fn add_source(
     source: web::Json<AddSource>,
     data: AppData,
-    req: HttpRequest,
-) -> impl Responder {
-    let db = data.app_db.connect().unwrap();
-    if is_logged_in(&req, db.clone()).await {
-        if let Some(ret) = test_source(&source.url).await {
-            return ret;
-        }
+    user: AuthUser,
+) -> RouteResult {
+    insert_source(&data, user.id, &source.url).await?;
 
-        info!("[Add Source] Inserting source to db");
-        let result = db
-            .execute(
-                &format!("INSERT INTO {SOURCES_T} (url, last_checked) VALUES (?1, ?2)"),
-                [
-                    source.url.clone(),
-                    serde_json::to_string(&(OffsetDateTime::now_utc() - 1.hours())).unwrap(),
-                ],
-            )
-            .await;
+    Ok(HttpResponse::Ok().json(Success {
+        message: "Source added successfully".into(),
+    }))
+}
 
-        match result {
-            Ok(success) => {
-                if success >= 1 {
-                    info!("[Add Source] Inserting source successful");
-                    HttpResponse::Ok().json(Success {
-                        message: "Source added successfully".into(),
-                    })
-                } else {
-                    error!(
-                        "[Add Source] Rows affected in insert not 1, is: {success}"
-                    );
-                    HttpResponse::InternalServerError().json(Failure {
-                        message: "Unexpected issue adding source".into(),
-                    })
-                }
-            }
-            Err(err) => {
-                error!("[Add Source] Inserting source failed with err: {err}");
-                HttpResponse::InternalServerError().json(Failure {
-                    message: format!("Couldn't add source. Err: {err}"),
-                })
-            }
-        }
-    } else {
-        error!("[Add Source] Failed due to auth error");
-        return_password_error()
+#[post("/source/preview")]
+pub async fn preview_new_source(
+    source: web::Json<AddSource>,
+    data: AppData,
+    _user: AuthUser,
+) -> RouteResult {
+    if let Err(err) = Url::parse(&source.url) {
+        error!(
+            "[Preview Source] Failed with error: {err} for url: {}",
+            source.url
+        );
+        return Err(GregError::BadRequest(format!("Invalid url. Err: {err}")));
     }
+
+    info!("[Preview Source] Previewing source {}", source.url);
+    let preview = preview_source(&data, &source.url).await.map_err(|err| {
+        GregError::FeedUnavailable(format!("Couldn't preview source. Err: {err}"))
+    })?;
+
+    info!("[Preview Source] Previewed source successfully");
+    Ok(HttpResponse::Ok().json(preview))
 }
 
 #[post("/source/{id}/enable/{enabled}")]
 pub async fn enable_source(
     path: web::Path<(u32, bool)>,
     data: AppData,
-    req: HttpRequest,
-) -> impl Responder {
+    user: AuthUser,
+) -> RouteResult {
     let (source_id, new_enabled) = path.into_inner();
 
-    let db = data.app_db.connect().unwrap();
+    let db = data.connect(false).await?;
 
-    if is_logged_in(&req, db.clone()).await {
-        info!(
-            "[Update Source] {} source",
-            if new_enabled { "Enabling" } else { "Disabling" }
-        );
+    info!(
+        "[Update Source] {} source",
+        if new_enabled { "Enabling" } else { "Disabling" }
+    );
 
-        let result = db
-            .execute(
-                &format!("UPDATE {SOURCES_T} SET failed_count = ?1, enabled = ?2 WHERE id = ?3"),
-                (0, if new_enabled { 1 } else { 0 }, source_id),
-            )
-            .await;
+    let success = db
+        .set_source_enabled(user.id, source_id, new_enabled)
+        .await
+        .log_err("[Update Source] Updating source failed")?;
 
-        match result {
-            Ok(success) => {
-                if success >= 1 {
-                    info!("[Update Source] Updated source successfully");
-                    HttpResponse::Ok().json(Success {
-                        message: "Source updated successfully".into(),
-                    })
-                } else {
-                    error!(
-                        "[Update Source] Rows affected in insert not 1, is: {success}"
-                    );
-                    HttpResponse::InternalServerError().json(Failure {
-                        message: "Unexpected issue updating source".into(),
-                    })
-                }
-            }
-            Err(err) => {
-                error!("[Update Source] Updating source failed with err: {err}");
-                HttpResponse::InternalServerError().json(Failure {
-                    message: format!("Couldn't update source. Err: {err}"),
-                })
-            }
+    if success == 0 {
+        error!("[Update Source] Source {source_id} does not exist");
+        return Err(GregError::NotFound(format!(
+            "Source {source_id} does not exist"
+        )));
+    }
+
+    info!("[Update Source] Updated source successfully");
+    Ok(HttpResponse::Ok().json(Success {
+        message: "Source updated successfully".into(),
+    }))
+}
+
+#[post("/source/{id}/redirect/accept")]
+pub async fn accept_source_redirect(
+    path: web::Path<u32>,
+    data: AppData,
+    user: AuthUser,
+) -> RouteResult {
+    let source_id = path.into_inner();
+
+    let db = data.connect(false).await?;
+
+    info!("[Accept Redirect] Moving source {source_id} to suggested url");
+    let moved = match db.accept_redirect(user.id, source_id).await {
+        Err(err) if is_unique_violation(err.as_ref()) => {
+            error!("[Accept Redirect] Suggested url of source {source_id} is already a source");
+            return Err(GregError::Conflict(
+                "Suggested url is already a source".into(),
+            ));
         }
-    } else {
-        error!("[Update Source] Failed due to auth error");
-        return_password_error()
+        result => result.log_err("[Accept Redirect] Updating source failed")?,
+    };
+
+    if !moved {
+        error!("[Accept Redirect] Source {source_id} has no suggested url");
+        return Err(GregError::NotFound("Source has no suggested url".into()));
     }
+
+    info!("[Accept Redirect] Updated source successfully");
+    Ok(HttpResponse::Ok().json(Success {
+        message: "Source updated successfully".into(),
+    }))
 }
 
 #[post("/watched_tabs/add/{tab_id}")]
 pub async fn add_watched_tab(
     path: web::Path<String>,
     data: AppData,
-    req: HttpRequest,
-) -> impl Responder {
-    let db = data.app_db.connect().unwrap();
-    if is_logged_in(&req, db.clone()).await {
-        let tab_id = path.into_inner();
+    user: AuthUser,
+) -> RouteResult {
+    watch_tab(&data, user.id, &path.into_inner()).await?;
 
-        info!("[Add Watched Tab] Inserting tab to db");
-        let result = db
-            .execute(
-                &format!(
-                    "INSERT INTO {R_WATCHED_TABS_T} (tab_roadmap_id, timestamp) VALUES (?1, ?2)"
-                ),
-                [
-                    tab_id,
-                    serde_json::to_string(&OffsetDateTime::now_utc()).unwrap(),
-                ],
-            )
-            .await;
-
-        match result {
-            Ok(success) => {
-                if success >= 1 {
-                    info!("[Add Watched Tab] Inserting watched tab successful");
-                    HttpResponse::Ok().json(Success {
-                        message: "Watched tab added successfully".into(),
-                    })
-                } else {
-                    error!(
-                        "[Add Watched Tab] Rows affected in insert not 1, is: {success}"
-                    );
-                    HttpResponse::InternalServerError().json(Failure {
-                        message: "Unexpected issue adding watched tab".into(),
-                    })
-                }
-            }
-            Err(err) => {
-                error!(
-                    "[Add Watched Tab] Inserting watched tab failed with err: {err}"
-                );
-                HttpResponse::InternalServerError().json(Failure {
-                    message: format!("Couldn't add Watched Tab. Err: {err}"),
-                })
-            }
-        }
-    } else {
-        error!("[Add Watched Tab] Failed due to auth error");
-        return_password_error()
-    }
+    Ok(HttpResponse::Ok().json(Success {
+        message: "Watched tab added successfully".into(),
+    }))
 }
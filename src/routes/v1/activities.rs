@@ -0,0 +1,67 @@
+use actix_web::{HttpResponse, delete, get, web};
+use log::info;
+use serde::Deserialize;
+use utoipa::IntoParams;
+
+use crate::{
+    auth::{AuthUser, DemoQuery, Viewer},
+    error::{ErrorBody, LogErr, RouteResult},
+    routes::v1::PaginationQuery,
+    types::{Activity, AppData, Page},
+};
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DeleteActivitiesQuery {
+    /// Only delete this many of the oldest activities
+    oldest: Option<u32>,
+}
+
+#[utoipa::path(
+    tag = "activities",
+    params(DemoQuery, PaginationQuery),
+    responses(
+        (status = 200, description = "Most recent posts across all sources", body = Page<Activity>),
+        (status = 401, description = "Not logged in", body = ErrorBody),
+    ),
+    security((), ("session" = []), ("token" = [])),
+)]
+#[get("/activities")]
+pub async fn list_activities(viewer: Viewer, query: web::Query<PaginationQuery>) -> RouteResult {
+    info!("[Get Activity] Getting activities from db");
+    let activities = viewer
+        .db
+        .get_activity(viewer.user_id, query.count.unwrap_or(35), query.cursor)
+        .await
+        .log_err("[Get Activity] Getting activities failed")?;
+
+    info!("[Get Activity] Got activities successfully");
+    Ok(HttpResponse::Ok().json(activities))
+}
+
+#[utoipa::path(
+    tag = "activities",
+    params(DeleteActivitiesQuery),
+    responses(
+        (status = 204, description = "Activities deleted"),
+        (status = 401, description = "Not logged in", body = ErrorBody),
+    ),
+    security(("session" = []), ("token" = [])),
+)]
+#[delete("/activities")]
+pub async fn delete_activities(
+    query: web::Query<DeleteActivitiesQuery>,
+    data: AppData,
+    user: AuthUser,
+) -> RouteResult {
+    let db = data.connect(false).await?;
+
+    let deleted = match query.oldest {
+        Some(count) => db.delete_oldest_activities(user.id, count).await,
+        None => db.delete_activities(user.id).await,
+    }
+    .log_err("[Delete Activities] Deleting activities failed")?;
+
+    info!("[Delete Activities] Deleted {deleted} activities");
+    Ok(HttpResponse::NoContent().finish())
+}
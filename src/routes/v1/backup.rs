@@ -0,0 +1,53 @@
+use actix_web::{
+    HttpResponse, get,
+    http::header::{ContentDisposition, DispositionParam, DispositionType},
+};
+use log::{error, info};
+
+use crate::{
+    auth::AdminUser,
+    backup::{self, Backup},
+    db,
+    error::{ErrorBody, GregError, LogErr, RouteResult},
+    types::AppData,
+};
+
+#[utoipa::path(
+    tag = "backup",
+    responses(
+        (status = 200, description = "Every source, activity, roadmap check and user as a JSON archive. Only `greg restore` can restore it", body = Backup),
+        (status = 401, description = "Not logged in", body = ErrorBody),
+        (status = 403, description = "Not an admin", body = ErrorBody),
+        (status = 409, description = "The app database is PostgreSQL, backups only work on libsql", body = ErrorBody),
+    ),
+    security(("session" = []), ("token" = [])),
+)]
+#[get("/backup")]
+pub async fn download_backup(data: AppData, _admin: AdminUser) -> RouteResult {
+    let Some(database) = data.app.libsql() else {
+        error!("[Backup] Backups only work on libsql");
+        return Err(GregError::Conflict(
+            "Backups only work on libsql databases".into(),
+        ));
+    };
+    let db = db::connect(database)
+        .await
+        .log_err("[Backup] Connecting to db failed")?;
+
+    info!("[Backup] Exporting db");
+    let backup = backup::export(db)
+        .await
+        .log_err("[Backup] Exporting db failed")?;
+
+    info!("[Backup] Exported {} rows", backup.row_count());
+    let filename = format!(
+        "greg-backup-{}.json",
+        backup.created.date().to_string().replace(' ', "-")
+    );
+    Ok(HttpResponse::Ok()
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(filename)],
+        })
+        .json(backup))
+}
@@ -0,0 +1,103 @@
+//! The resource based API under `/api/v1`. Creating returns 201 with the new
+//! resource, deleting returns 204 and updates use PATCH.
+
+use actix_web::{HttpResponse, Responder, get, web};
+use serde::Deserialize;
+use utoipa::IntoParams;
+
+use crate::{
+    auth::AuthUser,
+    db, openapi,
+    types::{AppData, Cursor, DatabaseHealth, DatabaseMode},
+};
+
+pub mod activities;
+pub mod backup;
+pub mod roadmap;
+pub mod search;
+pub mod sessions;
+pub mod sources;
+pub mod tokens;
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct PaginationQuery {
+    /// How many to return, defaults to 35
+    pub count: Option<u32>,
+    /// `next_cursor` of the previous page, leave out for the first page
+    #[param(value_type = Option<String>)]
+    pub cursor: Option<Cursor>,
+}
+
+#[utoipa::path(
+    tag = "meta",
+    responses(
+        (status = 204, description = "Server is up"),
+    ),
+)]
+#[get("/health")]
+pub async fn health() -> impl Responder {
+    HttpResponse::NoContent().finish()
+}
+
+#[utoipa::path(
+    tag = "meta",
+    responses(
+        (status = 200, description = "Where the app database lives and, for a replica, how its syncs went", body = DatabaseHealth),
+    ),
+    security((), ("session" = []), ("token" = [])),
+)]
+#[get("/health/database")]
+pub async fn database_health(data: AppData, user: Option<AuthUser>) -> impl Responder {
+    let mode = db::database_mode(&data.config.database);
+    let sync = (mode == DatabaseMode::Replica).then(|| {
+        let mut sync = data.db_sync.lock().unwrap().clone();
+        // Sync errors can name the remote server, so only users see them
+        if user.is_none() {
+            sync.last_error = None;
+            sync.frame_no = None;
+        }
+        sync
+    });
+
+    HttpResponse::Ok().json(DatabaseHealth { mode, sync })
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(health)
+        .service(database_health)
+        .service(sessions::create_session)
+        .service(sessions::list_sessions)
+        .service(sessions::delete_sessions)
+        .service(sessions::get_current_session)
+        .service(sessions::delete_current_session)
+        .service(sessions::delete_session)
+        .service(tokens::list_tokens)
+        .service(tokens::create_token)
+        .service(tokens::delete_token)
+        .service(sources::list_sources)
+        .service(sources::create_source)
+        .service(sources::preview_source)
+        .service(sources::check_sources)
+        .service(sources::get_source)
+        .service(sources::update_source)
+        .service(sources::delete_source)
+        .service(sources::check_source)
+        .service(sources::accept_redirect)
+        .service(sources::list_source_events)
+        .service(sources::list_source_activities)
+        .service(activities::list_activities)
+        .service(activities::delete_activities)
+        .service(roadmap::list_activities)
+        .service(roadmap::list_changes)
+        .service(roadmap::get_card_history)
+        .service(roadmap::compare_roadmaps)
+        .service(roadmap::list_tabs)
+        .service(roadmap::check_roadmap)
+        .service(roadmap::list_watched_tabs)
+        .service(roadmap::create_watched_tab)
+        .service(roadmap::delete_watched_tab)
+        .service(search::search)
+        .service(backup::download_backup)
+        .configure(openapi::configure);
+}
@@ -0,0 +1,315 @@
+use std::collections::HashSet;
+
+use actix_web::{HttpResponse, delete, get, post, web};
+use log::{error, info};
+use serde::Deserialize;
+use time::OffsetDateTime;
+use utoipa::IntoParams;
+
+use crate::{
+    auth::{AdminUser, AuthUser, DemoQuery, Viewer},
+    error::{ErrorBody, GregError, LogErr, RouteResult, is_unique_violation},
+    routes::v1::PaginationQuery,
+    tasks,
+    types::{
+        AddWatchedTab, AppData, Page, RCardHistory, RComparison, RDBChangeAlt, RTab,
+        RoadmapActivity, RoadmapWatchedTab, Success,
+    },
+};
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct CompareQuery {
+    /// Roadmap activity to compare from
+    pub from: u32,
+    /// Roadmap activity to compare to
+    pub to: u32,
+}
+
+/// Watches a tab for the user, for both this and the deprecated route.
+/// Returns the id of the watched tab.
+pub(crate) async fn watch_tab(
+    data: &AppData,
+    user_id: u32,
+    tab_id: &str,
+) -> Result<u32, GregError> {
+    let db = data.connect(false).await?;
+
+    info!("[Add Watched Tab] Inserting tab to db");
+    let id = match db.add_watched_tab(user_id, tab_id).await {
+        Err(err) if is_unique_violation(err.as_ref()) => {
+            error!("[Add Watched Tab] Tab is already watched");
+            return Err(GregError::Conflict("Tab is already watched".into()));
+        }
+        result => result.log_err("[Add Watched Tab] Inserting watched tab failed")?,
+    };
+
+    info!("[Add Watched Tab] Inserting watched tab successful");
+    Ok(id)
+}
+
+/// Stops watching a tab, for both this and the deprecated route
+pub(crate) async fn unwatch_tab(data: &AppData, user_id: u32, id: u32) -> Result<(), GregError> {
+    let db = data.connect(false).await?;
+
+    let deleted = db
+        .delete_watched_tab(user_id, id)
+        .await
+        .log_err("[Delete Watched Tab] Deleting watched tab failed")?;
+
+    if deleted == 0 {
+        error!("[Delete Watched Tab] Watched tab {id} does not exist");
+        return Err(GregError::NotFound(format!(
+            "Watched tab {id} does not exist"
+        )));
+    }
+
+    info!("[Delete Watched Tab] Deleted watched tab successfully");
+    Ok(())
+}
+
+#[utoipa::path(
+    tag = "roadmap",
+    operation_id = "list_roadmap_activities",
+    params(DemoQuery, PaginationQuery),
+    responses(
+        (status = 200, description = "Roadmap checks, newest first", body = Page<RoadmapActivity>),
+        (status = 401, description = "Not logged in", body = ErrorBody),
+    ),
+    security((), ("session" = []), ("token" = [])),
+)]
+#[get("/roadmap/activities")]
+pub async fn list_activities(viewer: Viewer, query: web::Query<PaginationQuery>) -> RouteResult {
+    info!("[Get Roadmap Activity] Getting activities from db");
+    let activities = viewer
+        .db
+        .get_roadmap_activities(viewer.user_id, query.count.unwrap_or(35), query.cursor)
+        .await
+        .log_err("[Get Roadmap Activity] Getting roadmap activities failed")?;
+
+    info!("[Get Roadmap Activity] Got activities successfully");
+    Ok(HttpResponse::Ok().json(activities))
+}
+
+#[utoipa::path(
+    tag = "roadmap",
+    params(("id" = u32, Path), DemoQuery),
+    responses(
+        (status = 200, description = "Changes found by a roadmap check on the watched tabs", body = Vec<RDBChangeAlt>),
+        (status = 401, description = "Not logged in", body = ErrorBody),
+    ),
+    security((), ("session" = []), ("token" = [])),
+)]
+#[get("/roadmap/activities/{id}/changes")]
+pub async fn list_changes(viewer: Viewer, path: web::Path<u32>) -> RouteResult {
+    let activity_id = path.into_inner();
+
+    info!("[Get Roadmap Changes] Getting changes from db");
+    let changes = viewer
+        .db
+        .get_roadmap_changes(viewer.user_id, activity_id)
+        .await
+        .log_err("[Get Roadmap Changes] Getting roadmap changes failed")?;
+
+    info!("[Get Roadmap Changes] Got changes successfully");
+    Ok(HttpResponse::Ok().json(changes))
+}
+
+#[utoipa::path(
+    tag = "roadmap",
+    params(("roadmap_id" = String, Path, description = "Roadmap id of the card"), DemoQuery),
+    responses(
+        (status = 200, description = "Every version of the card and the changes to it on the watched tabs", body = RCardHistory),
+        (status = 401, description = "Not logged in", body = ErrorBody),
+        (status = 404, description = "Card has never been on a watched tab", body = ErrorBody),
+    ),
+    security((), ("session" = []), ("token" = [])),
+)]
+#[get("/roadmap/cards/{roadmap_id}/history")]
+pub async fn get_card_history(viewer: Viewer, path: web::Path<String>) -> RouteResult {
+    let card_id = path.into_inner();
+
+    info!("[Get Card History] Getting history of {card_id} from db");
+    let Some(history) = viewer
+        .db
+        .get_card_history(viewer.user_id, &card_id)
+        .await
+        .log_err("[Get Card History] Getting card history failed")?
+    else {
+        error!("[Get Card History] Card {card_id} does not exist");
+        return Err(GregError::NotFound(format!(
+            "Card {card_id} does not exist"
+        )));
+    };
+
+    info!("[Get Card History] Got card history successfully");
+    Ok(HttpResponse::Ok().json(history))
+}
+
+#[utoipa::path(
+    tag = "roadmap",
+    params(CompareQuery, DemoQuery),
+    responses(
+        (status = 200, description = "Changes between the two roadmap checks on the watched tabs", body = RComparison),
+        (status = 401, description = "Not logged in", body = ErrorBody),
+        (status = 404, description = "Roadmap activity does not exist", body = ErrorBody),
+    ),
+    security((), ("session" = []), ("token" = [])),
+)]
+#[get("/roadmap/compare")]
+pub async fn compare_roadmaps(viewer: Viewer, query: web::Query<CompareQuery>) -> RouteResult {
+    let CompareQuery { from, to } = query.into_inner();
+
+    for id in [from, to] {
+        let exists = viewer
+            .db
+            .roadmap_activity_exists(id)
+            .await
+            .log_err("[Compare Roadmaps] Getting roadmap activity failed")?;
+        if !exists {
+            error!("[Compare Roadmaps] Roadmap activity {id} does not exist");
+            return Err(GregError::NotFound(format!(
+                "Roadmap activity {id} does not exist"
+            )));
+        }
+    }
+
+    let watched: HashSet<String> = viewer
+        .db
+        .get_watched_tabs(viewer.user_id)
+        .await
+        .log_err("[Compare Roadmaps] Getting watched tabs failed")?
+        .into_iter()
+        .map(|tab| tab.tab_id)
+        .collect();
+
+    info!("[Compare Roadmaps] Getting roadmaps {from} and {to} from db");
+    let mut previous = viewer
+        .db
+        .get_roadmap(from)
+        .await
+        .log_err("[Compare Roadmaps] Getting roadmap failed")?;
+    let mut current = viewer
+        .db
+        .get_roadmap(to)
+        .await
+        .log_err("[Compare Roadmaps] Getting roadmap failed")?;
+
+    // Checks keep the tabs anyone watches, only the user's are compared
+    for roadmap in [&mut previous, &mut current] {
+        roadmap.tabs.retain(|tab| watched.contains(&tab.id));
+        roadmap.cards.retain(|tab_id, _| watched.contains(tab_id));
+    }
+
+    let changes = tasks::check_roadmap::describe_changes(&previous, &current);
+
+    info!("[Compare Roadmaps] Found {} changes", changes.len());
+    Ok(HttpResponse::Ok().json(RComparison { from, to, changes }))
+}
+
+#[utoipa::path(
+    tag = "roadmap",
+    params(DemoQuery),
+    responses(
+        (status = 200, description = "Tabs on the most recent roadmap", body = Vec<RTab>),
+        (status = 401, description = "Not logged in", body = ErrorBody),
+    ),
+    security((), ("session" = []), ("token" = [])),
+)]
+#[get("/roadmap/tabs")]
+pub async fn list_tabs(viewer: Viewer) -> RouteResult {
+    info!("[Get Roadmap Tabs] Getting most recent tabs from db");
+    let tabs = viewer
+        .db
+        .get_most_recent_roadmap_tabs()
+        .await
+        .log_err("[Get Roadmap Tabs] Getting roadmap tabs failed")?;
+
+    info!("[Get Roadmap Tabs] Got tabs successfully");
+    Ok(HttpResponse::Ok().json(tabs))
+}
+
+#[utoipa::path(
+    tag = "roadmap",
+    responses(
+        (status = 200, description = "Roadmap checked", body = Success),
+        (status = 401, description = "Not logged in", body = ErrorBody),
+        (status = 403, description = "Not an admin", body = ErrorBody),
+    ),
+    security(("session" = []), ("token" = [])),
+)]
+#[post("/roadmap/check")]
+pub async fn check_roadmap(data: AppData, _admin: AdminUser) -> RouteResult {
+    tasks::check_roadmap::check_roadmap(&data).await;
+    Ok(HttpResponse::Ok().json(Success {
+        message: "Rechecked Roadmap Successfully".into(),
+    }))
+}
+
+#[utoipa::path(
+    tag = "roadmap",
+    params(DemoQuery),
+    responses(
+        (status = 200, description = "Tabs the user gets notified about", body = Vec<RoadmapWatchedTab>),
+        (status = 401, description = "Not logged in", body = ErrorBody),
+    ),
+    security((), ("session" = []), ("token" = [])),
+)]
+#[get("/roadmap/watched-tabs")]
+pub async fn list_watched_tabs(viewer: Viewer) -> RouteResult {
+    info!("[Get Watched Tabs] Getting watched tabs from db");
+    let watched_tabs = viewer
+        .db
+        .get_watched_tabs(viewer.user_id)
+        .await
+        .log_err("[Get Watched Tabs] Getting watched tabs failed")?;
+
+    info!("[Get Watched Tabs] Got watched tabs successfully");
+    Ok(HttpResponse::Ok().json(watched_tabs))
+}
+
+#[utoipa::path(
+    tag = "roadmap",
+    request_body = AddWatchedTab,
+    responses(
+        (status = 201, description = "Tab watched", body = RoadmapWatchedTab),
+        (status = 401, description = "Not logged in", body = ErrorBody),
+        (status = 409, description = "Tab is already watched", body = ErrorBody),
+    ),
+    security(("session" = []), ("token" = [])),
+)]
+#[post("/roadmap/watched-tabs")]
+pub async fn create_watched_tab(
+    tab: web::Json<AddWatchedTab>,
+    data: AppData,
+    user: AuthUser,
+) -> RouteResult {
+    let tab = tab.into_inner();
+    let id = watch_tab(&data, user.id, &tab.tab_id).await?;
+
+    Ok(HttpResponse::Created().json(RoadmapWatchedTab {
+        id,
+        tab_id: tab.tab_id,
+        timestamp: OffsetDateTime::now_utc(),
+    }))
+}
+
+#[utoipa::path(
+    tag = "roadmap",
+    params(("id" = u32, Path)),
+    responses(
+        (status = 204, description = "Tab no longer watched"),
+        (status = 401, description = "Not logged in", body = ErrorBody),
+        (status = 404, description = "Watched tab does not exist", body = ErrorBody),
+    ),
+    security(("session" = []), ("token" = [])),
+)]
+#[delete("/roadmap/watched-tabs/{id}")]
+pub async fn delete_watched_tab(
+    path: web::Path<u32>,
+    data: AppData,
+    user: AuthUser,
+) -> RouteResult {
+    unwatch_tab(&data, user.id, path.into_inner()).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
@@ -0,0 +1,95 @@
+use actix_web::{HttpResponse, get, web};
+use log::info;
+use serde::Deserialize;
+use time::{Date, Duration, Month, OffsetDateTime, Time};
+use utoipa::IntoParams;
+
+use crate::{
+    auth::{DemoQuery, Viewer},
+    error::{ErrorBody, GregError, LogErr, RouteResult},
+    repo::{SearchFilters, search_all},
+    types::SearchHit,
+};
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SearchQuery {
+    /// Words to look for, the last one can be the start of a word
+    q: String,
+    /// Only posts from this source
+    source: Option<u32>,
+    /// Only cards that have been on this tab, by its roadmap id
+    tab: Option<String>,
+    /// Only results from this day on, as `YYYY-MM-DD`
+    from: Option<String>,
+    /// Only results up to and including this day, as `YYYY-MM-DD`
+    to: Option<String>,
+    /// How many to return, defaults to 20 and can be at most 100
+    limit: Option<u32>,
+}
+
+/// Start of the day in UTC
+fn parse_day(day: &str) -> Result<OffsetDateTime, GregError> {
+    let invalid = || GregError::BadRequest(format!("Invalid date {day}, expected YYYY-MM-DD"));
+
+    let mut parts = day.splitn(3, '-');
+    let mut next = || parts.next().and_then(|part| part.parse::<i32>().ok());
+    let (Some(year), Some(month), Some(day)) = (next(), next(), next()) else {
+        return Err(invalid());
+    };
+
+    let month = u8::try_from(month)
+        .ok()
+        .and_then(|month| Month::try_from(month).ok())
+        .ok_or_else(invalid)?;
+    let day = u8::try_from(day).map_err(|_| invalid())?;
+    let date = Date::from_calendar_date(year, month, day).map_err(|_| invalid())?;
+
+    Ok(date.with_time(Time::MIDNIGHT).assume_utc())
+}
+
+#[utoipa::path(
+    tag = "search",
+    params(SearchQuery, DemoQuery),
+    responses(
+        (status = 200, description = "Posts and roadmap cards that match, best first", body = Vec<SearchHit>),
+        (status = 400, description = "Empty query or invalid date", body = ErrorBody),
+        (status = 401, description = "Not logged in", body = ErrorBody),
+    ),
+    security((), ("session" = []), ("token" = [])),
+)]
+#[get("/search")]
+pub async fn search(viewer: Viewer, query: web::Query<SearchQuery>) -> RouteResult {
+    if query.q.trim().is_empty() {
+        return Err(GregError::BadRequest("Search query is empty".into()));
+    }
+
+    let filters = SearchFilters {
+        source_id: query.source,
+        tab_id: query.tab.clone(),
+        from: query.from.as_deref().map(parse_day).transpose()?,
+        to: query
+            .to
+            .as_deref()
+            .map(|day| {
+                parse_day(day)?.checked_add(Duration::DAY).ok_or_else(|| {
+                    GregError::BadRequest(format!("Invalid date {day}, it's too far ahead"))
+                })
+            })
+            .transpose()?,
+    };
+
+    info!("[Search] Searching for {}", query.q);
+    let hits = search_all(
+        viewer.db.as_ref(),
+        viewer.user_id,
+        &query.q,
+        &filters,
+        query.limit.unwrap_or(20).clamp(1, 100),
+    )
+    .await
+    .log_err("[Search] Searching failed")?;
+
+    info!("[Search] Found {} results", hits.len());
+    Ok(HttpResponse::Ok().json(hits))
+}
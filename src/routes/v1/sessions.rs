@@ -0,0 +1,252 @@
+use actix_web::{HttpRequest, HttpResponse, delete, get, http::header::USER_AGENT, post, web};
+use log::{error, info};
+use time::OffsetDateTime;
+
+use crate::{
+    auth::{AuthUser, SessionUser, client_ip},
+    error::{ErrorBody, GregError, LogErr, RouteResult},
+    queries::users,
+    repo::Repo,
+    types::{AppData, LOGGED_IN_COOKIE, LoginInfo, Session},
+    utils::{random_key, removal_cookie, session_cookie},
+};
+
+async fn record_login_attempt(db: &dyn Repo, ip: &str, username: &str, reason: &str) {
+    if let Err(err) = db.add_login_attempt(ip, username, reason).await {
+        error!("[Login] Recording login attempt failed with err: {err}");
+    }
+}
+
+/// Checks the password, going through the login limiter, and starts a
+/// session. Returns the session and the key for its cookie.
+pub(crate) async fn log_in(
+    data: &AppData,
+    req: &HttpRequest,
+    login_info: &LoginInfo,
+) -> Result<(Session, String), GregError> {
+    let username = login_info
+        .username
+        .as_deref()
+        .unwrap_or(&data.config.auth.admin_username);
+    let ip = client_ip(req, &data.config.server);
+
+    let db = data.connect(false).await?;
+
+    let attempt = match data.login_limiter.check(&ip, username) {
+        Ok(attempt) => attempt,
+        Err(blocked) => {
+            error!(
+                "[Login] Refused login for user: {username} from {ip}, {}",
+                blocked.reason
+            );
+            // Not recorded, so a locked out client can't fill the table
+            return Err(GregError::RateLimited {
+                retry_after: blocked.retry_after.as_secs().max(1),
+            });
+        }
+    };
+
+    let user = db
+        .get_user_by_username(username)
+        .await
+        .log_err("[Login] Getting user failed")?;
+
+    // Checking against a throwaway hash when there's no such user keeps the
+    // response time from giving away which usernames exist
+    let password_hash = user.as_ref().map_or_else(
+        || users::DUMMY_HASH.clone(),
+        |user| user.password_hash.clone(),
+    );
+    let password = login_info.password.clone();
+    let verified = web::block(move || users::verify_password(&password_hash, &password))
+        .await
+        .log_err("[Login] Verifying password failed")?;
+
+    let Some(user) = user.filter(|_| verified) else {
+        error!("[Login] Login failed for user: {username} from {ip}");
+        record_login_attempt(db.as_ref(), &ip, username, "wrong_password").await;
+        return Err(GregError::WrongPassword);
+    };
+
+    data.login_limiter.record_success(attempt);
+    let key = random_key();
+
+    info!("[Login] Inserting login key");
+    let user_agent = req
+        .headers()
+        .get(USER_AGENT)
+        .and_then(|ua| ua.to_str().ok());
+    let id = db
+        .add_session(user.id, &key, user_agent)
+        .await
+        .log_err("[Login] Inserting login key failed")?;
+    info!("[Login] Insert successful");
+
+    match db
+        .prune_sessions(data.config.auth.session_lifetime_mins)
+        .await
+    {
+        Ok(0) => {}
+        Ok(pruned) => info!("[Login] Pruned {pruned} expired sessions"),
+        Err(err) => error!("[Login] Pruning sessions failed with err: {err}"),
+    }
+
+    let now = OffsetDateTime::now_utc();
+    let session = Session {
+        id,
+        key: String::new(),
+        timestamp: now,
+        last_seen: now,
+        user_agent: user_agent.map(str::to_owned),
+        current: true,
+    };
+
+    Ok((session, key))
+}
+
+#[utoipa::path(
+    tag = "sessions",
+    request_body = LoginInfo,
+    responses(
+        (status = 201, description = "Logged in, sets the session cookie", body = Session),
+        (status = 401, description = "Wrong username or password", body = ErrorBody),
+        (status = 429, description = "Too many failed logins", body = ErrorBody, headers(("Retry-After" = u64, description = "Seconds until logging in is allowed again"))),
+        (status = 500, description = "Server error", body = ErrorBody),
+    ),
+)]
+#[post("/sessions")]
+pub async fn create_session(
+    login_info: web::Json<LoginInfo>,
+    data: AppData,
+    req: HttpRequest,
+) -> RouteResult {
+    let (session, key) = log_in(&data, &req, &login_info).await?;
+
+    Ok(HttpResponse::Created()
+        .cookie(session_cookie(key))
+        .json(session))
+}
+
+#[utoipa::path(
+    tag = "sessions",
+    responses(
+        (status = 200, description = "Sessions of the user, newest first", body = Vec<Session>),
+        (status = 401, description = "Not logged in", body = ErrorBody),
+    ),
+    security(("session" = []), ("token" = [])),
+)]
+#[get("/sessions")]
+pub async fn list_sessions(data: AppData, user: AuthUser) -> RouteResult {
+    let db = data.connect(false).await?;
+
+    info!("[Get Sessions] Getting sessions from db");
+    let mut sessions = db
+        .get_sessions(user.id)
+        .await
+        .log_err("[Get Sessions] Getting sessions failed")?;
+    for session in sessions.iter_mut() {
+        session.current = Some(session.id) == user.session_id;
+    }
+
+    info!("[Get Sessions] Got sessions successfully");
+    Ok(HttpResponse::Ok().json(sessions))
+}
+
+#[utoipa::path(
+    tag = "sessions",
+    responses(
+        (status = 204, description = "Every session of the user deleted"),
+        (status = 401, description = "Not logged in", body = ErrorBody),
+    ),
+    security(("session" = []), ("token" = [])),
+)]
+#[delete("/sessions")]
+pub async fn delete_sessions(data: AppData, user: AuthUser) -> RouteResult {
+    let db = data.connect(false).await?;
+
+    let deleted = db
+        .delete_user_sessions(user.id)
+        .await
+        .log_err("[Logout All] Deleting sessions failed")?;
+
+    info!("[Logout All] Deleted {deleted} sessions");
+    Ok(HttpResponse::NoContent().cookie(removal_cookie()).finish())
+}
+
+#[utoipa::path(
+    tag = "sessions",
+    responses(
+        (status = 200, description = "The session making the request", body = Session),
+        (status = 401, description = "Not logged in", body = ErrorBody),
+        (status = 403, description = "Logged in with an API token", body = ErrorBody),
+    ),
+    security(("session" = [])),
+)]
+#[get("/sessions/current")]
+pub async fn get_current_session(data: AppData, SessionUser(user): SessionUser) -> RouteResult {
+    let db = data.connect(false).await?;
+
+    let session = db
+        .get_sessions(user.id)
+        .await
+        .log_err("[Get Session] Getting sessions failed")?
+        .into_iter()
+        .find(|session| Some(session.id) == user.session_id)
+        .ok_or(GregError::Unauthorized)?;
+
+    Ok(HttpResponse::Ok().json(Session {
+        current: true,
+        ..session
+    }))
+}
+
+#[utoipa::path(
+    tag = "sessions",
+    responses(
+        (status = 204, description = "Logged out and the cookie removed"),
+    ),
+)]
+#[delete("/sessions/current")]
+pub async fn delete_current_session(data: AppData, req: HttpRequest) -> RouteResult {
+    if let Some(key) = req.cookie(LOGGED_IN_COOKIE) {
+        let db = data.connect(false).await?;
+        db.delete_session(key.value())
+            .await
+            .log_err("[Logout] Deleting session failed")?;
+    }
+
+    Ok(HttpResponse::NoContent().cookie(removal_cookie()).finish())
+}
+
+#[utoipa::path(
+    tag = "sessions",
+    params(("id" = u32, Path)),
+    responses(
+        (status = 204, description = "Session deleted"),
+        (status = 401, description = "Not logged in", body = ErrorBody),
+        (status = 404, description = "Session does not exist", body = ErrorBody),
+    ),
+    security(("session" = []), ("token" = [])),
+)]
+#[delete("/sessions/{id}")]
+pub async fn delete_session(path: web::Path<u32>, data: AppData, user: AuthUser) -> RouteResult {
+    let db = data.connect(false).await?;
+    let id = path.into_inner();
+
+    let deleted = db
+        .delete_session_by_id(user.id, id)
+        .await
+        .log_err("[Delete Session] Deleting session failed")?;
+
+    if deleted == 0 {
+        error!("[Delete Session] Session {id} does not exist");
+        return Err(GregError::NotFound(format!("Session {id} does not exist")));
+    }
+
+    info!("[Delete Session] Deleted session {id}");
+    let mut res = HttpResponse::NoContent();
+    if user.session_id == Some(id) {
+        res.cookie(removal_cookie());
+    }
+    Ok(res.finish())
+}
@@ -0,0 +1,394 @@
+use actix_web::{HttpResponse, delete, get, patch, post, web};
+use feed_rs::parser;
+use log::{error, info};
+use url::Url;
+
+use crate::{
+    auth::{AdminUser, AuthUser, DemoQuery, Viewer},
+    error::{ErrorBody, GregError, LogErr, RouteResult, is_unique_violation},
+    repo::Repo,
+    routes::v1::PaginationQuery,
+    tasks::{
+        self,
+        check_sources::{SourceActivity, SourcePreview, check_single_source},
+    },
+    types::{Activity, AddSource, AppData, Page, Source, SourceEvent, Success, UpdateSource},
+};
+
+async fn test_source(url: &str, client: &reqwest::Client) -> Result<(), GregError> {
+    if let Err(err) = Url::parse(url) {
+        error!("[Add Source] Failed with error: {err} for url: {url}");
+        return Err(GregError::BadRequest(format!("Invalid url. Err: {err}")));
+    }
+    let res = match client.get(url).send().await {
+        Ok(x) => x,
+        Err(err) => {
+            error!("[Add Source] Failed due to network error: {err} for url: {url}");
+            return Err(GregError::FeedUnavailable(format!(
+                "Couldn't fetch source. Err: {err}"
+            )));
+        }
+    };
+    let bytes = match res.bytes().await {
+        Ok(x) => x,
+        Err(err) => {
+            error!("[Add Source] Failed due to network error: {err} for url: {url}");
+            return Err(GregError::FeedUnavailable(format!(
+                "Couldn't fetch source. Err: {err}"
+            )));
+        }
+    };
+    if let Err(err) = parser::parse(&bytes[..]) {
+        error!("[Add Source] Failed due to result parse error: {err} for url: {url}");
+        return Err(GregError::FeedUnavailable(format!(
+            "Couldn't parse source. Err: {err}"
+        )));
+    }
+
+    Ok(())
+}
+
+/// Makes sure the feed works before adding it. Returns the new source's id
+pub(crate) async fn insert_source(
+    data: &AppData,
+    user_id: u32,
+    url: &str,
+) -> Result<u32, GregError> {
+    let db = data.connect(false).await?;
+    test_source(url, &data.client).await?;
+
+    info!("[Add Source] Inserting source to db");
+    let id = match db.add_source(user_id, url).await {
+        Err(err) if is_unique_violation(err.as_ref()) => {
+            error!("[Add Source] Source {url} already exists");
+            return Err(GregError::Conflict("Source already exists".into()));
+        }
+        result => result.log_err("[Add Source] Inserting source failed")?,
+    };
+
+    info!("[Add Source] Inserting source successful");
+    Ok(id)
+}
+
+async fn find_source(
+    db: &dyn Repo,
+    user_id: u32,
+    source_id: u32,
+    tag: &str,
+) -> Result<Source, GregError> {
+    db.get_source(user_id, source_id)
+        .await
+        .log_err(&format!("{tag} Getting source failed"))?
+        .ok_or_else(|| {
+            error!("{tag} Source {source_id} does not exist");
+            GregError::NotFound(format!("Source {source_id} does not exist"))
+        })
+}
+
+#[utoipa::path(
+    tag = "sources",
+    params(DemoQuery),
+    responses(
+        (status = 200, description = "Sources of the user", body = Vec<Source>),
+        (status = 401, description = "Not logged in", body = ErrorBody),
+    ),
+    security((), ("session" = []), ("token" = [])),
+)]
+#[get("/sources")]
+pub async fn list_sources(viewer: Viewer) -> RouteResult {
+    info!("[Get Sources] Getting sources from db");
+    let sources = viewer
+        .db
+        .get_sources(viewer.user_id)
+        .await
+        .log_err("[Get Sources] Getting sources failed")?;
+
+    info!("[Get Sources] Got sources successfully");
+    Ok(HttpResponse::Ok().json(sources))
+}
+
+#[utoipa::path(
+    tag = "sources",
+    request_body = AddSource,
+    responses(
+        (status = 201, description = "Source added", body = Source),
+        (status = 400, description = "Invalid url", body = ErrorBody),
+        (status = 401, description = "Not logged in", body = ErrorBody),
+        (status = 409, description = "Source already exists", body = ErrorBody),
+        (status = 502, description = "Feed couldn't be fetched or parsed", body = ErrorBody),
+    ),
+    security(("session" = []), ("token" = [])),
+)]
+#[post("/sources")]
+pub async fn create_source(
+    source: web::Json<AddSource>,
+    data: AppData,
+    user: AuthUser,
+) -> RouteResult {
+    let id = insert_source(&data, user.id, &source.url).await?;
+    let source = find_source(
+        data.connect(false).await?.as_ref(),
+        user.id,
+        id,
+        "[Add Source]",
+    )
+    .await?;
+
+    Ok(HttpResponse::Created().json(source))
+}
+
+#[utoipa::path(
+    tag = "sources",
+    request_body = AddSource,
+    responses(
+        (status = 200, description = "What the feed would look like as a source", body = SourcePreview),
+        (status = 400, description = "Invalid url", body = ErrorBody),
+        (status = 401, description = "Not logged in", body = ErrorBody),
+        (status = 502, description = "Feed couldn't be fetched or parsed", body = ErrorBody),
+    ),
+    security(("session" = []), ("token" = [])),
+)]
+#[post("/sources/preview")]
+pub async fn preview_source(
+    source: web::Json<AddSource>,
+    data: AppData,
+    _user: AuthUser,
+) -> RouteResult {
+    if let Err(err) = Url::parse(&source.url) {
+        error!(
+            "[Preview Source] Failed with error: {err} for url: {}",
+            source.url
+        );
+        return Err(GregError::BadRequest(format!("Invalid url. Err: {err}")));
+    }
+
+    info!("[Preview Source] Previewing source {}", source.url);
+    let preview = tasks::check_sources::preview_source(&data, &source.url)
+        .await
+        .map_err(|err| {
+            GregError::FeedUnavailable(format!("Couldn't preview source. Err: {err}"))
+        })?;
+
+    info!("[Preview Source] Previewed source successfully");
+    Ok(HttpResponse::Ok().json(preview))
+}
+
+#[utoipa::path(
+    tag = "sources",
+    responses(
+        (status = 200, description = "Every source checked", body = Success),
+        (status = 401, description = "Not logged in", body = ErrorBody),
+        (status = 403, description = "Not an admin", body = ErrorBody),
+    ),
+    security(("session" = []), ("token" = [])),
+)]
+#[post("/sources/check")]
+pub async fn check_sources(data: AppData, _admin: AdminUser) -> RouteResult {
+    tasks::check_sources::check_sources(&data).await;
+    Ok(HttpResponse::Ok().json(Success {
+        message: "Rechecked Sources Successfully".into(),
+    }))
+}
+
+#[utoipa::path(
+    tag = "sources",
+    params(("id" = u32, Path), DemoQuery),
+    responses(
+        (status = 200, description = "The source", body = Source),
+        (status = 401, description = "Not logged in", body = ErrorBody),
+        (status = 404, description = "Source does not exist", body = ErrorBody),
+    ),
+    security((), ("session" = []), ("token" = [])),
+)]
+#[get("/sources/{id}")]
+pub async fn get_source(viewer: Viewer, path: web::Path<u32>) -> RouteResult {
+    let source = find_source(
+        viewer.db.as_ref(),
+        viewer.user_id,
+        path.into_inner(),
+        "[Get Source]",
+    )
+    .await?;
+    Ok(HttpResponse::Ok().json(source))
+}
+
+#[utoipa::path(
+    tag = "sources",
+    params(("id" = u32, Path)),
+    request_body = UpdateSource,
+    responses(
+        (status = 200, description = "The updated source", body = Source),
+        (status = 401, description = "Not logged in", body = ErrorBody),
+        (status = 404, description = "Source does not exist", body = ErrorBody),
+    ),
+    security(("session" = []), ("token" = [])),
+)]
+#[patch("/sources/{id}")]
+pub async fn update_source(
+    path: web::Path<u32>,
+    update: web::Json<UpdateSource>,
+    data: AppData,
+    user: AuthUser,
+) -> RouteResult {
+    let source_id = path.into_inner();
+    let db = data.connect(false).await?;
+
+    if let Some(enabled) = update.enabled {
+        info!(
+            "[Update Source] {} source",
+            if enabled { "Enabling" } else { "Disabling" }
+        );
+        db.set_source_enabled(user.id, source_id, enabled)
+            .await
+            .log_err("[Update Source] Updating source failed")?;
+    }
+
+    let source = find_source(db.as_ref(), user.id, source_id, "[Update Source]").await?;
+    Ok(HttpResponse::Ok().json(source))
+}
+
+#[utoipa::path(
+    tag = "sources",
+    params(("id" = u32, Path)),
+    responses(
+        (status = 204, description = "Source deleted"),
+        (status = 401, description = "Not logged in", body = ErrorBody),
+        (status = 404, description = "Source does not exist", body = ErrorBody),
+    ),
+    security(("session" = []), ("token" = [])),
+)]
+#[delete("/sources/{id}")]
+pub async fn delete_source(path: web::Path<u32>, data: AppData, user: AuthUser) -> RouteResult {
+    let db = data.connect(false).await?;
+    let id = path.into_inner();
+
+    let deleted = db
+        .delete_source(user.id, id)
+        .await
+        .log_err("[Delete Source] Deleting source failed")?;
+
+    if deleted == 0 {
+        error!("[Delete Source] Source {id} does not exist");
+        return Err(GregError::NotFound(format!("Source {id} does not exist")));
+    }
+
+    info!("[Delete Source] Deleted source successfully");
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[utoipa::path(
+    tag = "sources",
+    params(("id" = u32, Path)),
+    responses(
+        (status = 200, description = "Result of checking the source", body = SourceActivity),
+        (status = 401, description = "Not logged in", body = ErrorBody),
+        (status = 404, description = "Source does not exist", body = ErrorBody),
+    ),
+    security(("session" = []), ("token" = [])),
+)]
+#[post("/sources/{id}/check")]
+pub async fn check_source(path: web::Path<u32>, data: AppData, user: AuthUser) -> RouteResult {
+    let source_id = path.into_inner();
+    let db = data.connect(false).await?;
+
+    info!("[Recheck Source] Getting source {source_id} from db");
+    let source = find_source(db.as_ref(), user.id, source_id, "[Recheck Source]").await?;
+
+    let activity = check_single_source(&data, db, source).await;
+    info!("[Recheck Source] Rechecked source {source_id}");
+    Ok(HttpResponse::Ok().json(activity))
+}
+
+#[utoipa::path(
+    tag = "sources",
+    params(("id" = u32, Path)),
+    responses(
+        (status = 200, description = "The source, moved to its suggested url", body = Source),
+        (status = 401, description = "Not logged in", body = ErrorBody),
+        (status = 404, description = "Source has no suggested url", body = ErrorBody),
+        (status = 409, description = "Suggested url is already a source", body = ErrorBody),
+    ),
+    security(("session" = []), ("token" = [])),
+)]
+#[post("/sources/{id}/accept-redirect")]
+pub async fn accept_redirect(path: web::Path<u32>, data: AppData, user: AuthUser) -> RouteResult {
+    let source_id = path.into_inner();
+    let db = data.connect(false).await?;
+
+    info!("[Accept Redirect] Moving source {source_id} to suggested url");
+    let moved = match db.accept_redirect(user.id, source_id).await {
+        Err(err) if is_unique_violation(err.as_ref()) => {
+            error!("[Accept Redirect] Suggested url of source {source_id} is already a source");
+            return Err(GregError::Conflict(
+                "Suggested url is already a source".into(),
+            ));
+        }
+        result => result.log_err("[Accept Redirect] Updating source failed")?,
+    };
+
+    if !moved {
+        error!("[Accept Redirect] Source {source_id} has no suggested url");
+        return Err(GregError::NotFound("Source has no suggested url".into()));
+    }
+
+    info!("[Accept Redirect] Updated source successfully");
+    let source = find_source(db.as_ref(), user.id, source_id, "[Accept Redirect]").await?;
+    Ok(HttpResponse::Ok().json(source))
+}
+
+#[utoipa::path(
+    tag = "sources",
+    params(("id" = u32, Path), DemoQuery),
+    responses(
+        (status = 200, description = "History of the source, newest first", body = Vec<SourceEvent>),
+        (status = 401, description = "Not logged in", body = ErrorBody),
+    ),
+    security((), ("session" = []), ("token" = [])),
+)]
+#[get("/sources/{id}/events")]
+pub async fn list_source_events(viewer: Viewer, path: web::Path<u32>) -> RouteResult {
+    let source_id = path.into_inner();
+
+    info!("[Get Source Events] Getting events from db");
+    let events = viewer
+        .db
+        .get_source_events(viewer.user_id, source_id)
+        .await
+        .log_err("[Get Source Events] Getting events failed")?;
+
+    info!("[Get Source Events] Got events successfully");
+    Ok(HttpResponse::Ok().json(events))
+}
+
+#[utoipa::path(
+    tag = "sources",
+    params(("id" = u32, Path), DemoQuery, PaginationQuery),
+    responses(
+        (status = 200, description = "Most recent posts of the source", body = Page<Activity>),
+        (status = 401, description = "Not logged in", body = ErrorBody),
+    ),
+    security((), ("session" = []), ("token" = [])),
+)]
+#[get("/sources/{id}/activities")]
+pub async fn list_source_activities(
+    viewer: Viewer,
+    path: web::Path<u32>,
+    query: web::Query<PaginationQuery>,
+) -> RouteResult {
+    let source_id = path.into_inner();
+
+    info!("[Get Activity] Getting activities from db");
+    let activities = viewer
+        .db
+        .get_source_activity(
+            viewer.user_id,
+            query.count.unwrap_or(35),
+            query.cursor,
+            source_id,
+        )
+        .await
+        .log_err("[Get Activity] Getting activities failed")?;
+
+    info!("[Get Activity] Got activities successfully");
+    Ok(HttpResponse::Ok().json(activities))
+}
@@ -0,0 +1,97 @@
+use actix_web::{HttpResponse, delete, get, post, web};
+use log::{error, info};
+
+use crate::{
+    auth::{AuthUser, SessionUser},
+    error::{ErrorBody, GregError, LogErr, RouteResult},
+    types::{AddApiToken, ApiToken, AppData, CreatedApiToken},
+};
+
+#[utoipa::path(
+    tag = "tokens",
+    responses(
+        (status = 200, description = "API tokens of the user", body = Vec<ApiToken>),
+        (status = 401, description = "Not logged in", body = ErrorBody),
+    ),
+    security(("session" = []), ("token" = [])),
+)]
+#[get("/tokens")]
+pub async fn list_tokens(data: AppData, user: AuthUser) -> RouteResult {
+    let db = data.connect(false).await?;
+
+    info!("[Get Tokens] Getting tokens from db");
+    let tokens = db
+        .get_tokens(user.id)
+        .await
+        .log_err("[Get Tokens] Getting tokens failed")?;
+
+    info!("[Get Tokens] Got tokens successfully");
+    Ok(HttpResponse::Ok().json(tokens))
+}
+
+#[utoipa::path(
+    tag = "tokens",
+    request_body = AddApiToken,
+    responses(
+        (status = 201, description = "Token created, this is the only time it's shown", body = CreatedApiToken),
+        (status = 401, description = "Not logged in", body = ErrorBody),
+        (status = 403, description = "Logged in with an API token", body = ErrorBody),
+    ),
+    security(("session" = [])),
+)]
+#[post("/tokens")]
+pub async fn create_token(
+    token: web::Json<AddApiToken>,
+    data: AppData,
+    SessionUser(user): SessionUser,
+) -> RouteResult {
+    let db = data.connect(false).await?;
+
+    info!("[Add Token] Inserting token to db");
+    let (id, token) = db
+        .add_token(user.id, &token.name, token.scope)
+        .await
+        .log_err("[Add Token] Inserting token failed")?;
+
+    info!("[Add Token] Inserting token successful");
+    Ok(HttpResponse::Created().json(CreatedApiToken { id, token }))
+}
+
+/// Revokes a token, for both this and the deprecated route
+pub(crate) async fn revoke_token(data: &AppData, user_id: u32, id: u32) -> Result<(), GregError> {
+    let db = data.connect(false).await?;
+
+    let deleted = db
+        .delete_token(user_id, id)
+        .await
+        .log_err("[Delete Token] Deleting token failed")?;
+
+    if deleted == 0 {
+        error!("[Delete Token] Token {id} does not exist");
+        return Err(GregError::NotFound(format!("Token {id} does not exist")));
+    }
+
+    info!("[Delete Token] Deleted token successfully");
+    Ok(())
+}
+
+#[utoipa::path(
+    tag = "tokens",
+    params(("id" = u32, Path)),
+    responses(
+        (status = 204, description = "Token revoked"),
+        (status = 401, description = "Not logged in", body = ErrorBody),
+        (status = 403, description = "Logged in with an API token", body = ErrorBody),
+        (status = 404, description = "Token does not exist", body = ErrorBody),
+    ),
+    security(("session" = [])),
+)]
+#[delete("/tokens/{id}")]
+pub async fn delete_token(
+    path: web::Path<u32>,
+    data: AppData,
+    SessionUser(user): SessionUser,
+) -> RouteResult {
+    revoke_token(&data, user.id, path.into_inner()).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
@@ -1,26 +1,19 @@
-use std::{collections::HashMap, env};
+use std::collections::HashMap;
 
-use libsql::Transaction;
 use log::{error, info, warn};
 use time::OffsetDateTime;
 
 use crate::{
-    db::{R_ACTIVITIES_T, R_CARD_ASSIGNS_T, R_CARDS_T, R_CHANGES_T, R_TAB_ASSIGNS_T, R_TABS_T},
-    queries::roadmap::{get_most_recent_roadmap, get_watched_tabs},
-    types::{AppData, RCard, RChange, RTab, Roadmap, StringError, WebRoadmap},
+    repo::RoadmapCheck,
+    types::{AppData, RCard, RChange, RComparedChange, RTab, Roadmap, StringError, WebRoadmap},
     utils::clean_description,
 };
 
 const JSON_START_LANDMARK: &str = "window.pbData";
 const JSON_END_LANDMARK: &str = "</script>";
 
-pub async fn get_roadmap_json() -> anyhow::Result<String> {
-    let client = reqwest::Client::new();
-
-    let res = client
-        .get(env::var("VITE_ROADMAP_URL").expect("VITE_ROADMAP_URL exists"))
-        .send()
-        .await?;
+pub async fn get_roadmap_json(client: &reqwest::Client, url: &str) -> anyhow::Result<String> {
+    let res = client.get(url).send().await?;
 
     if !res.status().is_success() {
         return Err(StringError(format!("Request code is {}", res.status())).into());
@@ -97,7 +90,7 @@ fn web_to_saved_roadmap(mut roadmap: WebRoadmap, watched_ids: &[String]) -> Road
     url_saved_roadmap
 }
 
-fn compare_roadmaps(previous: &Roadmap, current: &Roadmap) -> Vec<RChange> {
+pub fn compare_roadmaps(previous: &Roadmap, current: &Roadmap) -> Vec<RChange> {
     info!("Started roadmap comparison");
 
     let mut all_changes: Vec<RChange> = Vec::new();
@@ -211,196 +204,126 @@ fn compare_roadmaps(previous: &Roadmap, current: &Roadmap) -> Vec<RChange> {
     all_changes
 }
 
-async fn save_card_tx(db: &Transaction, card: &RCard) -> anyhow::Result<u32> {
-    let mut result = db
-        .query(
-            &format!(
-                "INSERT INTO {R_CARDS_T} 
-                    (roadmap_id, name, description, image_url, slug, timestamp)
-                VALUES 
-                    (?1,?2,?3,?4,?5,?6)
-                RETURNING id
-                "
-            ),
-            (
-                card.id.as_str(),
-                card.name.as_str(),
-                card.description.as_str(),
-                card.image_url.as_deref(),
-                card.slug.as_str(),
-                serde_json::to_string(&OffsetDateTime::now_utc()).unwrap(),
-            ),
-        )
-        .await?;
-
-    let r = result.next().await?.unwrap();
-
-    Ok(r.get(0)?)
-}
-
-async fn save_tab_tx(db: &Transaction, tab: &RTab) -> anyhow::Result<u32> {
-    let mut result = db
-        .query(
-            &format!(
-                "INSERT INTO {R_TABS_T} 
-                    (roadmap_id, name, slug, timestamp) 
-                VALUES 
-                    (?1,?2,?3,?4)
-                RETURNING id
-                "
-            ),
-            (
-                tab.id.as_str(),
-                tab.name.as_str(),
-                tab.slug.as_str(),
-                serde_json::to_string(&OffsetDateTime::now_utc()).unwrap(),
-            ),
-        )
-        .await?;
-
-    let r = result.next().await?.unwrap();
-
-    Ok(r.get(0)?)
-}
-
-async fn new_roadmap_tx(db: &Transaction) -> anyhow::Result<u32> {
-    let mut result = db
-        .query(
-            &format!(
-                "INSERT INTO {R_ACTIVITIES_T} 
-                    (timestamp) 
-                VALUES
-                    (?1)
-                RETURNING id
-                "
-            ),
-            [serde_json::to_string(&OffsetDateTime::now_utc()).unwrap()],
-        )
-        .await?;
-
-    let r = result.next().await?.unwrap();
-
-    Ok(r.get(0)?)
-}
+/// Compares two roadmaps and looks up what each change is about. Tabs that
+/// only have cards on one side count as every card being added or removed.
+pub fn describe_changes(previous: &Roadmap, current: &Roadmap) -> Vec<RComparedChange> {
+    let card = |roadmap: &Roadmap, tab_id: &str, index: u32| {
+        roadmap
+            .cards
+            .get(tab_id)
+            .map(|cards| cards[index as usize].clone())
+    };
+    let tab = |roadmap: &Roadmap, index: u32| roadmap.tabs[index as usize].clone();
+    let tab_by_id =
+        |roadmap: &Roadmap, tab_id: &str| roadmap.tabs.iter().find(|t| t.id == tab_id).cloned();
+    let change = |r#type, previous_card, current_card, tab| RComparedChange {
+        r#type,
+        previous_card,
+        current_card,
+        tab,
+    };
 
-/// Save card assignment
-/// * `assign_ids` - activity, tab, section_pos, card_pos
-async fn save_card_assignment_tx(
-    db: &Transaction,
-    card_id: u32,
-    assign_info: &[u32; 4],
-) -> anyhow::Result<()> {
-    let _result = db
-        .execute(
-            &format!(
-                "INSERT INTO {R_CARD_ASSIGNS_T} 
-                    (activity_id, tab_id, card_id, section_position, card_position, timestamp) 
-                VALUES 
-                    (?1,?2,?3,?4,?5,?6)
-                "
-            ),
-            (
-                assign_info[0],
-                assign_info[1],
-                card_id,
-                assign_info[2],
-                assign_info[3],
-                serde_json::to_string(&OffsetDateTime::now_utc()).unwrap(),
-            ),
-        )
-        .await?;
+    let mut described = Vec::new();
+    for roadmap_change in compare_roadmaps(previous, current) {
+        match roadmap_change {
+            RChange::CardUnchanged { .. } | RChange::TabUnchanged { .. } => {}
+            RChange::TabAdded { tab_index } => {
+                described.push(change(
+                    "tab_added",
+                    None,
+                    None,
+                    Some(tab(current, tab_index)),
+                ));
+            }
+            RChange::TabRemoved { tab_index } => {
+                described.push(change(
+                    "tab_removed",
+                    None,
+                    None,
+                    Some(tab(previous, tab_index)),
+                ));
+            }
+            RChange::CardAdded { tab_id, card_index } => described.push(change(
+                "card_added",
+                None,
+                card(current, &tab_id, card_index),
+                tab_by_id(current, &tab_id),
+            )),
+            RChange::CardRemoved { tab_id, card_index } => described.push(change(
+                "card_removed",
+                card(previous, &tab_id, card_index),
+                None,
+                tab_by_id(previous, &tab_id),
+            )),
+            RChange::CardModified {
+                tab_id,
+                previous_card_index,
+                current_card_index,
+            } => described.push(change(
+                "card_modified",
+                card(previous, &tab_id, previous_card_index),
+                card(current, &tab_id, current_card_index),
+                tab_by_id(current, &tab_id),
+            )),
+            RChange::TabCardsNotInCurrent { tab_index } => {
+                let tab = tab(previous, tab_index);
+                for card in previous.cards.get(&tab.id).into_iter().flatten() {
+                    described.push(change(
+                        "card_removed",
+                        Some(card.clone()),
+                        None,
+                        Some(tab.clone()),
+                    ));
+                }
+            }
+            RChange::TabCardsNotInPrevious { tab_index } => {
+                let tab = tab(current, tab_index);
+                for card in current.cards.get(&tab.id).into_iter().flatten() {
+                    described.push(change(
+                        "card_added",
+                        None,
+                        Some(card.clone()),
+                        Some(tab.clone()),
+                    ));
+                }
+            }
+        }
+    }
 
-    Ok(())
+    described
 }
 
 async fn save_card_and_assignment(
-    db: &Transaction,
+    tx: &dyn RoadmapCheck,
     card: &RCard,
     assign_info: &[u32; 4],
 ) -> anyhow::Result<u32> {
-    let card_id = save_card_tx(db, card).await?;
-    save_card_assignment_tx(db, card_id, assign_info).await?;
+    let card_id = tx.save_card(card).await?;
+    tx.save_card_assignment(card_id, assign_info).await?;
     Ok(card_id)
 }
 
-/// Save tab assignment
-/// * `assign_ids` - activity, tab, card, section_pos, card_pos
-async fn save_tab_assignment_tx(
-    db: &Transaction,
-    activity_id: u32,
-    tab_db_id: u32,
-) -> anyhow::Result<()> {
-    let _result = db
-        .execute(
-            &format!(
-                "INSERT INTO {R_TAB_ASSIGNS_T} 
-                    (activity_id, tab_id, timestamp) 
-                VALUES 
-                    (?1,?2,?3)
-                "
-            ),
-            (
-                activity_id,
-                tab_db_id,
-                serde_json::to_string(&OffsetDateTime::now_utc()).unwrap(),
-            ),
-        )
-        .await?;
-
-    Ok(())
-}
-
 async fn save_tab_and_assignment(
-    db: &Transaction,
+    tx: &dyn RoadmapCheck,
     tab: &RTab,
     activity_id: u32,
 ) -> anyhow::Result<u32> {
-    let tab_id = save_tab_tx(db, tab)
+    let tab_id = tx
+        .save_tab(tab)
         .await
         .map_err(|e| StringError(format!("Failed to save tab {e}")))?;
 
-    save_tab_assignment_tx(db, activity_id, tab_id)
+    tx.save_tab_assignment(activity_id, tab_id)
         .await
         .map_err(|e| StringError(format!("Failed to save assignment {e}")))?;
 
     Ok(tab_id)
 }
 
-/// Save change
-/// * `change_info` - previous_card, current_card, tab
-async fn save_change_tx(
-    db: &Transaction,
-    change_type: &str,
-    activity: u32,
-    change_info: &[Option<u32>; 3],
-) -> anyhow::Result<()> {
-    let _result = db
-        .execute(
-            &format!(
-                "INSERT INTO {R_CHANGES_T} 
-                    (type, activity_id, previous_card_id, current_card_id, tab_id, timestamp) 
-                VALUES 
-                    (?1,?2,?3,?4,?5,?6)
-                "
-            ),
-            (
-                change_type,
-                activity,
-                change_info[0],
-                change_info[1],
-                change_info[2],
-                serde_json::to_string(&OffsetDateTime::now_utc()).unwrap(),
-            ),
-        )
-        .await?;
-
-    Ok(())
-}
-
 const _MAX_GOING_REQUESTS: usize = 10;
 
 async fn save_all_cards_sync_tx(
-    db: &Transaction,
+    tx: &dyn RoadmapCheck,
     roadmap: &Roadmap,
     roadmap_id: u32,
     tab_ids: &HashMap<String, u32>,
@@ -409,7 +332,7 @@ async fn save_all_cards_sync_tx(
     for k in roadmap.cards.keys() {
         for card in roadmap.cards.get(k).unwrap() {
             let _ = save_card_and_assignment(
-                db,
+                tx,
                 card,
                 &[
                     roadmap_id,
@@ -426,7 +349,7 @@ async fn save_all_cards_sync_tx(
 }
 
 async fn save_all_tab_cards_sync_tx(
-    db: &Transaction,
+    tx: &dyn RoadmapCheck,
     roadmap: &Roadmap,
     roadmap_id: u32,
     tab_ids: &HashMap<String, u32>,
@@ -441,7 +364,7 @@ async fn save_all_tab_cards_sync_tx(
 
     for card in cards {
         let _ = save_card_and_assignment(
-            db,
+            tx,
             card,
             &[
                 roadmap_id,
@@ -457,7 +380,7 @@ async fn save_all_tab_cards_sync_tx(
     Ok(())
 }
 
-async fn rollback_tx(tx: Transaction) {
+async fn rollback_tx(tx: Box<dyn RoadmapCheck>) {
     if let Err(e) = tx.rollback().await {
         error!("[Check Roadmap] Failed to rollback {e}");
     };
@@ -471,9 +394,15 @@ pub async fn check_roadmap(data: &AppData) {
 
     let start_time = OffsetDateTime::now_utc();
 
-    let db = data.app_db.connect().unwrap();
+    let db = match data.app.connect().await {
+        Ok(db) => db,
+        Err(err) => {
+            error!("[Check Roadmap] DB failed to connect {err}");
+            return;
+        }
+    };
     // Get Watched Tabs
-    let watched_tabs_result = get_watched_tabs(db.clone()).await;
+    let watched_tabs_result = db.get_all_watched_tab_ids().await;
     let Ok(watched_tabs) = watched_tabs_result else {
         error!(
             "[Check Roadmap] Failed to get watched tabs. Err: {}",
@@ -481,13 +410,13 @@ pub async fn check_roadmap(data: &AppData) {
         );
         return;
     };
-    let watched_tabs = watched_tabs
-        .into_iter()
-        .map(|rwt| rwt.tab_id)
-        .collect::<Vec<_>>();
 
     // Get roadmap
-    let roadmap_result = get_roadmap_json().await;
+    let Some(ref roadmap_url) = data.config.roadmap.url else {
+        error!("[Check Roadmap] roadmap.url is not configured");
+        return;
+    };
+    let roadmap_result = get_roadmap_json(&data.client, roadmap_url).await;
     let Ok(roadmap_string) = roadmap_result else {
         error!(
             "[Check Roadmap] Request to/parsing of roadmap page failed. Err: {}",
@@ -506,7 +435,7 @@ pub async fn check_roadmap(data: &AppData) {
     let roadmap = web_to_saved_roadmap(roadmap, &watched_tabs);
 
     // Get previous roadmap
-    let previous_roadmap_result = get_most_recent_roadmap(db.clone()).await;
+    let previous_roadmap_result = db.get_most_recent_roadmap().await;
     let Ok(previous_roadmap) = previous_roadmap_result else {
         error!(
             "[Check Roadmap] Failed to get previous roadmap. Err: {}",
@@ -548,22 +477,23 @@ pub async fn check_roadmap(data: &AppData) {
         }
 
         // Save Roadmap
-        let db = data.app_db.connect();
-        let Ok(db) = db else {
-            error!("[Check Roadmap] DB failed to connect {}", db.unwrap_err());
-            return;
+        let db = match data.app.connect().await {
+            Ok(db) => db,
+            Err(err) => {
+                error!("[Check Roadmap] DB failed to connect {err}");
+                return;
+            }
         };
 
-        let tx = db.transaction().await;
-        let Ok(tx) = tx else {
-            error!(
-                "[Check Roadmap] Failed to create transaction {}",
-                tx.err().unwrap()
-            );
-            return;
+        let tx = match db.begin_check().await {
+            Ok(tx) => tx,
+            Err(err) => {
+                error!("[Check Roadmap] Failed to create transaction {err}");
+                return;
+            }
         };
 
-        let roadmap_result = new_roadmap_tx(&tx).await;
+        let roadmap_result = tx.new_roadmap().await;
         let Ok(roadmap_id) = roadmap_result else {
             error!(
                 "[Check Roadmap] Failed to save roadmap to db err: {}",
@@ -601,7 +531,7 @@ pub async fn check_roadmap(data: &AppData) {
                     let tab = &roadmap.tabs[*tab_index as usize];
 
                     // Add tab
-                    let tab_result = save_tab_and_assignment(&tx, tab, roadmap_id).await;
+                    let tab_result = save_tab_and_assignment(tx.as_ref(), tab, roadmap_id).await;
                     let Ok(tab_id) = tab_result else {
                         error!(
                             "[Check Roadmap] Failed to save tab to db err: {}",
@@ -613,13 +543,13 @@ pub async fn check_roadmap(data: &AppData) {
 
                     tab_ids.insert(tab.id.clone(), tab_id);
 
-                    let change_result = save_change_tx(
-                        &tx,
-                        "tab_added",
-                        roadmap_id,
-                        &[Option::<u32>::None, Option::<u32>::None, Some(tab_id)],
-                    )
-                    .await;
+                    let change_result = tx
+                        .save_change(
+                            "tab_added",
+                            roadmap_id,
+                            &[Option::<u32>::None, Option::<u32>::None, Some(tab_id)],
+                        )
+                        .await;
                     let Ok(_) = change_result else {
                         error!(
                             "[Check Roadmap] Failed to save tab change to db err: {}",
@@ -631,13 +561,13 @@ pub async fn check_roadmap(data: &AppData) {
                 }
                 RChange::TabRemoved { tab_index } => {
                     let tab_id = previous_roadmap.tabs[*tab_index as usize].db_id.unwrap();
-                    let change_result = save_change_tx(
-                        &tx,
-                        "tab_removed",
-                        roadmap_id,
-                        &[Option::<u32>::None, Option::<u32>::None, Some(tab_id)],
-                    )
-                    .await;
+                    let change_result = tx
+                        .save_change(
+                            "tab_removed",
+                            roadmap_id,
+                            &[Option::<u32>::None, Option::<u32>::None, Some(tab_id)],
+                        )
+                        .await;
                     let Ok(_) = change_result else {
                         error!(
                             "[Save Change] unable to save tab change. err: {}",
@@ -650,7 +580,7 @@ pub async fn check_roadmap(data: &AppData) {
                 RChange::TabUnchanged { tab_index } => {
                     let tab_id = previous_roadmap.tabs[*tab_index as usize].db_id.unwrap();
 
-                    let assign_result = save_tab_assignment_tx(&tx, roadmap_id, tab_id).await;
+                    let assign_result = tx.save_tab_assignment(roadmap_id, tab_id).await;
                     let Ok(_) = assign_result else {
                         error!(
                             "[Check Roadmap] Failed to save tab assignment to db err: {}",
@@ -675,17 +605,17 @@ pub async fn check_roadmap(data: &AppData) {
                 RChange::CardUnchanged { tab_id, card_index } => {
                     let card = &previous_roadmap.cards.get(tab_id).unwrap()[*card_index as usize];
 
-                    let assign_result = save_card_assignment_tx(
-                        &tx,
-                        card.db_id.unwrap(),
-                        &[
-                            roadmap_id,
-                            *tab_ids.get(tab_id).unwrap(),
-                            card.section_position.unwrap(),
-                            card.card_position.unwrap(),
-                        ],
-                    )
-                    .await;
+                    let assign_result = tx
+                        .save_card_assignment(
+                            card.db_id.unwrap(),
+                            &[
+                                roadmap_id,
+                                *tab_ids.get(tab_id).unwrap(),
+                                card.section_position.unwrap(),
+                                card.card_position.unwrap(),
+                            ],
+                        )
+                        .await;
                     let Ok(_) = assign_result else {
                         error!(
                             "[Check Roadmap] Failed to save card assignment to db err: {}",
@@ -700,7 +630,7 @@ pub async fn check_roadmap(data: &AppData) {
                     let card = &roadmap.cards.get(tab_id).unwrap()[*card_index as usize];
 
                     let card_result = save_card_and_assignment(
-                        &tx,
+                        tx.as_ref(),
                         card,
                         &[
                             roadmap_id,
@@ -738,7 +668,7 @@ pub async fn check_roadmap(data: &AppData) {
 
                     let card = &roadmap.cards.get(tab_id).unwrap()[*current_card_index as usize];
                     let card_result = save_card_and_assignment(
-                        &tx,
+                        tx.as_ref(),
                         card,
                         &[
                             roadmap_id,
@@ -771,7 +701,7 @@ pub async fn check_roadmap(data: &AppData) {
                 RChange::TabCardsNotInPrevious { tab_index } => {
                     // Save all cards for this tab
                     if let Err(e) = save_all_tab_cards_sync_tx(
-                        &tx,
+                        tx.as_ref(),
                         &roadmap,
                         roadmap_id,
                         &tab_ids,
@@ -790,13 +720,13 @@ pub async fn check_roadmap(data: &AppData) {
 
             // Save change
             if previous_card_id.is_some() || current_card_id.is_some() {
-                let change_result = save_change_tx(
-                    &tx,
-                    change_type,
-                    roadmap_id,
-                    &[previous_card_id, current_card_id, Option::<u32>::None],
-                )
-                .await;
+                let change_result = tx
+                    .save_change(
+                        change_type,
+                        roadmap_id,
+                        &[previous_card_id, current_card_id, Option::<u32>::None],
+                    )
+                    .await;
                 let Ok(_) = change_result else {
                     error!(
                         "[Save Change] unable to save card change. err: {}",
@@ -815,7 +745,7 @@ pub async fn check_roadmap(data: &AppData) {
         };
 
         #[cfg(feature = "mail")]
-        if should_notify {
+        if should_notify && let Some(ref mail) = data.config.mail {
             // send email that there are changes
             let count = changes
                 .iter()
@@ -830,8 +760,15 @@ pub async fn check_roadmap(data: &AppData) {
                     )
                 })
                 .count();
-            let base_url = env::var("VITE_BASE_URL").unwrap_or("Missing base url".into());
+            let base_url = data
+                .config
+                .roadmap
+                .base_url
+                .as_deref()
+                .unwrap_or("Missing base url");
             let res = crate::queries::mail::send_email(
+                data.client.clone(),
+                mail,
                 &format!("{count} new changes on roadmap"),
                 &format!("{base_url}/roadmap/{roadmap_id}"),
                 &format!(r#"<a href="{base_url}/roadmap/{roadmap_id}">View changes</a>"#),
@@ -856,23 +793,24 @@ pub async fn check_roadmap(data: &AppData) {
             }
         }
     } else {
-        let db = data.app_db.connect();
-        let Ok(db) = db else {
-            error!("[Check Roadmap] DB failed to connect {}", db.unwrap_err());
-            return;
+        let db = match data.app.connect().await {
+            Ok(db) => db,
+            Err(err) => {
+                error!("[Check Roadmap] DB failed to connect {err}");
+                return;
+            }
         };
 
-        let tx = db.transaction().await;
-        let Ok(tx) = tx else {
-            error!(
-                "[Check Roadmap] Failed to create transaction {}",
-                tx.err().unwrap()
-            );
-            return;
+        let tx = match db.begin_check().await {
+            Ok(tx) => tx,
+            Err(err) => {
+                error!("[Check Roadmap] Failed to create transaction {err}");
+                return;
+            }
         };
 
         // save roadmap
-        let roadmap_result = new_roadmap_tx(&tx).await;
+        let roadmap_result = tx.new_roadmap().await;
         let Ok(roadmap_id) = roadmap_result else {
             error!(
                 "[Check Roadmap] Failed to save roadmap to db err: {}",
@@ -885,7 +823,7 @@ pub async fn check_roadmap(data: &AppData) {
         let mut tab_ids: HashMap<String, u32> = HashMap::new();
 
         for tab in roadmap.tabs.iter() {
-            let tab_result = save_tab_and_assignment(&tx, tab, roadmap_id).await;
+            let tab_result = save_tab_and_assignment(tx.as_ref(), tab, roadmap_id).await;
             let Ok(tab_id) = tab_result else {
                 error!(
                     "[Check Roadmap] Failed to save tab to db err: {}",
@@ -898,7 +836,7 @@ pub async fn check_roadmap(data: &AppData) {
             tab_ids.insert(tab.id.clone(), tab_id);
         }
 
-        if let Err(e) = save_all_cards_sync_tx(&tx, &roadmap, roadmap_id, &tab_ids).await {
+        if let Err(e) = save_all_cards_sync_tx(tx.as_ref(), &roadmap, roadmap_id, &tab_ids).await {
             error!("[Check Roadmap] Failed to save cards. err: {e}");
             rollback_tx(tx).await;
             return;
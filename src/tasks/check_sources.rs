@@ -1,16 +1,32 @@
-use std::env;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 
-use feed_rs::parser;
-use libsql::Connection;
+use feed_rs::{
+    model::{Entry, Feed},
+    parser,
+};
 use log::{error, info, warn};
+use reqwest::{StatusCode, header::LOCATION};
+use serde::Serialize;
 use time::OffsetDateTime;
-use tokio::task::JoinSet;
+use tokio::{
+    sync::{OwnedSemaphorePermit, Semaphore},
+    task::JoinSet,
+    time::Instant,
+};
+use url::Url;
+use utoipa::ToSchema;
 // use tokio::sync::mpsc;
 
 use crate::{
-    db::{ACTIVITIES_T, SOURCES_T},
-    queries::sources::get_sources,
-    types::{AppData, Source},
+    config::{Config, SourcesConfig},
+    error::is_unique_violation,
+    repo::Repo,
+    types::{AppData, Source, serialize_optional_timestamp, serialize_timestamp},
+    utils::strip_html,
 };
 
 // enum Message {
@@ -20,7 +36,87 @@ use crate::{
 
 const CHECK_BUFFER_IN_MINUTES: i64 = 5;
 
-enum SourceActivity {
+struct HostSlot {
+    permits: Arc<Semaphore>,
+    last_request: tokio::sync::Mutex<Option<Instant>>,
+}
+
+/// Limits how many feeds are fetched at once, overall and per host, and spaces
+/// out requests to the same host.
+#[derive(Clone)]
+struct FetchLimiter {
+    global: Arc<Semaphore>,
+    hosts: Arc<Mutex<HashMap<String, Arc<HostSlot>>>>,
+    per_host: usize,
+    host_delay: Duration,
+}
+
+struct FetchPermit {
+    _global: OwnedSemaphorePermit,
+    _host: OwnedSemaphorePermit,
+}
+
+impl FetchLimiter {
+    fn new(config: &SourcesConfig) -> Self {
+        FetchLimiter {
+            global: Arc::new(Semaphore::new(config.max_concurrent_checks.max(1))),
+            hosts: Arc::default(),
+            per_host: config.max_concurrent_per_host.max(1),
+            host_delay: Duration::from_millis(config.host_delay_ms),
+        }
+    }
+
+    /// Waits for a free slot for the url's host and its delay to pass, and
+    /// only then for a global one, so tasks waiting on a busy host don't hold
+    /// up other hosts
+    async fn acquire(&self, url: &str) -> FetchPermit {
+        let host = Url::parse(url)
+            .ok()
+            .and_then(|u| u.host_str().map(str::to_owned))
+            .unwrap_or_else(|| url.to_owned());
+
+        let slot = self
+            .hosts
+            .lock()
+            .unwrap()
+            .entry(host)
+            .or_insert_with(|| {
+                Arc::new(HostSlot {
+                    permits: Arc::new(Semaphore::new(self.per_host)),
+                    last_request: tokio::sync::Mutex::new(None),
+                })
+            })
+            .clone();
+
+        let host_permit = slot
+            .permits
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("Semaphore is never closed");
+
+        let mut last_request = slot.last_request.lock().await;
+        if let Some(last) = *last_request {
+            tokio::time::sleep_until(last + self.host_delay).await;
+        }
+        let global_permit = self
+            .global
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("Semaphore is never closed");
+        *last_request = Some(Instant::now());
+
+        FetchPermit {
+            _global: global_permit,
+            _host: host_permit,
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum SourceActivity {
     Disabled {
         source_url: String,
     },
@@ -31,92 +127,250 @@ enum SourceActivity {
         reason: String,
     },
     Unchanged {
+        source_id: u32,
         source_url: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        redirected_to: Option<String>,
     },
     Changed {
         source_id: u32,
         source_url: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        redirected_to: Option<String>,
         channel_title: String,
+        #[serde(serialize_with = "serialize_timestamp")]
+        #[schema(value_type = String)]
         most_recent: OffsetDateTime,
         posts: Vec<SourceEntry>,
     },
 }
 
-struct SourceEntry {
-    title: String,
-    url: String,
-    body: String,
+#[derive(Serialize, ToSchema)]
+pub struct SourceEntry {
+    pub title: String,
+    pub url: String,
+    pub body: String,
 }
 
-async fn check_source(source: Source, client: reqwest::Client) -> SourceActivity {
-    if !source.enabled {
-        info!("[Check Sources] Skipping disabled source {}", source.url);
-        return SourceActivity::Disabled {
-            source_url: source.url,
+#[derive(Serialize, ToSchema)]
+pub struct SourcePreview {
+    pub url: String,
+    pub permanent_redirect: Option<String>,
+    pub channel_title: Option<String>,
+    #[serde(serialize_with = "serialize_optional_timestamp")]
+    #[schema(value_type = Option<String>)]
+    pub updated: Option<OffsetDateTime>,
+    pub entries: Vec<PreviewEntry>,
+    pub warnings: Vec<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct PreviewEntry {
+    pub title: Option<String>,
+    pub url: String,
+    #[serde(serialize_with = "serialize_optional_timestamp")]
+    #[schema(value_type = Option<String>)]
+    pub published: Option<OffsetDateTime>,
+    pub snippet: Option<String>,
+    pub warnings: Vec<String>,
+}
+
+const PREVIEW_SNIPPET_LENGTH: usize = 280;
+
+struct FetchedFeed {
+    channel: Feed,
+    /// Where the feed now lives if every redirect followed was permanent
+    permanent_redirect: Option<String>,
+}
+
+/// Fetches and parses the feed at `url`, following redirects by hand. The error
+/// is a human readable reason that has already been logged.
+/// Every hop of a redirect goes through the limiter for its own host
+async fn fetch_feed(
+    url: &str,
+    client: &reqwest::Client,
+    max_redirects: usize,
+    limiter: &FetchLimiter,
+) -> Result<FetchedFeed, String> {
+    let mut current_url = match Url::parse(url) {
+        Ok(current_url) => current_url,
+        Err(err) => {
+            let err = format!("Url {url} is invalid with err {err}");
+            error!("[Check Sources] {err}");
+            return Err(err);
+        }
+    };
+    let mut redirects = 0;
+    let mut all_permanent = true;
+
+    let mut permit = None;
+
+    let res = loop {
+        // Let go of the last host before waiting on the next one
+        permit.take();
+        permit = Some(limiter.acquire(current_url.as_str()).await);
+
+        let res = client.get(current_url.clone()).send().await;
+        let Ok(res) = res else {
+            let err = format!(
+                "Network request for {} failed with err {}",
+                current_url,
+                res.expect_err("must be an error")
+            );
+            error!("[Check Sources] {err}");
+            return Err(err);
         };
-    }
 
-    let res = client.get(&source.url).send().await;
-    let Ok(res) = res else {
-        let err = format!(
-            "Network request for {} failed with err {}",
-            &source.url,
-            res.expect_err("must be an error")
-        );
-        error!("[Check Sources] {err}");
-        return SourceActivity::Failed {
-            source_id: source.id,
-            source_url: source.url,
-            new_failed_count: source.failed_count + 1,
-            reason: err,
+        if !res.status().is_redirection() {
+            break res;
+        }
+
+        if redirects >= max_redirects {
+            let err = format!("Too many redirects for {url}, last was {current_url}");
+            error!("[Check Sources] {err}");
+            return Err(err);
+        }
+
+        let next_url = res
+            .headers()
+            .get(LOCATION)
+            .and_then(|l| l.to_str().ok())
+            .and_then(|l| current_url.join(l).ok());
+        let Some(next_url) = next_url else {
+            let err = format!(
+                "Redirect for {} with status {} has no valid location",
+                current_url,
+                res.status()
+            );
+            error!("[Check Sources] {err}");
+            return Err(err);
         };
+
+        all_permanent &= matches!(
+            res.status(),
+            StatusCode::MOVED_PERMANENTLY | StatusCode::PERMANENT_REDIRECT
+        );
+        redirects += 1;
+        info!(
+            "[Check Sources] {current_url} redirected to {next_url} with status {}",
+            res.status()
+        );
+        current_url = next_url;
     };
 
     if !res.status().is_success() {
         let err = format!(
             "Network request for {} failed with status {}",
-            &source.url,
+            current_url,
             res.status()
         );
         error!("[Check Sources] {err}");
-        return SourceActivity::Failed {
-            source_id: source.id,
-            source_url: source.url,
-            new_failed_count: source.failed_count + 1,
-            reason: err,
-        };
+        return Err(err);
     }
 
     let content = res.bytes().await;
     let Ok(content) = content else {
         let err = format!(
             "Bytes failed for {} with err {}",
-            &source.url,
+            url,
             content.expect_err("must be an error")
         );
         error!("[Check Sources] {err}");
-        return SourceActivity::Failed {
-            source_id: source.id,
-            source_url: source.url,
-            new_failed_count: source.failed_count + 1,
-            reason: err,
-        };
+        return Err(err);
     };
 
     let channel = parser::parse(&(content)[..]);
     let Ok(channel) = channel else {
         let err = format!(
             "Parsing failed for {} with err {}",
-            &source.url,
+            url,
             channel.expect_err("must be an error")
         );
         error!("[Check Sources] {err}");
-        return SourceActivity::Failed {
-            source_id: source.id,
+        return Err(err);
+    };
+
+    let permanent_redirect = (redirects > 0 && all_permanent).then(|| current_url.to_string());
+
+    Ok(FetchedFeed {
+        channel,
+        permanent_redirect,
+    })
+}
+
+/// Picks the url to link an entry with, along with a warning if a fallback was used
+fn select_entry_url(entry: &Entry) -> (String, Option<String>) {
+    if let Some(x) = entry.links.iter().find(|link| {
+        if let Some(ref rel) = link.rel
+            && let Some(_) = (rel == "alternate" || rel == "self").then_some(())
+            && let Some(ref med_t) = link.media_type
+            && let Some(_) = (med_t == "text/html").then_some(())
+        {
+            true
+        } else {
+            false
+        }
+    }) {
+        (x.href.clone(), None)
+    } else if entry.links.len() == 1 {
+        (entry.links[0].href.clone(), None)
+    } else if !entry.links.is_empty() {
+        let content_url = entry.links[0].href.clone();
+        let warning = format!("Using first url for entry {content_url}");
+        (content_url, Some(warning))
+    } else if let Some(ref content) = entry.content
+        && let Some(ref url) = content.src
+    {
+        let content_url = url.href.clone();
+        let warning = format!("Using content url for entry {content_url}");
+        (content_url, Some(warning))
+    } else {
+        ("No Url".into(), Some("Entry has no url".into()))
+    }
+}
+
+/// Picks the summary, falling back to the content body, skipping empty ones
+fn select_entry_body(entry: &Entry) -> Option<String> {
+    if let Some(ref summary) = entry.summary
+        && let Some(_) = (!summary.content.trim().is_empty()).then_some(())
+    {
+        Some(summary.content.clone())
+    } else if let Some(ref content) = entry.content
+        && let Some(ref body) = content.body
+        && let Some(_) = (!body.trim().is_empty()).then_some(())
+    {
+        Some(body.clone())
+    } else {
+        None
+    }
+}
+
+async fn check_source(
+    source: Source,
+    client: reqwest::Client,
+    max_redirects: usize,
+    limiter: &FetchLimiter,
+) -> SourceActivity {
+    if !source.enabled {
+        info!("[Check Sources] Skipping disabled source {}", source.url);
+        return SourceActivity::Disabled {
             source_url: source.url,
-            new_failed_count: source.failed_count + 1,
-            reason: err,
         };
+    }
+
+    let FetchedFeed {
+        channel,
+        permanent_redirect,
+    } = match fetch_feed(&source.url, &client, max_redirects, limiter).await {
+        Ok(fetched) => fetched,
+        Err(err) => {
+            return SourceActivity::Failed {
+                source_id: source.id,
+                source_url: source.url,
+                new_failed_count: source.failed_count + 1,
+                reason: err,
+            };
+        }
     };
 
     if let Some(a) = channel.updated
@@ -128,7 +382,9 @@ async fn check_source(source: Source, client: reqwest::Client) -> SourceActivity
                 &source.url, source.last_checked, upd_time
             );
             return SourceActivity::Unchanged {
+                source_id: source.id,
                 source_url: source.url,
+                redirected_to: permanent_redirect,
             };
         }
     // The only way return from second condition fails is a logic bug in one of the dependencies so don't bother
@@ -163,31 +419,9 @@ async fn check_source(source: Source, client: reqwest::Client) -> SourceActivity
     let mut entries = Vec::new();
 
     for entry in channel.entries {
-        let content_url: String;
-        if let Some(x) = entry.links.iter().find(|link| {
-            if let Some(ref rel) = link.rel
-                && let Some(_) = (rel == "alternate" || rel == "self").then_some(())
-                && let Some(ref med_t) = link.media_type
-                && let Some(_) = (med_t == "text/html").then_some(())
-            {
-                true
-            } else {
-                false
-            }
-        }) {
-            content_url = x.href.clone();
-        } else if entry.links.len() == 1 {
-            content_url = entry.links[0].href.clone();
-        } else if !entry.links.is_empty() {
-            content_url = entry.links[0].href.clone();
-            warn!("[Check Sources] Using first url for entry {content_url}");
-        } else if let Some(ref content) = entry.content
-            && let Some(ref url) = content.src
-        {
-            content_url = url.href.clone();
-            warn!("[Check Sources] Using content url for entry {content_url}");
-        } else {
-            content_url = "No Url".into();
+        let (content_url, url_warning) = select_entry_url(&entry);
+        if let Some(url_warning) = url_warning {
+            warn!("[Check Sources] {url_warning}");
         }
 
         let pub_time = if let Some(ref pub_) = entry.published
@@ -214,18 +448,7 @@ async fn check_source(source: Source, client: reqwest::Client) -> SourceActivity
             .as_ref()
             .map_or_else(|| "Missing Content Title".into(), |t| t.content.clone());
 
-        let content_body = if let Some(ref summary) = entry.summary
-            && let Some(_) = (!summary.content.trim().is_empty()).then_some(())
-        {
-            summary.content.clone()
-        } else if let Some(ref content) = entry.content
-            && let Some(ref body) = content.body
-            && let Some(_) = (!body.trim().is_empty()).then_some(())
-        {
-            body.clone()
-        } else {
-            "No body".into()
-        };
+        let content_body = select_entry_body(&entry).unwrap_or_else(|| "No body".into());
 
         entries.push(SourceEntry {
             title: content_title,
@@ -236,12 +459,15 @@ async fn check_source(source: Source, client: reqwest::Client) -> SourceActivity
 
     if entries.is_empty() {
         SourceActivity::Unchanged {
+            source_id: source.id,
             source_url: source.url,
+            redirected_to: permanent_redirect,
         }
     } else {
         SourceActivity::Changed {
             source_id: source.id,
             source_url: source.url,
+            redirected_to: permanent_redirect,
             channel_title: channel
                 .title
                 .map_or_else(|| "Missing Channel Title".into(), |t| t.content),
@@ -251,7 +477,83 @@ async fn check_source(source: Source, client: reqwest::Client) -> SourceActivity
     }
 }
 
-async fn handle_activity(activity: SourceActivity, client: reqwest::Client, conn: Connection) {
+async fn save_source_event(db: &dyn Repo, source_id: u32, event_type: &str, message: &str) {
+    let res = db.add_source_event(source_id, event_type, message).await;
+
+    if let Err(err) = res {
+        error!(
+            "[Check Sources]:[Handle Redirect] failed to save {event_type} event for source {source_id} for reason {err}"
+        );
+    }
+}
+
+/// Moves a source to where it permanently redirects. Redirects to another host
+/// are only suggested, as a domain being taken over looks the same. So are
+/// redirects to a url the user already has as another source.
+async fn handle_redirect(source_id: u32, source_url: &str, new_url: &str, db: &dyn Repo) {
+    let same_host = match (Url::parse(source_url), Url::parse(new_url)) {
+        (Ok(old), Ok(new)) => old.host_str() == new.host_str(),
+        _ => false,
+    };
+
+    let reason = if same_host {
+        match db.move_source(source_id, new_url).await {
+            Ok(_) => {
+                info!(
+                    "[Check Sources]:[Handle Redirect] Updated source url {source_url} to {new_url}"
+                );
+                save_source_event(
+                    db,
+                    source_id,
+                    "url_updated",
+                    &format!("Permanently redirected from {source_url} to {new_url}"),
+                )
+                .await;
+                return;
+            }
+            Err(err) if is_unique_violation(err.as_ref()) => "which is already a source",
+            Err(err) => {
+                error!(
+                    "[Check Sources]:[Handle Redirect] failed to update source url {source_url} to {new_url} for reason {err}"
+                );
+                return;
+            }
+        }
+    } else {
+        "on another host"
+    };
+
+    let res = db.suggest_redirect(source_id, new_url).await;
+
+    match res {
+        Ok(0) => {}
+        Ok(_) => {
+            warn!(
+                "[Check Sources]:[Handle Redirect] Source {source_url} moved to {new_url} {reason}"
+            );
+            save_source_event(
+                db,
+                source_id,
+                "redirect_suggested",
+                &format!("Permanently redirected from {source_url} to {new_url} {reason}"),
+            )
+            .await;
+        }
+        Err(err) => {
+            error!(
+                "[Check Sources]:[Handle Redirect] failed to suggest {new_url} for source {source_url} for reason {err}"
+            );
+        }
+    }
+}
+
+#[cfg_attr(not(feature = "mail"), allow(unused_variables))]
+async fn handle_activity(
+    activity: &SourceActivity,
+    client: reqwest::Client,
+    db: Arc<dyn Repo>,
+    config: &Config,
+) {
     match activity {
         SourceActivity::Disabled { source_url } => {
             info!("[Check Sources]:[Handle Activity] Source at {source_url} remains disabled");
@@ -262,44 +564,34 @@ async fn handle_activity(activity: SourceActivity, client: reqwest::Client, conn
             new_failed_count,
             reason,
         } => {
-            let new_enabled: u32 = if new_failed_count
-                >= env::var("SOURCE_DISABLE_AFTER").map_or(10, |v| v.parse().unwrap_or(10))
-            {
-                0
-            } else {
-                1
-            };
+            let new_enabled = *new_failed_count < config.sources.disable_after;
 
-            let res = conn
-                .execute(
-                    &format!(
-                        "UPDATE {SOURCES_T} SET failed_count = ?1, enabled = ?2 WHERE id = ?3"
-                    ),
-                    (new_failed_count, new_enabled, source_id),
-                )
+            let res = db
+                .set_failed_count(*source_id, *new_failed_count, new_enabled)
                 .await;
 
             if let Err(err) = res {
                 error!(
                     "[Check Sources]:[Handle Activity] failed to update failed source at {source_url} for reason: {err}"
                 );
-            } else if new_enabled == 0 {
+            } else if !new_enabled {
                 error!(
                     "[Check Sources]:[Handle Activity] Disabling source at {source_url} for reason: {reason}"
                 );
 
                 #[cfg(feature = "mail")]
-                let res = crate::queries::mail::send_email_with_cient(
+                if let Some(ref mail) = config.mail
+                    && let Err(err) = crate::queries::mail::send_email(
                     client,
+                    mail,
                     "Source disabled",
                     &format!("The source at {source_url} has been disabled after failing too much. The error is {reason}"),
                     &format!(r#"
                         <p>Url: {source_url}</p>
                         <p>Link: <a href="{source_url}">Link</a></p>
                         <p>Reason:</p><pre>{reason}</pre>
-                    "#)).await;
-                #[cfg(feature = "mail")]
-                if let Err(err) = res {
+                    "#)).await
+                {
                     error!(
                         "[Check Sources]:[Handle Activity] failed to send disabled email for source at {source_url} for reason: {err}"
                     );
@@ -310,12 +602,21 @@ async fn handle_activity(activity: SourceActivity, client: reqwest::Client, conn
                 );
             }
         }
-        SourceActivity::Unchanged { source_url } => {
+        SourceActivity::Unchanged {
+            source_id,
+            source_url,
+            redirected_to,
+        } => {
             info!("[Check Sources]:[Handle Activity] Source at {source_url} has no new posts");
+
+            if let Some(redirected_to) = redirected_to {
+                handle_redirect(*source_id, source_url, redirected_to, db.as_ref()).await;
+            }
         }
         SourceActivity::Changed {
             source_id,
             source_url,
+            redirected_to,
             channel_title,
             most_recent,
             posts,
@@ -325,14 +626,11 @@ async fn handle_activity(activity: SourceActivity, client: reqwest::Client, conn
                 posts.len()
             );
 
-            let res = conn
-                .execute(
-                    &format!(
-                        "UPDATE {SOURCES_T} SET last_checked = ?1, failed_count = ?2 WHERE id = ?3"
-                    ),
-                    (serde_json::to_string(&most_recent).unwrap(), 0, source_id),
-                )
-                .await;
+            if let Some(redirected_to) = redirected_to {
+                handle_redirect(*source_id, source_url, redirected_to, db.as_ref()).await;
+            }
+
+            let res = db.set_last_checked(*source_id, *most_recent).await;
 
             if let Err(err) = res {
                 error!(
@@ -340,22 +638,9 @@ async fn handle_activity(activity: SourceActivity, client: reqwest::Client, conn
                 );
             }
 
-            for post in posts.into_iter().rev() {
-                let res = conn
-                    .execute(
-                        &format!(
-                            "INSERT INTO {ACTIVITIES_T} 
-                                        (source_id, post_url, timestamp) 
-                                    VALUES 
-                                        (?1, ?2, ?3)
-                                    "
-                        ),
-                        (
-                            source_id,
-                            post.url.clone(),
-                            serde_json::to_string(&OffsetDateTime::now_utc()).unwrap(),
-                        ),
-                    )
+            for post in posts.iter().rev() {
+                let res = db
+                    .add_activity(*source_id, &post.url, &post.title, &strip_html(&post.body))
                     .await;
 
                 if let Err(err) = res {
@@ -366,21 +651,22 @@ async fn handle_activity(activity: SourceActivity, client: reqwest::Client, conn
                 }
 
                 #[cfg(feature = "mail")]
-                let res = crate::queries::mail::send_email(
-                    &format!("{} - {}", post.title, channel_title),
-                    &format!("Source: {}\n\n{}", post.url, post.body),
-                    &format!(
-                        r#"
+                if let Some(ref mail) = config.mail
+                    && let Err(err) = crate::queries::mail::send_email(
+                        client.clone(),
+                        mail,
+                        &format!("{} - {}", post.title, channel_title),
+                        &format!("Source: {}\n\n{}", post.url, post.body),
+                        &format!(
+                            r#"
                             <p>Source: <a href="{}">Link</a></p>
                             <p>{}</p>
                         "#,
-                        post.url, post.body
-                    ),
-                )
-                .await;
-
-                #[cfg(feature = "mail")]
-                if let Err(err) = res {
+                            post.url, post.body
+                        ),
+                    )
+                    .await
+                {
                     error!(
                         "[Check Sources]:[Handle Activity] failed to send email for activity at url {} for reason {}",
                         post.url, err
@@ -391,6 +677,117 @@ async fn handle_activity(activity: SourceActivity, client: reqwest::Client, conn
     }
 }
 
+/// Fetches and parses a feed like [`check_source`] without saving anything,
+/// so it can be vetted before being added.
+pub async fn preview_source(data: &AppData, url: &str) -> Result<SourcePreview, String> {
+    let FetchedFeed {
+        channel,
+        permanent_redirect,
+    } = fetch_feed(
+        url,
+        &data.feed_client,
+        data.config.http.max_redirects,
+        &FetchLimiter::new(&data.config.sources),
+    )
+    .await?;
+
+    let mut warnings = Vec::new();
+
+    if let Some(ref redirect) = permanent_redirect {
+        warnings.push(format!("Feed permanently redirects to {redirect}"));
+    }
+
+    let updated = channel
+        .updated
+        .and_then(|a| OffsetDateTime::from_unix_timestamp(a.timestamp()).ok());
+    if updated.is_none() {
+        warnings.push("Feed has no updated date, every check will read all entries".into());
+    }
+
+    let channel_title = channel.title.map(|t| t.content);
+    if channel_title.is_none() {
+        warnings.push("Feed has no title".into());
+    }
+
+    if channel.entries.is_empty() {
+        warnings.push("Feed has no entries".into());
+    }
+
+    let entries = channel
+        .entries
+        .iter()
+        .map(|entry| {
+            let mut entry_warnings = Vec::new();
+
+            let (url, url_warning) = select_entry_url(entry);
+            entry_warnings.extend(url_warning);
+
+            let published = entry
+                .published
+                .and_then(|p| OffsetDateTime::from_unix_timestamp(p.timestamp()).ok());
+            if published.is_none() {
+                entry_warnings.push(
+                    "Entry has no published date, it and later entries will be skipped".into(),
+                );
+            }
+
+            let title = entry.title.as_ref().map(|t| t.content.clone());
+            if title.is_none() {
+                entry_warnings.push("Entry has no title".into());
+            }
+
+            let snippet = select_entry_body(entry).map(|body| {
+                let body = body.trim();
+                match body.char_indices().nth(PREVIEW_SNIPPET_LENGTH) {
+                    Some((index, _)) => format!("{}...", &body[..index]),
+                    None => body.to_owned(),
+                }
+            });
+            if snippet.is_none() {
+                entry_warnings.push("Entry has no body".into());
+            }
+
+            PreviewEntry {
+                title,
+                url,
+                published,
+                snippet,
+                warnings: entry_warnings,
+            }
+        })
+        .collect();
+
+    Ok(SourcePreview {
+        url: url.to_owned(),
+        permanent_redirect,
+        channel_title,
+        updated,
+        entries,
+        warnings,
+    })
+}
+
+/// Checks a single source and handles the result the same way [`check_sources`] does,
+/// returning the activity so callers can report on it.
+pub async fn check_single_source(
+    data: &AppData,
+    db: Arc<dyn Repo>,
+    source: Source,
+) -> SourceActivity {
+    info!("[Check Sources] Checking single source {}", source.url);
+
+    let activity = check_source(
+        source,
+        data.feed_client.clone(),
+        data.config.http.max_redirects,
+        &FetchLimiter::new(&data.config.sources),
+    )
+    .await;
+    handle_activity(&activity, data.client.clone(), db, &data.config).await;
+
+    activity
+}
+
 pub async fn check_sources(data: &AppData) {
     let start_time = OffsetDateTime::now_utc();
     info!("[Check Sources] Starting check {start_time}");
@@ -399,25 +796,38 @@ pub async fn check_sources(data: &AppData) {
         warn!("[Check Sources] will not send emails as feature is not enabled");
     }
 
-    let sources_res = get_sources(data.app_db.connect().unwrap()).await;
-    let Ok(sources) = sources_res else {
-        panic!(
-            "Couldn't check sources. Err: {}",
-            sources_res.err().unwrap()
-        );
+    let db = match data.app.connect().await {
+        Ok(db) => db,
+        Err(err) => {
+            error!("[Check Sources] DB failed to connect {err}");
+            return;
+        }
+    };
+    let sources = match db.get_all_sources().await {
+        Ok(sources) => sources,
+        Err(err) => {
+            error!("[Check Sources] Couldn't get sources. Err: {err}");
+            return;
+        }
     };
 
     let mut threads = JoinSet::new();
-    let client = reqwest::Client::new();
-    let conn = data.app_db.connect().unwrap();
+    let limiter = FetchLimiter::new(&data.config.sources);
 
     for source in sources {
-        let s_client = client.clone();
-        let s_conn = conn.clone();
+        let s_data = data.clone();
+        let s_db = db.clone();
+        let s_limiter = limiter.clone();
 
         threads.spawn(async move {
-            let activity = check_source(source, s_client.clone()).await;
-            handle_activity(activity, s_client, s_conn).await
+            let activity = check_source(
+                source,
+                s_data.feed_client.clone(),
+                s_data.config.http.max_redirects,
+                &s_limiter,
+            )
+            .await;
+            handle_activity(&activity, s_data.client.clone(), s_db, &s_data.config).await
         });
     }
 
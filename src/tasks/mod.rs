@@ -1,2 +1,5 @@
 pub mod check_roadmap;
 pub mod check_sources;
+pub mod prune_data;
+pub mod prune_sessions;
+pub mod sync_database;
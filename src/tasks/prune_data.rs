@@ -0,0 +1,89 @@
+use std::fmt::Display;
+
+use log::{error, info};
+use time::{Duration, OffsetDateTime};
+
+use crate::{config::RetentionConfig, repo::Repo, types::AppData};
+
+/// How many rows a prune deleted
+#[derive(Debug, Default)]
+pub struct PruneReport {
+    pub activities: u64,
+    pub roadmap_activities: u64,
+    pub roadmap_cards: u64,
+    pub roadmap_tabs: u64,
+    pub login_attempts: u64,
+}
+
+impl Display for PruneReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} activities, {} roadmap checks, {} roadmap cards, {} roadmap tabs and {} login attempts",
+            self.activities,
+            self.roadmap_activities,
+            self.roadmap_cards,
+            self.roadmap_tabs,
+            self.login_attempts
+        )
+    }
+}
+
+fn days_ago(days: u32) -> OffsetDateTime {
+    OffsetDateTime::now_utc() - Duration::days(days.into())
+}
+
+/// Deletes whatever the retention config says is too old. Every step can be
+/// run again on its own, so a prune that fails part way is finished by the next
+pub async fn prune(db: &dyn Repo, config: &RetentionConfig) -> anyhow::Result<PruneReport> {
+    let mut report = PruneReport::default();
+
+    if config.activity_days > 0 {
+        report.activities += db
+            .delete_activities_before(days_ago(config.activity_days))
+            .await?;
+    }
+    if config.activities_per_source > 0 {
+        report.activities += db
+            .delete_activities_over_limit(config.activities_per_source)
+            .await?;
+    }
+
+    if config.roadmap_days > 0 {
+        report.roadmap_activities += db
+            .delete_roadmap_activities_before(days_ago(config.roadmap_days), false)
+            .await?;
+    }
+    if config.unchanged_roadmap_days > 0 {
+        report.roadmap_activities += db
+            .delete_roadmap_activities_before(days_ago(config.unchanged_roadmap_days), true)
+            .await?;
+    }
+    if report.roadmap_activities > 0 {
+        report.roadmap_cards = db.delete_unused_cards().await?;
+        report.roadmap_tabs = db.delete_unused_tabs().await?;
+    }
+
+    if config.login_attempt_days > 0 {
+        report.login_attempts = db
+            .delete_login_attempts_before(days_ago(config.login_attempt_days))
+            .await?;
+    }
+
+    Ok(report)
+}
+
+pub async fn prune_data(data: &AppData) {
+    let db = match data.app.connect().await {
+        Ok(db) => db,
+        Err(err) => {
+            error!("[Prune Data] DB failed to connect {err}");
+            return;
+        }
+    };
+
+    match prune(db.as_ref(), &data.config.retention).await {
+        Ok(report) => info!("[Prune Data] Deleted {report}"),
+        Err(err) => error!("[Prune Data] Pruning failed with err: {err}"),
+    }
+}
@@ -0,0 +1,15 @@
+use log::{error, info};
+
+use crate::types::AppData;
+
+pub async fn prune_sessions(data: &AppData) {
+    let db = data.app.connect().await.unwrap();
+
+    match db
+        .prune_sessions(data.config.auth.session_lifetime_mins)
+        .await
+    {
+        Ok(pruned) => info!("[Prune Sessions] Pruned {pruned} expired sessions"),
+        Err(err) => error!("[Prune Sessions] Pruning failed with err: {err}"),
+    }
+}
@@ -0,0 +1,36 @@
+use log::{error, info};
+use time::OffsetDateTime;
+
+use crate::{
+    db,
+    types::{AppData, DatabaseMode},
+};
+
+/// Pulls changes from the remote database into the embedded replica. Does
+/// nothing unless the app database is a replica.
+pub async fn sync_database(data: &AppData) {
+    if db::database_mode(&data.config.database) != DatabaseMode::Replica {
+        return;
+    }
+    let Some(database) = data.app.libsql() else {
+        return;
+    };
+
+    let result = database.sync().await;
+    let now = OffsetDateTime::now_utc();
+
+    let mut status = data.db_sync.lock().unwrap();
+    status.last_attempt = Some(now);
+    match result {
+        Ok(synced) => {
+            info!("[Sync Database] Synced {} frames", synced.frames_synced());
+            status.last_synced = Some(now);
+            status.last_error = None;
+            status.frame_no = synced.frame_no();
+        }
+        Err(err) => {
+            error!("[Sync Database] Syncing failed with err: {err}");
+            status.last_error = Some(err.to_string());
+        }
+    }
+}
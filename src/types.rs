@@ -1,31 +1,158 @@
-use std::{collections::HashMap, fmt::Display, hash::Hash};
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    hash::Hash,
+    sync::{Arc, Mutex},
+};
 
 use actix_web::web;
-use libsql::Database;
 use log::info;
 use serde::{Deserialize, Serialize, de, ser};
 use serde_with::with_prefix;
 use time::{OffsetDateTime, format_description};
+use utoipa::ToSchema;
 
-use crate::utils::clean_description;
+use crate::{
+    auth::LoginLimiter,
+    config::Config,
+    error::{GregError, LogErr},
+    repo::{Backend, Repo},
+    utils::clean_description,
+};
 
 pub const LOGGED_IN_COOKIE: &str = "logged_in";
+/// The demo database has no users, its data belongs to the first one
+pub const DEMO_USER_ID: u32 = 1;
 
 // DB Types
 #[derive(Serialize, Deserialize)]
+pub struct User {
+    pub id: u32,
+    pub username: String,
+    #[serde(skip_serializing)]
+    pub password_hash: String,
+    pub is_admin: bool,
+    #[serde(
+        deserialize_with = "deserialize_timestamp",
+        serialize_with = "serialize_timestamp"
+    )]
+    pub timestamp: OffsetDateTime,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct Session {
+    pub id: u32,
+    #[serde(skip_serializing)]
+    pub key: String,
+    #[serde(
+        rename = "created",
+        alias = "timestamp",
+        deserialize_with = "deserialize_timestamp",
+        serialize_with = "serialize_timestamp"
+    )]
+    #[schema(value_type = String)]
+    pub timestamp: OffsetDateTime,
+    #[serde(
+        deserialize_with = "deserialize_timestamp",
+        serialize_with = "serialize_timestamp"
+    )]
+    #[schema(value_type = String)]
+    pub last_seen: OffsetDateTime,
+    pub user_agent: Option<String>,
+    /// Whether this is the session making the request
+    #[serde(default)]
+    pub current: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case", try_from = "String")]
+pub enum TokenScope {
+    /// Only GET requests
+    Read,
+    /// Everything the user can do, except admin actions
+    Write,
+    /// Everything, including admin actions if the user is an admin
+    Admin,
+}
+
+impl TokenScope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TokenScope::Read => "read",
+            TokenScope::Write => "write",
+            TokenScope::Admin => "admin",
+        }
+    }
+}
+
+impl TryFrom<String> for TokenScope {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match value.as_str() {
+            "read" => Ok(TokenScope::Read),
+            "write" => Ok(TokenScope::Write),
+            "admin" => Ok(TokenScope::Admin),
+            _ => Err(format!("Unknown token scope {value}")),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct ApiToken {
+    pub id: u32,
+    pub name: String,
+    #[serde(skip_serializing)]
+    pub token_hash: String,
+    pub scope: TokenScope,
+    #[serde(
+        rename = "created",
+        alias = "timestamp",
+        deserialize_with = "deserialize_timestamp",
+        serialize_with = "serialize_timestamp"
+    )]
+    #[schema(value_type = String)]
+    pub timestamp: OffsetDateTime,
+    #[serde(
+        deserialize_with = "deserialize_optional_timestamp",
+        serialize_with = "serialize_optional_timestamp"
+    )]
+    #[schema(value_type = Option<String>)]
+    pub last_used: Option<OffsetDateTime>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct Source {
     pub id: u32,
+    pub user_id: u32,
     pub url: String,
     #[serde(
         deserialize_with = "deserialize_timestamp",
         serialize_with = "serialize_timestamp"
     )]
+    #[schema(value_type = String)]
     pub last_checked: OffsetDateTime,
     pub enabled: bool,
     pub failed_count: u32,
+    /// Permanent redirect to another host, waiting to be accepted
+    pub redirect_url: Option<String>,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct SourceEvent {
+    pub id: u32,
+    pub source_id: u32,
+    pub r#type: String,
+    pub message: String,
+    #[serde(
+        deserialize_with = "deserialize_timestamp",
+        serialize_with = "serialize_timestamp"
+    )]
+    #[schema(value_type = String)]
+    pub timestamp: OffsetDateTime,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct Activity {
     pub id: u32,
     pub source_url: String,
@@ -34,10 +161,11 @@ pub struct Activity {
         deserialize_with = "deserialize_timestamp",
         serialize_with = "serialize_timestamp"
     )]
+    #[schema(value_type = String)]
     pub timestamp: OffsetDateTime,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct RoadmapActivity {
     pub id: u32,
     pub change_count: Option<u32>,
@@ -45,10 +173,11 @@ pub struct RoadmapActivity {
         deserialize_with = "deserialize_timestamp",
         serialize_with = "serialize_timestamp"
     )]
+    #[schema(value_type = String)]
     pub timestamp: OffsetDateTime,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
 pub struct RoadmapWatchedTab {
     pub id: u32,
     #[serde(alias = "tab_roadmap_id")]
@@ -57,21 +186,48 @@ pub struct RoadmapWatchedTab {
         deserialize_with = "deserialize_timestamp",
         serialize_with = "serialize_timestamp"
     )]
+    #[schema(value_type = String)]
     pub timestamp: OffsetDateTime,
 }
 
 // JSON Types
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 pub struct AddSource {
     pub url: String,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
+pub struct UpdateSource {
+    /// Enabling also resets the failure count
+    pub enabled: Option<bool>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct AddWatchedTab {
+    pub tab_id: String,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct AddApiToken {
+    pub name: String,
+    pub scope: TokenScope,
+}
+
+/// Only time the token itself is shown
+#[derive(Serialize, ToSchema)]
+pub struct CreatedApiToken {
+    pub id: u32,
+    pub token: String,
+}
+
+#[derive(Deserialize, ToSchema)]
 pub struct LoginInfo {
+    /// Defaults to `auth.admin_username` so password-only logins keep working
+    pub username: Option<String>,
     pub password: String,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, Eq)]
+#[derive(Debug, Serialize, Deserialize, Clone, Eq, ToSchema)]
 pub struct RTab {
     pub id: String,
     pub name: String,
@@ -113,7 +269,7 @@ pub struct RCardAssignmentInfo {
     pub tab_id: u32,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
 pub struct RCard {
     pub id: String,
     pub name: String,
@@ -211,6 +367,25 @@ pub enum RChange {
     },
 }
 
+/// What changed on the roadmap between two checks
+#[derive(Serialize, ToSchema)]
+pub struct RComparison {
+    pub from: u32,
+    pub to: u32,
+    pub changes: Vec<RComparedChange>,
+}
+
+/// A change between two checks with the cards and tab it's about. Cards are
+/// as they were in `from` when previous and in `to` when current.
+#[derive(Serialize, ToSchema)]
+pub struct RComparedChange {
+    /// `tab_added`, `tab_removed`, `card_added`, `card_removed` or `card_modified`
+    pub r#type: &'static str,
+    pub previous_card: Option<RCard>,
+    pub current_card: Option<RCard>,
+    pub tab: Option<RTab>,
+}
+
 with_prefix!(prefix_previous_card "previous_card_");
 with_prefix!(prefix_current_card "current_card_");
 with_prefix!(prefix_tab "tab_");
@@ -228,10 +403,11 @@ pub struct RDBChange {
 }
 
 /// libsql crate's deserializer does not support flattened fields turns out
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[schema(as = RoadmapChange)]
 pub struct RDBChangeAlt {
-    id: u32,
-    r#type: String,
+    pub(crate) id: u32,
+    pub(crate) r#type: String,
 
     pub previous_card_id: Option<String>,
     pub previous_card_name: Option<String>,
@@ -255,6 +431,63 @@ pub struct RDBChangeAlt {
     pub card_tab_name: Option<String>,
 }
 
+/// Every version a roadmap card has had, oldest first
+#[derive(Serialize, ToSchema)]
+pub struct RCardHistory {
+    /// Roadmap id of the card
+    pub card_id: String,
+    pub revisions: Vec<RCardRevision>,
+    pub changes: Vec<RCardChange>,
+}
+
+/// One version of a card and the roadmap checks it was seen in
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct RCardRevision {
+    pub db_id: u32,
+    pub name: String,
+    pub description: String,
+    pub image_url: Option<String>,
+    pub slug: String,
+    pub first_activity_id: u32,
+    #[serde(
+        deserialize_with = "deserialize_timestamp",
+        serialize_with = "serialize_timestamp"
+    )]
+    #[schema(value_type = String)]
+    pub first_seen: OffsetDateTime,
+    pub last_activity_id: u32,
+    #[serde(
+        deserialize_with = "deserialize_timestamp",
+        serialize_with = "serialize_timestamp"
+    )]
+    #[schema(value_type = String)]
+    pub last_seen: OffsetDateTime,
+    /// Tabs and sections this version was in
+    #[serde(default)]
+    pub placements: Vec<RCardPlacement>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct RCardPlacement {
+    /// Roadmap id of the tab
+    pub tab_id: String,
+    pub tab_name: String,
+    pub section_position: u32,
+    pub first_activity_id: u32,
+    pub last_activity_id: u32,
+}
+
+/// A change that touched a card and the roadmap check that found it
+#[derive(Serialize, ToSchema)]
+pub struct RCardChange {
+    pub activity_id: u32,
+    #[serde(serialize_with = "serialize_timestamp")]
+    #[schema(value_type = String)]
+    pub timestamp: OffsetDateTime,
+    #[serde(flatten)]
+    pub change: RDBChangeAlt,
+}
+
 impl RDBChangeAlt {
     pub fn into(self) -> RDBChange {
         let previous_card = if let Some(id) = self.previous_card_id {
@@ -320,24 +553,163 @@ impl RDBChangeAlt {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SearchKind {
+    Activity,
+    Card,
+}
+
+/// Where a matched term is in the snippet, in characters
+#[derive(Serialize, ToSchema)]
+pub struct Highlight {
+    pub start: u32,
+    pub end: u32,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct SearchHit {
+    pub kind: SearchKind,
+    pub id: u32,
+    /// Title of the post or name of the card. Posts saved before search
+    /// existed use their url
+    pub title: String,
+    /// Link to the post, only for activities
+    pub url: Option<String>,
+    /// Only for activities
+    pub source_id: Option<u32>,
+    /// Roadmap id of the card, only for cards
+    pub card_id: Option<String>,
+    pub snippet: String,
+    pub highlights: Vec<Highlight>,
+    /// bm25 score on libsql and negated ts_rank on PostgreSQL, lower is a
+    /// better match
+    pub rank: f64,
+    #[serde(serialize_with = "serialize_timestamp")]
+    #[schema(value_type = String)]
+    pub timestamp: OffsetDateTime,
+}
+
 // Server Types
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 pub struct Success {
     pub message: String,
 }
 
-#[derive(Serialize)]
-pub struct Failure {
-    pub message: String,
+/// Where the app database lives
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum DatabaseMode {
+    /// A file on disk
+    Local,
+    /// The remote libsql server, every query goes over the network
+    Remote,
+    /// A file on disk synced with the remote libsql server
+    Replica,
+    /// A PostgreSQL server
+    Postgres,
+}
+
+/// The last syncs of an embedded replica
+#[derive(Debug, Default, Clone, Serialize, ToSchema)]
+pub struct SyncStatus {
+    #[serde(serialize_with = "serialize_optional_timestamp")]
+    #[schema(value_type = Option<String>)]
+    pub last_attempt: Option<OffsetDateTime>,
+    /// When a sync last succeeded
+    #[serde(serialize_with = "serialize_optional_timestamp")]
+    #[schema(value_type = Option<String>)]
+    pub last_synced: Option<OffsetDateTime>,
+    /// Why the last sync failed, null if it succeeded. Only sent to logged in
+    /// users
+    pub last_error: Option<String>,
+    /// Replication index of the replica after the last successful sync. Only
+    /// sent to logged in users
+    pub frame_no: Option<u64>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct DatabaseHealth {
+    pub mode: DatabaseMode,
+    /// Only set in `replica` mode
+    pub sync: Option<SyncStatus>,
 }
 
 pub struct AppState {
-    pub app_db: Database,
-    pub demo_db: Database,
+    pub app: Box<dyn Backend>,
+    /// Always libsql
+    pub demo: Box<dyn Backend>,
+    pub client: reqwest::Client,
+    pub feed_client: reqwest::Client,
+    pub login_limiter: LoginLimiter,
+    /// Updated by the sync task in replica mode
+    pub db_sync: Mutex<SyncStatus>,
+    pub config: Config,
+}
+
+impl AppState {
+    /// Connects to the demo or app database
+    pub async fn connect(&self, demo: bool) -> Result<Arc<dyn Repo>, GregError> {
+        let backend = if demo { &self.demo } else { &self.app };
+        backend
+            .connect()
+            .await
+            .log_err("[Connect] Connecting to db failed")
+    }
 }
 
 pub type AppData = web::Data<AppState>;
 
+/// One page of a list, newest first
+#[derive(Serialize, ToSchema)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// Pass as `cursor` to get the next page, null on the last one
+    #[schema(value_type = Option<String>, example = "1760832000000000000_42")]
+    pub next_cursor: Option<Cursor>,
+    /// How many items there are across every page
+    pub total: u64,
+}
+
+/// Where a page ends, as the sort key of the timestamp and the id of the
+/// last item. Sent to clients as `{key}_{id}`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(into = "String", try_from = "String")]
+pub struct Cursor {
+    pub key: i64,
+    pub id: u32,
+}
+
+impl Cursor {
+    /// Before every item, for the first page
+    pub const START: Cursor = Cursor {
+        key: i64::MAX,
+        id: u32::MAX,
+    };
+}
+
+impl From<Cursor> for String {
+    fn from(cursor: Cursor) -> Self {
+        format!("{}_{}", cursor.key, cursor.id)
+    }
+}
+
+impl TryFrom<String> for Cursor {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value
+            .split_once('_')
+            .and_then(|(key, id)| {
+                Some(Cursor {
+                    key: key.parse().ok()?,
+                    id: id.parse().ok()?,
+                })
+            })
+            .ok_or_else(|| format!("Invalid cursor {value}"))
+    }
+}
+
 // Other types
 #[derive(thiserror::Error, Debug)]
 pub struct StringError(pub String);
@@ -349,7 +721,7 @@ impl Display for StringError {
 }
 
 // Util
-fn deserialize_timestamp<'de, D>(deserializer: D) -> Result<OffsetDateTime, D::Error>
+pub(crate) fn deserialize_timestamp<'de, D>(deserializer: D) -> Result<OffsetDateTime, D::Error>
 where
     D: de::Deserializer<'de>,
 {
@@ -357,7 +729,21 @@ where
     serde_json::from_str(&s).map_err(de::Error::custom)
 }
 
-fn serialize_timestamp<S>(timestamp: &OffsetDateTime, serializer: S) -> Result<S::Ok, S::Error>
+fn deserialize_optional_timestamp<'de, D>(
+    deserializer: D,
+) -> Result<Option<OffsetDateTime>, D::Error>
+where
+    D: de::Deserializer<'de>,
+{
+    let s: Option<String> = de::Deserialize::deserialize(deserializer)?;
+    s.map(|s| serde_json::from_str(&s).map_err(de::Error::custom))
+        .transpose()
+}
+
+pub(crate) fn serialize_timestamp<S>(
+    timestamp: &OffsetDateTime,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
 where
     S: ser::Serializer,
 {
@@ -367,3 +753,16 @@ where
     // let s = serde_json::to_string(timestamp).map_err(ser::Error::custom)?;
     serializer.serialize_str(&s)
 }
+
+pub(crate) fn serialize_optional_timestamp<S>(
+    timestamp: &Option<OffsetDateTime>,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: ser::Serializer,
+{
+    match timestamp {
+        Some(timestamp) => serialize_timestamp(timestamp, serializer),
+        None => serializer.serialize_none(),
+    }
+}
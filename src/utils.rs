@@ -1,69 +1,52 @@
-use actix_web::{HttpRequest, HttpResponse, cookie::Cookie};
+use actix_web::cookie::{Cookie, SameSite};
 use itertools::Itertools;
-use libsql::Connection;
-use log::{error, info};
-use time::OffsetDateTime;
+use log::error;
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
 
-use crate::{
-    db::LOGINS_T,
-    types::{Failure, LOGGED_IN_COOKIE},
-};
+use crate::types::LOGGED_IN_COOKIE;
 
-pub fn return_password_error() -> HttpResponse {
-    let mut c = Cookie::build(LOGGED_IN_COOKIE, "").finish();
+/// The login cookie, which is never readable from scripts or sent cross site
+pub fn session_cookie(value: String) -> Cookie<'static> {
+    Cookie::build(LOGGED_IN_COOKIE, value)
+        .path("/")
+        .secure(true)
+        .http_only(true)
+        .same_site(SameSite::Strict)
+        .expires(None)
+        .finish()
+}
+
+pub fn removal_cookie() -> Cookie<'static> {
+    let mut c = session_cookie(String::new());
     c.make_removal();
-    HttpResponse::Unauthorized().cookie(c).json(Failure {
-        message: "Wrong password".into(),
-    })
+    c
 }
 
-pub async fn is_logged_in(req: &HttpRequest, db: Connection) -> bool {
-    match req.cookie(LOGGED_IN_COOKIE) {
-        None => {
-            info!("[LoggedInCheck] No Loggged in cookie set");
-            false
-        }
-        Some(res) => {
-            let result = db
-                .query(
-                    &format!("SELECT timestamp FROM {LOGINS_T} WHERE key = ?1 LIMIT 1"),
-                    [res.value()],
-                )
-                .await;
-
-            match result {
-                Err(err) => {
-                    info!("[LoggedInCheck] Searching key in db failed with error: {err}");
-                    false
-                }
-                Ok(mut res) => {
-                    let res = res.next().await;
-                    let Ok(rows) = res else {
-                        info!(
-                            "[LoggedInCheck] Searching key in db failed with error: {}",
-                            res.unwrap_err()
-                        );
-                        return false;
-                    };
-                    let Some(row) = rows else {
-                        info!("[LoggedInCheck] Key not in db");
-                        return false;
-                    };
-
-                    let timestamp = row.get_str(0).unwrap();
-                    match serde_json::from_str::<OffsetDateTime>(timestamp) {
-                        Err(err) => {
-                            error!(
-                                "[LoggedInCheck] Timestamp: {timestamp} couldn't be parsed with err: {err}"
-                            );
-                            false
-                        }
-                        Ok(timestamp) => (OffsetDateTime::now_utc() - timestamp).whole_hours() < 1,
-                    }
-                }
-            }
-        }
-    }
+/// 244 random bits, hex encoded. Used for session keys and API tokens
+pub fn random_key() -> String {
+    format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple())
+}
+
+/// Keys are random enough that a plain hash is all they need before storing
+pub fn hash_key(key: &str) -> String {
+    Sha256::digest(key.as_bytes())
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+/// Identifies a version of a roadmap card, so identical cards share a row
+pub fn hash_card(
+    roadmap_id: &str,
+    name: &str,
+    description: &str,
+    image_url: Option<&str>,
+    slug: &str,
+) -> String {
+    let content = serde_json::to_string(&(roadmap_id, name, description, image_url, slug))
+        .expect("Strings always serialize");
+    hash_key(&content)
 }
 
 pub fn clean_description(mut s: String) -> String {
@@ -124,3 +107,30 @@ pub fn clean_description(mut s: String) -> String {
 
     s
 }
+
+/// Feed bodies are usually html, this keeps only the text so it can be
+/// searched and shown in snippets
+pub fn strip_html(s: &str) -> String {
+    let mut text = String::with_capacity(s.len());
+    let mut in_tag = false;
+    for c in s.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => {
+                in_tag = false;
+                text.push(' ');
+            }
+            _ if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+
+    text.replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&")
+        .split_whitespace()
+        .join(" ")
+}
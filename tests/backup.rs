@@ -0,0 +1,89 @@
+mod common;
+
+use common::migrated;
+use greg::backup::{self, Backup};
+use libsql::{Connection, params};
+
+async fn count(conn: &Connection, table: &str) -> u32 {
+    conn.query(&format!("SELECT COUNT(*) FROM {table}"), params!())
+        .await
+        .unwrap()
+        .next()
+        .await
+        .unwrap()
+        .unwrap()
+        .get(0)
+        .unwrap()
+}
+
+async fn seeded() -> Connection {
+    let conn = migrated().await;
+    conn.execute_batch(
+        "INSERT INTO users (id, username, password_hash, timestamp) VALUES (1, 'admin', 'hash', '');
+        INSERT INTO sources (id, user_id, url, last_checked) VALUES (1, 1, 'https://a.test', '');
+        INSERT INTO activities (source_id, post_url, title, timestamp)
+            VALUES (1, 'https://a.test/1', 'Hello there', '[2025,1,0,0,0,0,0,0,0]');
+        INSERT INTO roadmap_activities (id, timestamp) VALUES (1, '[2025,1,0,0,0,0,0,0,0]');
+        INSERT INTO roadmap_tabs (id, roadmap_id, name, slug, timestamp) VALUES (1, 't', 'Tab', 'tab', '');
+        INSERT INTO roadmap_cards (id, roadmap_id, name, description, slug, content_hash, timestamp)
+            VALUES (1, 'c', 'Card', 'Described', 'card', 'c', '');
+        INSERT INTO roadmap_card_assignments (activity_id, tab_id, card_id, section_position, card_position, timestamp)
+            VALUES (1, 1, 1, 0, 0, '')",
+    )
+    .await
+    .unwrap();
+    conn
+}
+
+#[tokio::test]
+async fn restores_a_backup() {
+    let source = seeded().await;
+    let backup = backup::export(source).await.unwrap();
+    let backup: Backup = serde_json::from_str(&serde_json::to_string(&backup).unwrap()).unwrap();
+
+    let target = migrated().await;
+    target
+        .execute(
+            "INSERT INTO sources (id, user_id, url, last_checked) VALUES (5, 1, 'https://old.test', '')",
+            params!(),
+        )
+        .await
+        .unwrap();
+    backup::restore(target.clone(), &backup).await.unwrap();
+
+    for (table, expected) in [
+        ("users", 1),
+        ("sources", 1),
+        ("activities", 1),
+        ("roadmap_cards", 1),
+        ("roadmap_card_assignments", 1),
+    ] {
+        assert_eq!(count(&target, table).await, expected, "{table}");
+    }
+
+    let matches: u32 = target
+        .query(
+            "SELECT COUNT(*) FROM activities_fts WHERE activities_fts MATCH 'hello'",
+            params!(),
+        )
+        .await
+        .unwrap()
+        .next()
+        .await
+        .unwrap()
+        .unwrap()
+        .get(0)
+        .unwrap();
+    assert_eq!(matches, 1);
+}
+
+#[tokio::test]
+async fn refuses_other_schema_version() {
+    let mut backup = backup::export(seeded().await).await.unwrap();
+    backup.schema_version -= 1;
+
+    let target = migrated().await;
+    let err = backup::restore(target.clone(), &backup).await.unwrap_err();
+    assert!(err.to_string().contains("schema version"), "{err}");
+    assert_eq!(count(&target, "users").await, 0);
+}
@@ -0,0 +1,61 @@
+//! Fixtures shared by the integration tests. Each test crate only uses some.
+#![allow(dead_code)]
+
+use std::{
+    env, fs, process,
+    sync::{
+        Mutex,
+        atomic::{AtomicU32, Ordering},
+    },
+};
+
+use greg::{
+    auth::LoginLimiter,
+    config::Config,
+    db::{self, migrations::migrate_up},
+    repo::{Backend, libsql::LibsqlBackend},
+    types::{AppData, AppState},
+};
+use libsql::{Builder, Connection};
+
+/// A fresh in-memory database without any migrations
+pub async fn connect() -> Connection {
+    let database = Builder::new_local(":memory:").build().await.unwrap();
+    db::connect(&database).await.unwrap()
+}
+
+/// A fresh in-memory database with every migration applied
+pub async fn migrated() -> Connection {
+    let conn = connect().await;
+    migrate_up(conn.clone()).await.unwrap();
+    conn
+}
+
+static NEXT_DB: AtomicU32 = AtomicU32::new(0);
+
+/// App state with the default config, backed by migrated databases in the
+/// temp dir. Connections to an in-memory database don't share its tables.
+pub async fn app_data() -> AppData {
+    let backend = || async {
+        let path = env::temp_dir().join(format!(
+            "greg-test-{}-{}.db",
+            process::id(),
+            NEXT_DB.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _ = fs::remove_file(&path);
+        let backend = LibsqlBackend::new(Builder::new_local(path).build().await.unwrap());
+        backend.migrate().await.unwrap();
+        Box::new(backend)
+    };
+    let config = Config::default();
+
+    AppData::new(AppState {
+        app: backend().await,
+        demo: backend().await,
+        client: reqwest::Client::new(),
+        feed_client: reqwest::Client::new(),
+        login_limiter: LoginLimiter::new(&config.auth),
+        db_sync: Mutex::default(),
+        config,
+    })
+}
@@ -0,0 +1,52 @@
+use std::{thread, time::Duration};
+
+use greg::{auth::LoginLimiter, config::AuthConfig};
+
+fn limiter() -> LoginLimiter {
+    LoginLimiter::new(&AuthConfig {
+        max_failed_logins: 2,
+        login_delay_ms: 0,
+        max_global_failed_logins: 3,
+        ..AuthConfig::default()
+    })
+}
+
+#[test]
+fn locks_out_a_username() {
+    let limiter = limiter();
+    limiter.check("ip", "admin").ok().unwrap();
+    limiter.check("ip", "admin").ok().unwrap();
+
+    let blocked = limiter.check("ip", "admin").err().unwrap();
+    assert_eq!(blocked.reason, "locked_out");
+}
+
+#[test]
+fn logging_in_elsewhere_keeps_the_lockout() {
+    let limiter = limiter();
+    limiter.check("ip", "admin").ok().unwrap();
+    limiter.check("ip", "admin").ok().unwrap();
+
+    // Logging in to an account the client owns between guesses
+    let own = limiter.check("ip", "mine").ok().unwrap();
+    limiter.record_success(own);
+
+    assert!(limiter.check("ip", "admin").is_err());
+}
+
+#[test]
+fn success_only_takes_back_its_own_attempt() {
+    let limiter = limiter();
+    let success = limiter.check("a", "one").ok().unwrap();
+    thread::sleep(Duration::from_millis(500));
+    limiter.check("b", "two").ok().unwrap();
+    limiter.check("c", "three").ok().unwrap();
+    limiter.record_success(success);
+
+    // The two failures are still counted, so one more reaches the limit
+    limiter.check("d", "four").ok().unwrap();
+    let blocked = limiter.check("e", "five").err().unwrap();
+    assert_eq!(blocked.reason, "global_limit");
+    // and the oldest left is the one after the success
+    assert!(blocked.retry_after > Duration::from_millis(59_750));
+}
@@ -0,0 +1,202 @@
+mod common;
+
+use common::{connect, migrated};
+use greg::db::{
+    MIGRATIONS_T, VERSION_T,
+    migrations::{
+        MIGRATIONS, applied_migrations, latest_version, legacy_version, migrate_down, migrate_up,
+    },
+};
+use libsql::{Connection, params};
+
+async fn versions(conn: &Connection) -> Vec<u32> {
+    applied_migrations(conn.clone())
+        .await
+        .unwrap()
+        .iter()
+        .map(|m| m.version)
+        .collect()
+}
+
+async fn table_exists(conn: &Connection, name: &str) -> bool {
+    conn.query(
+        "SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?1",
+        [name],
+    )
+    .await
+    .unwrap()
+    .next()
+    .await
+    .unwrap()
+    .is_some()
+}
+
+#[tokio::test]
+async fn applies_every_migration() {
+    let conn = connect().await;
+    migrate_up(conn.clone()).await.unwrap();
+
+    let expected = MIGRATIONS.iter().map(|m| m.version).collect::<Vec<_>>();
+    assert_eq!(versions(&conn).await, expected);
+    assert_eq!(*expected.last().unwrap(), latest_version());
+
+    // Running again is a no-op
+    migrate_up(conn.clone()).await.unwrap();
+    assert_eq!(versions(&conn).await, expected);
+}
+
+#[tokio::test]
+async fn rolls_back_and_reapplies() {
+    let conn = connect().await;
+    migrate_up(conn.clone()).await.unwrap();
+
+    migrate_down(conn.clone(), Some(3)).await.unwrap();
+    assert_eq!(versions(&conn).await, [1, 2, 3]);
+    assert!(!table_exists(&conn, "users").await);
+
+    migrate_down(conn.clone(), Some(0)).await.unwrap();
+    assert!(versions(&conn).await.is_empty());
+    assert!(!table_exists(&conn, "sources").await);
+
+    migrate_up(conn.clone()).await.unwrap();
+    assert_eq!(versions(&conn).await.len(), MIGRATIONS.len());
+
+    // Without a version only the latest is rolled back
+    let latest = latest_version();
+    assert_eq!(migrate_down(conn.clone(), None).await.unwrap(), latest - 1);
+    assert_eq!(versions(&conn).await.len(), MIGRATIONS.len() - 1);
+}
+
+#[tokio::test]
+async fn adopts_version_table() {
+    let conn = connect().await;
+    migrate_up(conn.clone()).await.unwrap();
+    migrate_down(conn.clone(), Some(3)).await.unwrap();
+
+    conn.execute_batch(&format!(
+        "DROP TABLE {MIGRATIONS_T};
+        CREATE TABLE {VERSION_T} (id INTEGER NOT NULL PRIMARY KEY, version_number INTEGER NOT NULL);
+        INSERT INTO {VERSION_T} (version_number) VALUES (3)"
+    ))
+    .await
+    .unwrap();
+
+    // Reading the applied migrations leaves the old table alone
+    assert!(versions(&conn).await.is_empty());
+    assert_eq!(legacy_version(&conn).await.unwrap(), Some(3));
+    assert!(!table_exists(&conn, MIGRATIONS_T).await);
+
+    migrate_up(conn.clone()).await.unwrap();
+    assert_eq!(versions(&conn).await.len(), MIGRATIONS.len());
+    assert!(!table_exists(&conn, VERSION_T).await);
+}
+
+#[tokio::test]
+async fn refuses_unknown_version() {
+    let conn = connect().await;
+    migrate_up(conn.clone()).await.unwrap();
+
+    conn.execute(
+        &format!(
+            "INSERT INTO {MIGRATIONS_T} (version, name, checksum, applied_at)
+            SELECT 999, 'future', '', applied_at FROM {MIGRATIONS_T} WHERE version = 1"
+        ),
+        params!(),
+    )
+    .await
+    .unwrap();
+
+    let err = migrate_up(conn).await.unwrap_err();
+    assert!(err.to_string().contains("999"), "{err}");
+}
+
+#[tokio::test]
+async fn refuses_changed_migration() {
+    let conn = connect().await;
+    migrate_up(conn.clone()).await.unwrap();
+
+    conn.execute(
+        &format!("UPDATE {MIGRATIONS_T} SET checksum = 'changed' WHERE version = 2"),
+        params!(),
+    )
+    .await
+    .unwrap();
+
+    let err = migrate_up(conn).await.unwrap_err();
+    assert!(err.to_string().contains("changed"), "{err}");
+}
+
+#[tokio::test]
+async fn deleting_source_deletes_its_rows() {
+    let conn = migrated().await;
+
+    conn.execute_batch(
+        "INSERT INTO sources (id, user_id, url, last_checked) VALUES (1, 1, 'https://a.test', '');
+        INSERT INTO activities (source_id, post_url, timestamp) VALUES (1, 'https://a.test/1', '[2025,1,0,0,0,0,0,0,0]');
+        INSERT INTO source_events (source_id, type, message, timestamp) VALUES (1, 'x', '', '');
+        DELETE FROM sources WHERE id = 1",
+    )
+    .await
+    .unwrap();
+
+    for table in ["activities", "source_events", "activities_fts"] {
+        let count: u32 = conn
+            .query(&format!("SELECT COUNT(*) FROM {table}"), params!())
+            .await
+            .unwrap()
+            .next()
+            .await
+            .unwrap()
+            .unwrap()
+            .get(0)
+            .unwrap();
+        assert_eq!(count, 0, "{table} has rows left");
+    }
+}
+
+#[tokio::test]
+async fn merges_duplicate_cards() {
+    let conn = connect().await;
+    migrate_up(conn.clone()).await.unwrap();
+    migrate_down(conn.clone(), Some(9)).await.unwrap();
+
+    conn.execute_batch(
+        "INSERT INTO roadmap_activities (id, timestamp) VALUES (1, '[2025,1,0,0,0,0,0,0,0]');
+        INSERT INTO roadmap_tabs (id, roadmap_id, name, slug, timestamp) VALUES (1, 't', 'Tab', 'tab', '');
+        INSERT INTO roadmap_cards (id, roadmap_id, name, description, slug, timestamp)
+            VALUES (1, 'c', 'Card', 'Same', 'card', ''), (2, 'c', 'Card', 'Same', 'card', ''),
+                (3, 'c', 'Card', 'Changed', 'card', '');
+        INSERT INTO roadmap_card_assignments (activity_id, tab_id, card_id, section_position, card_position, timestamp)
+            VALUES (1, 1, 1, 0, 0, ''), (1, 1, 2, 0, 1, '');
+        INSERT INTO roadmap_changes (type, activity_id, previous_card_id, current_card_id, timestamp)
+            VALUES ('card_modified', 1, 2, 3, '')",
+    )
+    .await
+    .unwrap();
+
+    migrate_up(conn.clone()).await.unwrap();
+
+    let ids = |sql: &'static str| {
+        let conn = conn.clone();
+        async move {
+            let mut rows = conn.query(sql, params!()).await.unwrap();
+            let mut ids = Vec::new();
+            while let Some(row) = rows.next().await.unwrap() {
+                ids.push(row.get::<u32>(0).unwrap());
+            }
+            ids
+        }
+    };
+    assert_eq!(
+        ids("SELECT id FROM roadmap_cards ORDER BY id").await,
+        [1, 3]
+    );
+    assert_eq!(
+        ids("SELECT card_id FROM roadmap_card_assignments").await,
+        [1, 1]
+    );
+    assert_eq!(
+        ids("SELECT previous_card_id FROM roadmap_changes").await,
+        [1]
+    );
+}
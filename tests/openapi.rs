@@ -0,0 +1,129 @@
+use std::{env, fs};
+
+use actix_web::{
+    App,
+    test::{TestRequest, call_and_read_body_json, init_service},
+    web::scope,
+};
+use greg::openapi::{self, ApiDoc};
+use utoipa::OpenApi;
+
+/// Checked in so the frontend can generate a client without running the server
+const SPEC_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/frontend/openapi.json");
+
+const ROUTES: &[(&str, &str)] = &[
+    ("get", "/health"),
+    ("get", "/health/database"),
+    ("post", "/sessions"),
+    ("get", "/sessions"),
+    ("delete", "/sessions"),
+    ("get", "/sessions/current"),
+    ("delete", "/sessions/current"),
+    ("delete", "/sessions/{id}"),
+    ("get", "/tokens"),
+    ("post", "/tokens"),
+    ("delete", "/tokens/{id}"),
+    ("get", "/sources"),
+    ("post", "/sources"),
+    ("post", "/sources/preview"),
+    ("post", "/sources/check"),
+    ("get", "/sources/{id}"),
+    ("patch", "/sources/{id}"),
+    ("delete", "/sources/{id}"),
+    ("post", "/sources/{id}/check"),
+    ("post", "/sources/{id}/accept-redirect"),
+    ("get", "/sources/{id}/events"),
+    ("get", "/sources/{id}/activities"),
+    ("get", "/activities"),
+    ("delete", "/activities"),
+    ("get", "/roadmap/activities"),
+    ("get", "/roadmap/activities/{id}/changes"),
+    ("get", "/roadmap/cards/{roadmap_id}/history"),
+    ("get", "/roadmap/compare"),
+    ("get", "/roadmap/tabs"),
+    ("post", "/roadmap/check"),
+    ("get", "/roadmap/watched-tabs"),
+    ("post", "/roadmap/watched-tabs"),
+    ("delete", "/roadmap/watched-tabs/{id}"),
+    ("get", "/search"),
+    ("get", "/backup"),
+];
+
+fn spec() -> serde_json::Value {
+    serde_json::to_value(ApiDoc::openapi()).unwrap()
+}
+
+#[test]
+fn documents_every_route() {
+    let spec = spec();
+    let paths = spec["paths"].as_object().unwrap();
+
+    for (method, path) in ROUTES {
+        assert!(
+            paths.get(*path).and_then(|p| p.get(*method)).is_some(),
+            "{method} {path} is not documented"
+        );
+    }
+
+    let documented = paths
+        .values()
+        .map(|p| p.as_object().unwrap().len())
+        .sum::<usize>();
+    assert_eq!(
+        documented,
+        ROUTES.len(),
+        "a route is documented but not listed here"
+    );
+}
+
+#[test]
+fn schemas_match_serialized_names() {
+    let spec = spec();
+    let schemas = &spec["components"]["schemas"];
+
+    for schema in ["Session", "ApiToken"] {
+        let properties = schemas[schema]["properties"].as_object().unwrap();
+        assert!(
+            properties.contains_key("created"),
+            "{schema} has no created"
+        );
+        assert!(
+            !properties.contains_key("timestamp"),
+            "{schema} has timestamp"
+        );
+    }
+    assert!(schemas["Session"]["properties"].get("key").is_none());
+    assert!(
+        schemas["ApiToken"]["properties"]
+            .get("token_hash")
+            .is_none()
+    );
+    assert!(schemas["ErrorBody"]["properties"].get("code").is_some());
+}
+
+#[test]
+fn checked_in_spec_is_up_to_date() {
+    let generated = ApiDoc::openapi().to_pretty_json().unwrap() + "\n";
+
+    if env::var_os("UPDATE_OPENAPI").is_some() {
+        fs::write(SPEC_PATH, &generated).unwrap();
+        return;
+    }
+
+    let checked_in = fs::read_to_string(SPEC_PATH).unwrap_or_default();
+    assert!(
+        checked_in == generated,
+        "frontend/openapi.json is out of date, run `UPDATE_OPENAPI=1 cargo test --test openapi`"
+    );
+}
+
+#[actix_web::test]
+async fn serves_spec() {
+    let app =
+        init_service(App::new().service(scope("/api/v1").configure(openapi::configure))).await;
+
+    let req = TestRequest::get().uri("/api/v1/openapi.json").to_request();
+    let served: serde_json::Value = call_and_read_body_json(&app, req).await;
+
+    assert_eq!(served, spec());
+}
@@ -0,0 +1,236 @@
+//! Runs the PostgreSQL repository against the server in `TEST_POSTGRES_URL`.
+//! The database's public schema is dropped first, so point it at a throwaway
+//! one. Ignored by default, run it with
+//! `TEST_POSTGRES_URL=... cargo test --features postgres --test postgres -- --ignored`
+#![cfg(feature = "postgres")]
+
+use std::{env, sync::Arc};
+
+use greg::{
+    error::is_unique_violation,
+    repo::{Backend, Repo, SearchFilters, postgres::PostgresBackend, search_all},
+    types::{RCard, RTab, SearchKind, TokenScope},
+};
+use time::OffsetDateTime;
+use tokio_postgres::NoTls;
+
+async fn connect() -> Arc<dyn Repo> {
+    let url = env::var("TEST_POSTGRES_URL").expect("TEST_POSTGRES_URL should be set");
+
+    let (client, connection) = tokio_postgres::connect(&url, NoTls).await.unwrap();
+    tokio::spawn(connection);
+    client
+        .batch_execute("DROP SCHEMA public CASCADE; CREATE SCHEMA public;")
+        .await
+        .unwrap();
+
+    let backend = PostgresBackend::new(&url).unwrap();
+    backend.migrate().await.unwrap();
+    // Migrating again is a no-op
+    backend.migrate().await.unwrap();
+
+    backend.connect().await.unwrap()
+}
+
+fn card(id: &str, name: &str, description: &str) -> RCard {
+    RCard {
+        id: id.into(),
+        name: name.into(),
+        description: description.into(),
+        image_url: None,
+        slug: id.into(),
+        db_id: None,
+        section_position: None,
+        card_position: None,
+        assign_db_id: None,
+        tab_id: None,
+    }
+}
+
+fn no_filters() -> SearchFilters {
+    SearchFilters {
+        source_id: None,
+        tab_id: None,
+        from: None,
+        to: None,
+    }
+}
+
+// One test, since every part of it shares the database
+#[tokio::test]
+#[ignore = "needs a PostgreSQL server in TEST_POSTGRES_URL"]
+async fn postgres_repo() {
+    let db = connect().await;
+
+    // Users, sessions and tokens
+    db.add_user("admin", "pw", true).await.unwrap();
+    let user = db.get_user_by_username("admin").await.unwrap().unwrap();
+    assert!(user.is_admin);
+
+    let session_id = db.add_session(user.id, "key", Some("test")).await.unwrap();
+    let owner = db.get_session_owner("key").await.unwrap().unwrap();
+    assert_eq!((owner.session_id, owner.user_id), (session_id, user.id));
+    assert_eq!(db.prune_sessions(60).await.unwrap(), 0);
+    assert_eq!(db.prune_sessions(0).await.unwrap(), 1);
+    assert!(db.get_session_owner("key").await.unwrap().is_none());
+
+    let (_, token) = db.add_token(user.id, "ci", TokenScope::Read).await.unwrap();
+    assert_eq!(
+        db.get_token_owner(&token).await.unwrap(),
+        Some((user.id, true, TokenScope::Read))
+    );
+    assert!(db.get_tokens(user.id).await.unwrap()[0].last_used.is_some());
+
+    // Sources and redirects
+    let source_id = db.add_source(user.id, "https://a.test").await.unwrap();
+    let err = db.add_source(user.id, "https://a.test").await.unwrap_err();
+    assert!(is_unique_violation(err.as_ref()));
+
+    assert_eq!(
+        db.suggest_redirect(source_id, "https://b.test")
+            .await
+            .unwrap(),
+        1
+    );
+    assert_eq!(
+        db.suggest_redirect(source_id, "https://b.test")
+            .await
+            .unwrap(),
+        0
+    );
+    assert!(db.accept_redirect(user.id, source_id).await.unwrap());
+    assert!(!db.accept_redirect(user.id, source_id).await.unwrap());
+    let other_id = db.add_source(user.id, "https://c.test").await.unwrap();
+    db.suggest_redirect(other_id, "https://b.test")
+        .await
+        .unwrap();
+    let err = db.accept_redirect(user.id, other_id).await.unwrap_err();
+    assert!(is_unique_violation(err.as_ref()));
+    let source = db.get_source(user.id, source_id).await.unwrap().unwrap();
+    assert_eq!(source.url, "https://b.test");
+    let events = db.get_source_events(user.id, source_id).await.unwrap();
+    assert_eq!(
+        events[0].message,
+        "Accepted redirect from https://a.test to https://b.test"
+    );
+
+    // Activity pages and search
+    for (i, title) in ["Rust 2024", "Gardening", "Rustaceans meet"]
+        .iter()
+        .enumerate()
+    {
+        db.add_activity(source_id, &format!("https://b.test/{i}"), title, "")
+            .await
+            .unwrap();
+    }
+    let first = db.get_activity(user.id, 2, None).await.unwrap();
+    assert_eq!((first.items.len(), first.total), (2, 3));
+    assert_eq!(first.items[0].post_url, "https://b.test/2");
+    let second = db
+        .get_activity(user.id, 2, first.next_cursor)
+        .await
+        .unwrap();
+    assert_eq!(second.items.len(), 1);
+    assert_eq!(second.items[0].post_url, "https://b.test/0");
+    assert!(second.next_cursor.is_none());
+
+    let hits = search_all(db.as_ref(), user.id, "rust", &no_filters(), 10)
+        .await
+        .unwrap();
+    assert_eq!(hits.len(), 2);
+    assert!(hits.iter().all(|hit| hit.kind == SearchKind::Activity));
+    assert!(!hits[0].highlights.is_empty());
+
+    // Roadmap checks
+    let tab = RTab {
+        id: "t".into(),
+        name: "Tab".into(),
+        slug: "tab".into(),
+        db_id: None,
+    };
+    let check = db.begin_check().await.unwrap();
+    let activity_id = check.new_roadmap().await.unwrap();
+    let tab_id = check.save_tab(&tab).await.unwrap();
+    check
+        .save_tab_assignment(activity_id, tab_id)
+        .await
+        .unwrap();
+    let card_id = check
+        .save_card(&card("c", "Rusty card", "Described"))
+        .await
+        .unwrap();
+    assert_eq!(
+        check
+            .save_card(&card("c", "Rusty card", "Described"))
+            .await
+            .unwrap(),
+        card_id
+    );
+    check
+        .save_card_assignment(card_id, &[activity_id, tab_id, 0, 0])
+        .await
+        .unwrap();
+    check
+        .save_change("card_added", activity_id, &[None, Some(card_id), None])
+        .await
+        .unwrap();
+    check.commit().await.unwrap();
+
+    // A check that's dropped keeps nothing
+    let check = db.begin_check().await.unwrap();
+    check.new_roadmap().await.unwrap();
+    drop(check);
+
+    db.add_watched_tab(user.id, "t").await.unwrap();
+    let err = db.add_watched_tab(user.id, "t").await.unwrap_err();
+    assert!(is_unique_violation(err.as_ref()));
+    assert_eq!(db.get_all_watched_tab_ids().await.unwrap(), ["t"]);
+
+    let roadmap = db.get_most_recent_roadmap().await.unwrap().unwrap();
+    assert_eq!(roadmap.cards["t"][0].name, "Rusty card");
+    let activities = db.get_roadmap_activities(user.id, 10, None).await.unwrap();
+    assert_eq!(activities.total, 1);
+    assert_eq!(activities.items[0].change_count, Some(1));
+
+    let history = db.get_card_history(user.id, "c").await.unwrap().unwrap();
+    assert_eq!(history.revisions[0].placements[0].tab_name, "Tab");
+    assert_eq!(
+        history.changes[0].change.card_tab_name.as_deref(),
+        Some("Tab")
+    );
+
+    let hits = search_all(db.as_ref(), user.id, "rus", &no_filters(), 10)
+        .await
+        .unwrap();
+    assert_eq!(hits.len(), 3);
+
+    // Someone who doesn't watch the tab sees none of it
+    db.add_user("other", "pw", false).await.unwrap();
+    let other = db.get_user_by_username("other").await.unwrap().unwrap();
+    assert!(
+        db.get_roadmap_changes(other.id, activity_id)
+            .await
+            .unwrap()
+            .is_empty()
+    );
+    assert!(db.get_card_history(other.id, "c").await.unwrap().is_none());
+    let hits = search_all(db.as_ref(), other.id, "rus", &no_filters(), 10)
+        .await
+        .unwrap();
+    assert!(hits.is_empty());
+
+    // Retention keeps the newest activity and the latest check
+    assert_eq!(db.delete_activities_over_limit(1).await.unwrap(), 2);
+    assert_eq!(
+        db.delete_roadmap_activities_before(OffsetDateTime::now_utc(), false)
+            .await
+            .unwrap(),
+        0
+    );
+    assert_eq!(db.delete_unused_cards().await.unwrap(), 0);
+    assert_eq!(db.delete_unused_tabs().await.unwrap(), 0);
+
+    // Deleting the source takes its activities with it
+    assert_eq!(db.delete_source(user.id, source_id).await.unwrap(), 1);
+    assert_eq!(db.get_activity(user.id, 10, None).await.unwrap().total, 0);
+}
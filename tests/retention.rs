@@ -0,0 +1,111 @@
+mod common;
+
+use common::migrated;
+use greg::queries::retention;
+use libsql::{Connection, params};
+use time::{Duration, OffsetDateTime};
+
+fn days_ago(days: i64) -> String {
+    serde_json::to_string(&(OffsetDateTime::now_utc() - Duration::days(days))).unwrap()
+}
+
+async fn ids(conn: &Connection, sql: &str) -> Vec<u32> {
+    let mut rows = conn.query(sql, params!()).await.unwrap();
+    let mut ids = Vec::new();
+    while let Some(row) = rows.next().await.unwrap() {
+        ids.push(row.get::<u32>(0).unwrap());
+    }
+    ids
+}
+
+#[tokio::test]
+async fn prunes_activities() {
+    let conn = migrated().await;
+    conn.execute_batch(
+        "INSERT INTO sources (id, user_id, url, last_checked) VALUES (1, 1, 'https://a.test', ''), (2, 1, 'https://b.test', '')",
+    )
+    .await
+    .unwrap();
+    for (id, source_id, days) in [(1, 1, 30), (2, 1, 3), (3, 1, 2), (4, 1, 1), (5, 2, 1)] {
+        conn.execute(
+            "INSERT INTO activities (id, source_id, post_url, timestamp) VALUES (?1, ?2, '', ?3)",
+            params!(id, source_id, days_ago(days)),
+        )
+        .await
+        .unwrap();
+    }
+
+    let cutoff = OffsetDateTime::now_utc() - Duration::days(7);
+    assert_eq!(
+        retention::delete_activities_before(&conn, cutoff)
+            .await
+            .unwrap(),
+        1
+    );
+    assert_eq!(
+        retention::delete_activities_over_limit(&conn, 2)
+            .await
+            .unwrap(),
+        1
+    );
+    assert_eq!(
+        ids(&conn, "SELECT id FROM activities ORDER BY id").await,
+        [3, 4, 5]
+    );
+}
+
+#[tokio::test]
+async fn prunes_unchanged_roadmap_checks() {
+    let conn = migrated().await;
+    conn.execute_batch(&format!(
+        "INSERT INTO roadmap_activities (id, timestamp) VALUES (1, '{0}'), (2, '{0}'), (3, '{0}');
+        INSERT INTO roadmap_tabs (id, roadmap_id, name, slug, timestamp) VALUES (1, 't', 'Tab', 'tab', '');
+        INSERT INTO roadmap_cards (id, roadmap_id, name, description, slug, content_hash, timestamp)
+            VALUES (1, 'a', 'A', '', 'a', 'a', ''), (2, 'b', 'B', '', 'b', 'b', '');
+        INSERT INTO roadmap_card_assignments (activity_id, tab_id, card_id, section_position, card_position, timestamp)
+            VALUES (1, 1, 1, 0, 0, ''), (3, 1, 2, 0, 0, '');
+        INSERT INTO roadmap_changes (type, activity_id, current_card_id, tab_id, timestamp)
+            VALUES ('card_added', 2, 2, 1, '')",
+        days_ago(30)
+    ))
+    .await
+    .unwrap();
+
+    let cutoff = OffsetDateTime::now_utc() - Duration::days(7);
+    // 2 has a change and 3 is the latest check
+    assert_eq!(
+        retention::delete_roadmap_activities_before(&conn, cutoff, true)
+            .await
+            .unwrap(),
+        1
+    );
+    assert_eq!(retention::delete_unused_cards(&conn).await.unwrap(), 1);
+    assert_eq!(retention::delete_unused_tabs(&conn).await.unwrap(), 0);
+    assert_eq!(
+        ids(&conn, "SELECT id FROM roadmap_activities ORDER BY id").await,
+        [2, 3]
+    );
+    assert_eq!(ids(&conn, "SELECT id FROM roadmap_cards").await, [2]);
+}
+
+#[tokio::test]
+async fn prunes_login_attempts() {
+    let conn = migrated().await;
+    for (id, days) in [(1, 30), (2, 1)] {
+        conn.execute(
+            "INSERT INTO login_attempts (id, ip, username, reason, timestamp) VALUES (?1, '', '', '', ?2)",
+            params!(id, days_ago(days)),
+        )
+        .await
+        .unwrap();
+    }
+
+    let cutoff = OffsetDateTime::now_utc() - Duration::days(7);
+    assert_eq!(
+        retention::delete_login_attempts_before(&conn, cutoff)
+            .await
+            .unwrap(),
+        1
+    );
+    assert_eq!(ids(&conn, "SELECT id FROM login_attempts").await, [2]);
+}
@@ -0,0 +1,129 @@
+mod common;
+
+use common::migrated;
+use greg::{
+    repo::{RoadmapRepo, SearchFilters, UserRepo, libsql::LibsqlRepo},
+    types::{RCard, RTab},
+};
+
+fn tab(id: &str, name: &str) -> RTab {
+    RTab {
+        id: id.into(),
+        name: name.into(),
+        slug: id.into(),
+        db_id: None,
+    }
+}
+
+fn card(id: &str) -> RCard {
+    RCard {
+        id: id.into(),
+        name: "Card".into(),
+        description: "Described".into(),
+        image_url: None,
+        slug: id.into(),
+        db_id: None,
+        section_position: None,
+        card_position: None,
+        assign_db_id: None,
+        tab_id: None,
+    }
+}
+
+/// Saves a check with the card on the tab and a change pointing at the card
+async fn check(db: &LibsqlRepo, tab: &RTab, change_type: &str) -> u32 {
+    let check = db.begin_check().await.unwrap();
+    let activity_id = check.new_roadmap().await.unwrap();
+    let tab_id = check.save_tab(tab).await.unwrap();
+    check
+        .save_tab_assignment(activity_id, tab_id)
+        .await
+        .unwrap();
+    let card_id = check.save_card(&card("c")).await.unwrap();
+    check
+        .save_card_assignment(card_id, &[activity_id, tab_id, 0, 0])
+        .await
+        .unwrap();
+    check
+        .save_change(change_type, activity_id, &[None, Some(card_id), None])
+        .await
+        .unwrap();
+    check.commit().await.unwrap();
+    activity_id
+}
+
+/// A user watching the tabs
+async fn user(db: &LibsqlRepo, username: &str, tab_ids: &[&str]) -> u32 {
+    db.add_user(username, "pw", false).await.unwrap();
+    let user_id = db.get_user_by_username(username).await.unwrap().unwrap().id;
+    for tab_id in tab_ids {
+        db.add_watched_tab(user_id, tab_id).await.unwrap();
+    }
+    user_id
+}
+
+#[tokio::test]
+async fn changes_name_the_tab_the_card_was_on_then() {
+    let db = LibsqlRepo::new(migrated().await);
+    let user_id = user(&db, "a", &["a", "b"]).await;
+
+    // The card is unchanged so both checks share its row
+    let first = check(&db, &tab("a", "Tab A"), "card_added").await;
+    let second = check(&db, &tab("b", "Tab B"), "card_modified").await;
+
+    for (activity_id, tab_name) in [(first, "Tab A"), (second, "Tab B")] {
+        let changes = db.get_roadmap_changes(user_id, activity_id).await.unwrap();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].card_tab_name.as_deref(), Some(tab_name));
+    }
+}
+
+#[tokio::test]
+async fn only_shows_the_users_tabs() {
+    let db = LibsqlRepo::new(migrated().await);
+    let watcher = user(&db, "watcher", &["a"]).await;
+    let other = user(&db, "other", &["b"]).await;
+    let activity_id = check(&db, &tab("a", "Tab A"), "card_added").await;
+
+    let activities = db.get_roadmap_activities(watcher, 10, None).await.unwrap();
+    assert_eq!(activities.items[0].change_count, Some(1));
+    let activities = db.get_roadmap_activities(other, 10, None).await.unwrap();
+    assert_eq!(activities.items[0].change_count, None);
+
+    assert_eq!(
+        db.get_roadmap_changes(watcher, activity_id)
+            .await
+            .unwrap()
+            .len(),
+        1
+    );
+    assert!(
+        db.get_roadmap_changes(other, activity_id)
+            .await
+            .unwrap()
+            .is_empty()
+    );
+
+    assert!(db.get_card_history(watcher, "c").await.unwrap().is_some());
+    assert!(db.get_card_history(other, "c").await.unwrap().is_none());
+
+    let filters = SearchFilters {
+        source_id: None,
+        tab_id: None,
+        from: None,
+        to: None,
+    };
+    assert_eq!(
+        db.search_cards(watcher, "card", &filters, 10)
+            .await
+            .unwrap()
+            .len(),
+        1
+    );
+    assert!(
+        db.search_cards(other, "card", &filters, 10)
+            .await
+            .unwrap()
+            .is_empty()
+    );
+}
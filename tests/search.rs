@@ -0,0 +1,39 @@
+mod common;
+
+use actix_web::{
+    App,
+    http::StatusCode,
+    test::{TestRequest, call_service, init_service},
+    web::scope,
+};
+use common::app_data;
+use greg::routes::v1;
+
+async fn status(uri: &str) -> StatusCode {
+    let app = init_service(
+        App::new()
+            .app_data(app_data().await)
+            .service(scope("/api/v1").configure(v1::configure)),
+    )
+    .await;
+
+    call_service(&app, TestRequest::get().uri(uri).to_request())
+        .await
+        .status()
+}
+
+#[actix_web::test]
+async fn searches_up_to_a_day() {
+    assert_eq!(
+        status("/api/v1/search?demo=true&q=rust&to=2025-01-31").await,
+        StatusCode::OK
+    );
+}
+
+#[actix_web::test]
+async fn refuses_the_last_representable_day() {
+    assert_eq!(
+        status("/api/v1/search?demo=true&q=rust&to=9999-12-31").await,
+        StatusCode::BAD_REQUEST
+    );
+}
@@ -0,0 +1,32 @@
+mod common;
+
+use common::migrated;
+use greg::{
+    error::is_unique_violation,
+    repo::{SourcesRepo, UserRepo, libsql::LibsqlRepo},
+};
+
+#[tokio::test]
+async fn refuses_redirect_to_an_existing_source() {
+    let db = LibsqlRepo::new(migrated().await);
+    db.add_user("admin", "pw", false).await.unwrap();
+    let user_id = db.get_user_by_username("admin").await.unwrap().unwrap().id;
+    let old = db.add_source(user_id, "https://a.test/old").await.unwrap();
+    db.add_source(user_id, "https://a.test/feed").await.unwrap();
+
+    db.suggest_redirect(old, "https://a.test/feed")
+        .await
+        .unwrap();
+    let err = db.accept_redirect(user_id, old).await.unwrap_err();
+    assert!(is_unique_violation(err.as_ref()));
+
+    let source = db.get_source(user_id, old).await.unwrap().unwrap();
+    assert_eq!(source.url, "https://a.test/old");
+    assert!(db.get_source_events(user_id, old).await.unwrap().is_empty());
+
+    let err = db
+        .move_source(old, "https://a.test/feed")
+        .await
+        .unwrap_err();
+    assert!(is_unique_violation(err.as_ref()));
+}